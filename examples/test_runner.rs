@@ -20,8 +20,8 @@ fn run_encode_test_inner(endian: Endian, input: InMemoryRasterData, width: u16,
         }],
     };
 
-    let encoded = setup.clone().to_wkb_string();
-    let decoded = Raster::from_wkb_string(&encoded.as_bytes()).unwrap();
+    let encoded = setup.clone().try_to_wkb_string().unwrap();
+    let decoded = Raster::from_wkb_string(encoded.as_bytes()).unwrap();
     if decoded != setup {
         use std::process::exit;
         println!("expected: {:#?}\n\ngot:{:#?}", setup, decoded);
@@ -30,5 +30,5 @@ fn main() {
         }],
     };
 
-    println!("{}", raster.to_wkb_string());
+    println!("{}", raster.try_to_wkb_string().unwrap());
 }
\ No newline at end of file
@@ -0,0 +1,221 @@
+//! Esri ASCII grid (AAIGrid, `.asc`) decoding and encoding
+
+use std::fmt;
+use crate::{Raster, RasterBand, RasterDataSource, InMemoryRasterData, PixType, Endian};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AaiGridError {
+    /// The header doesn't contain the given key
+    MissingHeader(&'static str),
+    /// The value of the given header key is not a valid number
+    InvalidHeaderValue(String),
+    /// A cell value is not a number, contains the offending token
+    InvalidValue(String),
+    /// The number of cell values doesn't match `ncols` * `nrows`
+    ValueCount { expected: usize, found: usize },
+    /// The grid is larger than 65535 cells in one direction
+    TooLarge { width: usize, height: usize },
+    /// The band index does not exist in the raster
+    BandIndexOutOfRange(usize),
+    /// Band data is stored out-of-db, it can't be encoded
+    BandIsOffline,
+    /// The raster has a skew, ASCII grids can't be rotated
+    SkewedPixels,
+    /// Pixel width and height differ, ASCII grids have a single `cellsize`
+    NonSquarePixels,
+    /// The raster isn't north-up (`scale_x` has to be positive, `scale_y` negative)
+    NotNorthUp,
+}
+
+impl fmt::Display for AaiGridError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AaiGridError::MissingHeader(key) => write!(f, "ASCII grid header has no {}", key),
+            AaiGridError::InvalidHeaderValue(key) => write!(f, "ASCII grid header {} has an invalid value", key),
+            AaiGridError::InvalidValue(token) => write!(f, "ASCII grid cell value {:?} is not a number", token),
+            AaiGridError::ValueCount { expected, found } => write!(f, "ASCII grid has {} cell values, expected {}", found, expected),
+            AaiGridError::TooLarge { width, height } => write!(f, "ASCII grid of {}x{} cells is too large for a raster (at most 65535x65535)", width, height),
+            AaiGridError::BandIndexOutOfRange(band) => write!(f, "band {} does not exist", band),
+            AaiGridError::BandIsOffline => write!(f, "band is stored out-of-db and can't be encoded as ASCII grid"),
+            AaiGridError::SkewedPixels => write!(f, "ASCII grids can't have skewed pixels"),
+            AaiGridError::NonSquarePixels => write!(f, "ASCII grids require square pixels"),
+            AaiGridError::NotNorthUp => write!(f, "ASCII grids require a north-up raster"),
+        }
+    }
+}
+
+impl std::error::Error for AaiGridError { }
+
+impl Raster {
+
+    /// Parses an Esri ASCII grid into a single-band raster with the given SRID.
+    ///
+    /// The header consists of `ncols`, `nrows`, `xllcorner` / `xllcenter`, `yllcorner` /
+    /// `yllcenter`, `cellsize` (or `dx` and `dy`) and the optional `NODATA_value` (keys are
+    /// case-insensitive), followed by the cell values from the top row to the bottom row.
+    /// The band is `Int32` if all values are integers and `Float64` otherwise.
+    ///
+    /// ```rust
+    /// use wkb_raster::{Raster, RasterDataSource, InMemoryRasterData};
+    ///
+    /// let grid = "\
+    /// ncols        3
+    /// nrows        2
+    /// xllcorner    1000
+    /// yllcorner    2000
+    /// cellsize     25
+    /// NODATA_value -9999
+    /// 1 2 3
+    /// 4 5 -9999
+    /// ";
+    ///
+    /// let raster = Raster::from_aaigrid(grid, 2056).unwrap();
+    /// assert_eq!((raster.ip_x, raster.ip_y, raster.scale_x, raster.scale_y), (1000.0, 2050.0, 25.0, -25.0));
+    /// assert_eq!(raster.bands[0].data, RasterDataSource::InMemory(InMemoryRasterData::Int32 {
+    ///     data: vec![vec![1, 2, 3], vec![4, 5, -9999]],
+    ///     nodata: Some(-9999),
+    /// }));
+    ///
+    /// assert_eq!(raster.to_aaigrid(0).unwrap(), grid);
+    /// ```
+    pub fn from_aaigrid(text: &str, srid: i32) -> Result<Raster, AaiGridError> {
+
+        let mut tokens = text.split_whitespace().peekable();
+
+        let mut ncols = None;
+        let mut nrows = None;
+        let mut x = None;
+        let mut y = None;
+        let mut cellsize = None;
+        let mut dx = None;
+        let mut dy = None;
+        let mut nodata = None;
+
+        while let Some(key) = tokens.next_if(|t| t.starts_with(|c: char| c.is_ascii_alphabetic())) {
+            let key = key.to_ascii_lowercase();
+            let value = tokens.next()
+                .and_then(|v| v.parse::<f64>().ok())
+                .ok_or_else(|| AaiGridError::InvalidHeaderValue(key.clone()))?;
+            // unknown keys are ignored, `true` marks coordinates of the cell center
+            match key.as_str() {
+                "ncols" => ncols = Some(value),
+                "nrows" => nrows = Some(value),
+                "xllcorner" => x = Some((value, false)),
+                "xllcenter" => x = Some((value, true)),
+                "yllcorner" => y = Some((value, false)),
+                "yllcenter" => y = Some((value, true)),
+                "cellsize" => cellsize = Some(value),
+                "dx" => dx = Some(value),
+                "dy" => dy = Some(value),
+                "nodata_value" => nodata = Some(value),
+                _ => {},
+            }
+        }
+
+        let count = |key: &'static str, value: Option<f64>| match value {
+            Some(v) if v >= 0.0 && v.fract() == 0.0 => Ok(v as usize),
+            Some(_) => Err(AaiGridError::InvalidHeaderValue(key.to_string())),
+            None => Err(AaiGridError::MissingHeader(key)),
+        };
+        let width = count("ncols", ncols)?;
+        let height = count("nrows", nrows)?;
+        if width > u16::MAX as usize || height > u16::MAX as usize {
+            return Err(AaiGridError::TooLarge { width, height });
+        }
+
+        let (x, x_center) = x.ok_or(AaiGridError::MissingHeader("xllcorner"))?;
+        let (y, y_center) = y.ok_or(AaiGridError::MissingHeader("yllcorner"))?;
+        let (dx, dy) = match (cellsize, dx, dy) {
+            (Some(size), _, _) => (size, size),
+            (None, Some(dx), Some(dy)) => (dx, dy),
+            _ => return Err(AaiGridError::MissingHeader("cellsize")),
+        };
+
+        let values = tokens.map(|t| t.parse::<f64>().map_err(|_| AaiGridError::InvalidValue(t.to_string()))).collect::<Result<Vec<_>, _>>()?;
+        if values.len() != width * height {
+            return Err(AaiGridError::ValueCount { expected: width * height, found: values.len() });
+        }
+
+        let is_int32 = |v: &f64| v.fract() == 0.0 && *v >= i32::MIN as f64 && *v <= i32::MAX as f64;
+        let pixtype = if values.iter().chain(nodata.iter()).all(is_int32) {
+            PixType::Int32(None)
+        } else {
+            PixType::Float64(None)
+        };
+
+        // lower left corner to upper left corner
+        let ip_x = if x_center { x - dx / 2.0 } else { x };
+        let ip_y = if y_center { y - dy / 2.0 } else { y } + height as f64 * dy;
+
+        Ok(Raster {
+            endian: Endian::Little,
+            version: 0,
+            scale_x: dx,
+            scale_y: -dy,
+            ip_x,
+            ip_y,
+            skew_x: 0.0,
+            skew_y: 0.0,
+            srid,
+            width: width as u16,
+            height: height as u16,
+            bands: vec![RasterBand {
+                is_nodata_value: false,
+                data: RasterDataSource::InMemory(InMemoryRasterData::from_fn_f64(pixtype.with_nodata_f64(nodata), width, height, |col, row| {
+                    values[row * width + col]
+                })),
+            }],
+        })
+    }
+
+    /// Encodes band `band` as Esri ASCII grid, with the lower left corner of the raster as
+    /// `xllcorner` / `yllcorner` and nodata pixels set to the `NODATA_value` of the band.
+    /// The raster has to be north-up without skew and with square pixels.
+    pub fn to_aaigrid(&self, band: usize) -> Result<String, AaiGridError> {
+
+        let data = match &self.bands.get(band).ok_or(AaiGridError::BandIndexOutOfRange(band))?.data {
+            RasterDataSource::InMemory(data) => data,
+            RasterDataSource::Offline(_) => return Err(AaiGridError::BandIsOffline),
+        };
+
+        if self.skew_x != 0.0 || self.skew_y != 0.0 {
+            return Err(AaiGridError::SkewedPixels);
+        }
+        if self.scale_x.abs() != self.scale_y.abs() {
+            return Err(AaiGridError::NonSquarePixels);
+        }
+        if self.scale_x <= 0.0 || self.scale_y >= 0.0 {
+            return Err(AaiGridError::NotNorthUp);
+        }
+
+        let width = data.width();
+        let height = data.height();
+        let pixtype = data.get_pixtype();
+
+        // shortest representation that parses back to the same value, with an exponent
+        // for very large or small floats
+        let format = |v: f64| match pixtype {
+            PixType::Float32(_) => format!("{:?}", v as f32),
+            PixType::Float64(_) => format!("{:?}", v),
+            _ => v.to_string(),
+        };
+
+        let mut text = String::new();
+        text.push_str(&format!("{:<12} {}\n", "ncols", width));
+        text.push_str(&format!("{:<12} {}\n", "nrows", height));
+        text.push_str(&format!("{:<12} {}\n", "xllcorner", self.ip_x));
+        text.push_str(&format!("{:<12} {}\n", "yllcorner", self.ip_y + height as f64 * self.scale_y));
+        text.push_str(&format!("{:<12} {}\n", "cellsize", self.scale_x));
+        if let Some(nodata) = pixtype.nodata_f64() {
+            text.push_str(&format!("{:<12} {}\n", "NODATA_value", format(nodata)));
+        }
+
+        for row in 0..height {
+            let line = (0..width).map(|col| format(data.get_f64(col, row).unwrap_or(0.0))).collect::<Vec<_>>();
+            text.push_str(&line.join(" "));
+            text.push('\n');
+        }
+
+        Ok(text)
+    }
+}
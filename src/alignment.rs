@@ -0,0 +1,136 @@
+//! Grid alignment checks (`ST_SameAlignment`) and snapping to a reference grid
+
+use std::fmt;
+use crate::Raster;
+use crate::resample::{ResampleAlgorithm, ResampleError, checked_size};
+
+/// Tolerance used when comparing scales, skews and pixel offsets (same as `FLT_EPSILON` in PostGIS)
+const ALIGNMENT_EPSILON: f64 = f32::EPSILON as f64;
+
+/// Reason why two rasters are not aligned
+#[derive(Debug, Copy, Clone, PartialEq, PartialOrd)]
+pub enum Misalignment {
+    /// The rasters have different SRIDs
+    Srid(i32, i32),
+    /// The rasters have different pixel sizes (`(scale_x, scale_y)` of both rasters)
+    Scale((f64, f64), (f64, f64)),
+    /// The rasters have different skews (`(skew_x, skew_y)` of both rasters)
+    Skew((f64, f64), (f64, f64)),
+    /// The upper-left corner of the second raster lies at the given non-integer
+    /// pixel position (column, row) of the first raster, i.e. the grids are shifted
+    /// against each other by a fraction of a pixel (`Offset(5.5, 0.0)` is a shift
+    /// of half a pixel)
+    Offset(f64, f64),
+}
+
+impl fmt::Display for Misalignment {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Misalignment::Srid(a, b) => write!(f, "rasters have different SRIDs ({} and {})", a, b),
+            Misalignment::Scale(a, b) => write!(f, "rasters have different scales ({:?} and {:?})", a, b),
+            Misalignment::Skew(a, b) => write!(f, "rasters have different skews ({:?} and {:?})", a, b),
+            Misalignment::Offset(col, row) => write!(f, "raster grids are shifted, the second raster starts at pixel ({}, {}) of the first", col, row),
+        }
+    }
+}
+
+impl std::error::Error for Misalignment { }
+
+/// Returns `true` if both rasters have the same scale, skew and SRID and
+/// their pixel corners lie on the same grid (like `ST_SameAlignment`)
+pub fn same_alignment(a: &Raster, b: &Raster) -> bool {
+    a.alignment_with(b).is_ok()
+}
+
+impl Raster {
+
+    /// Checks whether `other` is aligned with this raster, returning the first reason
+    /// why it isn't otherwise
+    ///
+    /// ```rust
+    /// use wkb_raster::{Raster, Endian, Misalignment, same_alignment};
+    ///
+    /// let a = Raster {
+    ///     endian: Endian::Big, version: 0,
+    ///     scale_x: 10.0, scale_y: -10.0, ip_x: 0.0, ip_y: 0.0, skew_x: 0.0, skew_y: 0.0,
+    ///     srid: 4326, width: 10, height: 10, bands: Vec::new(),
+    /// };
+    ///
+    /// let b = Raster { ip_x: 50.0, ip_y: -30.0, .. a.clone() };
+    /// assert!(same_alignment(&a, &b));
+    ///
+    /// let c = Raster { ip_x: 55.0, .. a.clone() };
+    /// assert_eq!(a.alignment_with(&c), Err(Misalignment::Offset(5.5, 0.0)));
+    /// ```
+    pub fn alignment_with(&self, other: &Raster) -> Result<(), Misalignment> {
+
+        if self.srid != other.srid {
+            return Err(Misalignment::Srid(self.srid, other.srid));
+        }
+
+        if !approx_eq(self.scale_x, other.scale_x) || !approx_eq(self.scale_y, other.scale_y) {
+            return Err(Misalignment::Scale((self.scale_x, self.scale_y), (other.scale_x, other.scale_y)));
+        }
+
+        if !approx_eq(self.skew_x, other.skew_x) || !approx_eq(self.skew_y, other.skew_y) {
+            return Err(Misalignment::Skew((self.skew_x, self.skew_y), (other.skew_x, other.skew_y)));
+        }
+
+        let (col, row) = self.world_to_pixel(other.ip_x, other.ip_y).unwrap_or((f64::NAN, f64::NAN));
+        if !approx_eq(col, col.round()) || !approx_eq(row, row.round()) {
+            return Err(Misalignment::Offset(col, row));
+        }
+
+        Ok(())
+    }
+
+    /// Resamples the raster onto the grid of `reference` (same scale, skew and pixel
+    /// corners), covering at least the extent of this raster. Only the geotransform
+    /// and SRID of `reference` are used, its size and bands are ignored.
+    pub fn snap_to_grid(&self, reference: &Raster, algorithm: ResampleAlgorithm) -> Result<Raster, ResampleError> {
+
+        if self.srid != reference.srid {
+            return Err(ResampleError::SridMismatch { source: self.srid, target: reference.srid });
+        }
+
+        let w = self.width as f64;
+        let h = self.height as f64;
+        let mut corners = Vec::with_capacity(4);
+        for &(col, row) in &[(0.0, 0.0), (w, 0.0), (0.0, h), (w, h)] {
+            let (x, y) = self.pixel_to_world(col, row);
+            let pixel = reference.world_to_pixel(x, y).ok_or(ResampleError::NonInvertibleGeoTransform)?;
+            corners.push(pixel);
+        }
+
+        // round to the grid, but don't add a column / row for floating point noise
+        let snap_down = |v: f64| if approx_eq(v, v.round()) { v.round() } else { v.floor() };
+        let snap_up = |v: f64| if approx_eq(v, v.round()) { v.round() } else { v.ceil() };
+
+        let min_col = snap_down(corners.iter().map(|c| c.0).fold(f64::INFINITY, f64::min));
+        let max_col = snap_up(corners.iter().map(|c| c.0).fold(f64::NEG_INFINITY, f64::max));
+        let min_row = snap_down(corners.iter().map(|c| c.1).fold(f64::INFINITY, f64::min));
+        let max_row = snap_up(corners.iter().map(|c| c.1).fold(f64::NEG_INFINITY, f64::max));
+
+        let (width, height) = checked_size(max_col - min_col, max_row - min_row)?;
+
+        let (ip_x, ip_y) = reference.pixel_to_world(min_col, min_row);
+
+        let target = Raster {
+            scale_x: reference.scale_x,
+            scale_y: reference.scale_y,
+            skew_x: reference.skew_x,
+            skew_y: reference.skew_y,
+            ip_x,
+            ip_y,
+            width,
+            height,
+            .. self.clone_without_bands()
+        };
+
+        self.resample(&target, algorithm)
+    }
+}
+
+fn approx_eq(a: f64, b: f64) -> bool {
+    (a - b).abs() <= ALIGNMENT_EPSILON
+}
@@ -0,0 +1,177 @@
+//! Construction of rasters from a size, a georeference and bands (like `ST_MakeEmptyRaster` and `ST_AddBand`)
+
+use std::fmt;
+use crate::{Raster, RasterBand, RasterDataSource, InMemoryRasterData, PixType, Endian, Pixel, TypedBand};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BuildError {
+    /// The in-memory band at the given index doesn't have `width` * `height` pixels
+    BandSizeMismatch { band: usize, width: usize, height: usize },
+    /// Pixel width and height have to be finite and non-zero
+    InvalidPixelSize { scale_x: f64, scale_y: f64 },
+    /// Origin and skew have to be finite
+    InvalidGeoTransform,
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BuildError::BandSizeMismatch { band, width, height } => write!(f, "band {} has {}x{} pixels, which doesn't match the raster size", band, width, height),
+            BuildError::InvalidPixelSize { scale_x, scale_y } => write!(f, "pixel size {} x {} is invalid (has to be finite and non-zero)", scale_x, scale_y),
+            BuildError::InvalidGeoTransform => write!(f, "origin and skew have to be finite"),
+        }
+    }
+}
+
+impl std::error::Error for BuildError { }
+
+/// Builds a raster of a fixed size, by default with little endian byte order (like the rasters
+/// read by `from_geotiff`, and the native order of common platforms), 1x1 north-up pixels
+/// with the upper left corner at (0, 0), SRID 0 and no bands.
+///
+/// ```rust
+/// use wkb_raster::{RasterBuilder, RasterDataSource, InMemoryRasterData, PixType, Endian};
+///
+/// let raster = RasterBuilder::new(3, 2)
+///     .origin(2600000.0, 1200000.0)
+///     .pixel_size(10.0, -10.0)
+///     .srid(2056)
+///     .add_band_filled(PixType::UInt8(Some(0)), 0.0)
+///     .add_band_from_fn(PixType::Int16(None), |col, row| (col * 10 + row) as f64)
+///     .build()
+///     .unwrap();
+///
+/// assert_eq!((raster.ip_x, raster.scale_y, raster.srid), (2600000.0, -10.0, 2056));
+/// assert_eq!(raster.endian, Endian::Little);
+/// assert!(raster.bands[0].is_nodata_value);
+/// assert_eq!(raster.bands[1].data, RasterDataSource::InMemory(InMemoryRasterData::Int16 {
+///     data: vec![vec![0, 10, 20], vec![1, 11, 21]],
+///     nodata: None,
+/// }));
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct RasterBuilder {
+    raster: Raster,
+}
+
+impl RasterBuilder {
+
+    /// Starts a raster of `width` pixel columns and `height` pixel rows
+    pub fn new(width: u16, height: u16) -> Self {
+        RasterBuilder {
+            raster: Raster {
+                endian: Endian::Little,
+                version: 0,
+                scale_x: 1.0,
+                scale_y: -1.0,
+                ip_x: 0.0,
+                ip_y: 0.0,
+                skew_x: 0.0,
+                skew_y: 0.0,
+                srid: 0,
+                width,
+                height,
+                bands: Vec::new(),
+            },
+        }
+    }
+
+    /// Sets the byte order of the WKB encoding
+    pub fn endian(mut self, endian: Endian) -> Self {
+        self.raster.endian = endian;
+        self
+    }
+
+    /// Sets the upper left corner of the upper left pixel
+    pub fn origin(mut self, x: f64, y: f64) -> Self {
+        self.raster.ip_x = x;
+        self.raster.ip_y = y;
+        self
+    }
+
+    /// Sets the pixel width and height (negative for north-up rasters)
+    pub fn pixel_size(mut self, scale_x: f64, scale_y: f64) -> Self {
+        self.raster.scale_x = scale_x;
+        self.raster.scale_y = scale_y;
+        self
+    }
+
+    /// Sets the rotation terms of the geotransform
+    pub fn skew(mut self, skew_x: f64, skew_y: f64) -> Self {
+        self.raster.skew_x = skew_x;
+        self.raster.skew_y = skew_y;
+        self
+    }
+
+    pub fn srid(mut self, srid: i32) -> Self {
+        self.raster.srid = srid;
+        self
+    }
+
+    /// Adds an in-memory band of `pixtype` (including its nodata value) with all pixels set to
+    /// `value`; the band is flagged as nodata band if `value` is the nodata value
+    pub fn add_band_filled(self, pixtype: PixType, value: f64) -> Self {
+        // compare the value after the conversion to the pixel type
+        let is_nodata_value = pixtype.has_nodata_value()
+            && InMemoryRasterData::from_fn_f64(pixtype, 1, 1, |_, _| value).get_value(0, 0).is_none();
+        let data = InMemoryRasterData::from_fn_f64(pixtype, self.raster.width as usize, self.raster.height as usize, |_, _| value);
+        self.add_band(RasterBand {
+            is_nodata_value,
+            data: RasterDataSource::InMemory(data),
+        })
+    }
+
+    /// Adds an in-memory band of `pixtype`, calling `f(col, row)` for each pixel (values are
+    /// converted as by `InMemoryRasterData::from_fn_f64`)
+    pub fn add_band_from_fn<F: FnMut(usize, usize) -> f64>(self, pixtype: PixType, f: F) -> Self {
+        let data = InMemoryRasterData::from_fn_f64(pixtype, self.raster.width as usize, self.raster.height as usize, f);
+        self.add_band(RasterBand {
+            is_nodata_value: false,
+            data: RasterDataSource::InMemory(data),
+        })
+    }
+
+    /// Adds a typed in-memory band, its size is checked by `build`
+    pub fn add_typed_band<T: Pixel>(self, band: TypedBand<T>) -> Self {
+        self.add_band(RasterBand {
+            is_nodata_value: false,
+            data: RasterDataSource::InMemory(band.into()),
+        })
+    }
+
+    /// Adds an in-memory or out-db band, the size of in-memory bands is checked by `build`
+    pub fn add_band(mut self, band: RasterBand) -> Self {
+        self.raster.bands.push(band);
+        self
+    }
+
+    /// Returns the raster, or an error if an in-memory band doesn't have the size of the raster
+    /// or the geotransform is invalid
+    pub fn build(self) -> Result<Raster, BuildError> {
+
+        let raster = self.raster;
+
+        if raster.scale_x == 0.0 || raster.scale_y == 0.0 || !raster.scale_x.is_finite() || !raster.scale_y.is_finite() {
+            return Err(BuildError::InvalidPixelSize { scale_x: raster.scale_x, scale_y: raster.scale_y });
+        }
+        if ![raster.ip_x, raster.ip_y, raster.skew_x, raster.skew_y].iter().all(|v| v.is_finite()) {
+            return Err(BuildError::InvalidGeoTransform);
+        }
+
+        for (i, band) in raster.bands.iter().enumerate() {
+            if let RasterDataSource::InMemory(data) = &band.data {
+                let (width, height) = (data.width(), data.height());
+                if width != raster.width as usize || height != raster.height as usize || !same_row_lengths(data, width) {
+                    return Err(BuildError::BandSizeMismatch { band: i, width, height });
+                }
+            }
+        }
+
+        Ok(raster)
+    }
+}
+
+/// All rows have `width` pixels
+fn same_row_lengths(data: &InMemoryRasterData, width: usize) -> bool {
+    match_in_memory_data!(data, rows => rows.iter().all(|row| row.len() == width))
+}
@@ -0,0 +1,161 @@
+//! Clipping rasters by polygon geometries (`ST_Clip`)
+
+use std::fmt;
+use crate::{Raster, RasterBand, RasterDataSource, InMemoryRasterData};
+use crate::geometry::{Geometry, GeometryError};
+use crate::rasterize::{BurnRule, geometry_mask};
+use crate::window::WindowError;
+
+#[derive(Debug, Clone, PartialEq, PartialOrd, Hash, Eq, Ord)]
+pub enum ClipError {
+    /// The geometry WKB could not be parsed
+    Geometry(GeometryError),
+    /// The geometry doesn't contain any polygons
+    NotAPolygon,
+    /// With `crop = true`: the bounding box of the geometry doesn't intersect the raster
+    NoIntersection,
+    /// Band at the given index is stored out-of-db, pixels can't be clipped
+    BandIsOffline(usize),
+    /// The geotransform of the raster can't be inverted
+    NonInvertibleGeoTransform,
+}
+
+impl fmt::Display for ClipError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ClipError::Geometry(e) => write!(f, "invalid clip geometry: {}", e),
+            ClipError::NotAPolygon => write!(f, "clip geometry doesn't contain any polygons"),
+            ClipError::NoIntersection => write!(f, "clip geometry doesn't intersect the raster"),
+            ClipError::BandIsOffline(band) => write!(f, "band {} is stored out-of-db and can't be clipped", band),
+            ClipError::NonInvertibleGeoTransform => write!(f, "geotransform of the raster can't be inverted"),
+        }
+    }
+}
+
+impl std::error::Error for ClipError { }
+
+impl From<GeometryError> for ClipError {
+    fn from(e: GeometryError) -> Self {
+        ClipError::Geometry(e)
+    }
+}
+
+impl Raster {
+
+    /// Sets all pixels outside of the polygon(s) in `geometry_wkb` (binary WKB or EWKB,
+    /// multipolygons and holes are supported) to nodata, like `ST_Clip`. The coordinates
+    /// of the geometry have to be in the SRID of the raster.
+    ///
+    /// Outside pixels are set to `nodata` if given, otherwise to the nodata value of
+    /// each band, otherwise to the minimum value of the pixel type; the nodata value of
+    /// each band is updated accordingly, and nodata pixels inside of the polygon(s) are set
+    /// to the new nodata value as well. `rule` decides whether a pixel is inside.
+    /// If `crop` is true, the raster is cropped to the bounding box of the geometry
+    /// (and `ip_x` / `ip_y` are updated).
+    ///
+    /// ```rust
+    /// use wkb_raster::{Raster, RasterBand, RasterDataSource, InMemoryRasterData, Endian, Geometry, BurnRule};
+    ///
+    /// let raster = Raster {
+    ///     endian: Endian::Big, version: 0,
+    ///     scale_x: 1.0, scale_y: -1.0, ip_x: 0.0, ip_y: 4.0, skew_x: 0.0, skew_y: 0.0,
+    ///     srid: 4326, width: 4, height: 4,
+    ///     bands: vec![RasterBand {
+    ///         is_nodata_value: false,
+    ///         data: RasterDataSource::InMemory(InMemoryRasterData::UInt8 {
+    ///             data: vec![vec![1; 4]; 4],
+    ///             nodata: None,
+    ///         }),
+    ///     }],
+    /// };
+    ///
+    /// let l_shape = Geometry::Polygon(vec![vec![
+    ///     (1.0, 1.0), (3.0, 1.0), (3.0, 2.0), (2.0, 2.0), (2.0, 3.0), (1.0, 3.0), (1.0, 1.0),
+    /// ]]);
+    /// let wkb = l_shape.to_wkb(Endian::Little);
+    ///
+    /// let clipped = raster.clip(&wkb, true, Some(0.0), BurnRule::CenterIn).unwrap();
+    /// assert_eq!((clipped.ip_x, clipped.ip_y, clipped.width, clipped.height), (1.0, 3.0, 2, 2));
+    /// assert_eq!(clipped.bands[0].data, RasterDataSource::InMemory(InMemoryRasterData::UInt8 {
+    ///     data: vec![vec![1, 0], vec![1, 1]],
+    ///     nodata: Some(0),
+    /// }));
+    ///
+    /// // nodata pixels of the band become the new nodata value
+    /// let with_nodata = Raster {
+    ///     bands: vec![RasterBand {
+    ///         is_nodata_value: false,
+    ///         data: RasterDataSource::InMemory(InMemoryRasterData::UInt8 {
+    ///             data: vec![vec![1, 1, 1, 1], vec![1, 9, 1, 1], vec![1, 1, 1, 1], vec![1, 1, 1, 1]],
+    ///             nodata: Some(9),
+    ///         }),
+    ///     }],
+    ///     .. raster.clone()
+    /// };
+    /// let clipped = with_nodata.clip(&wkb, true, Some(0.0), BurnRule::CenterIn).unwrap();
+    /// assert_eq!(clipped.bands[0].data, RasterDataSource::InMemory(InMemoryRasterData::UInt8 {
+    ///     data: vec![vec![0, 0], vec![1, 1]],
+    ///     nodata: Some(0),
+    /// }));
+    /// ```
+    pub fn clip(&self, geometry_wkb: &[u8], crop: bool, nodata: Option<f64>, rule: BurnRule) -> Result<Raster, ClipError> {
+
+        let geometry = Geometry::from_wkb(geometry_wkb)?;
+        if geometry.polygons().is_empty() {
+            return Err(ClipError::NotAPolygon);
+        }
+
+        // only the polygons are used for clipping
+        let polygons = Geometry::MultiPolygon(geometry.polygons().iter().map(|p| p.to_vec()).collect());
+
+        let (col_off, row_off, width, height) = if crop {
+            let (min_x, min_y, max_x, max_y) = polygons.bounding_box().ok_or(ClipError::NotAPolygon)?;
+            self.bbox_to_window(min_x, min_y, max_x, max_y).map_err(|e| match e {
+                WindowError::NonInvertibleGeoTransform => ClipError::NonInvertibleGeoTransform,
+                _ => ClipError::NoIntersection,
+            })?
+        } else {
+            (0, 0, self.width as usize, self.height as usize)
+        };
+
+        let (ip_x, ip_y) = self.pixel_to_world(col_off as f64, row_off as f64);
+        let mut clipped = Raster {
+            ip_x,
+            ip_y,
+            width: width as u16,
+            height: height as u16,
+            .. self.clone_without_bands()
+        };
+
+        let mask = geometry_mask(&clipped, &polygons, rule).ok_or(ClipError::NonInvertibleGeoTransform)?;
+
+        for (i, band) in self.bands.iter().enumerate() {
+
+            let data = match &band.data {
+                RasterDataSource::InMemory(data) => data,
+                RasterDataSource::Offline(_) => return Err(ClipError::BandIsOffline(i)),
+            };
+
+            let pixtype = data.get_pixtype();
+            let fill = nodata
+                .or_else(|| pixtype.nodata_f64())
+                .unwrap_or_else(|| pixtype.value_range().0);
+            let pixtype = pixtype.with_nodata_f64(Some(fill));
+
+            let new_data = InMemoryRasterData::from_fn_f64(pixtype, width, height, |col, row| {
+                if mask[row * width + col] {
+                    data.get_value(col_off + col, row_off + row).unwrap_or(fill)
+                } else {
+                    fill
+                }
+            });
+
+            clipped.bands.push(RasterBand {
+                is_nodata_value: false,
+                data: RasterDataSource::InMemory(new_data),
+            });
+        }
+
+        Ok(clipped)
+    }
+}
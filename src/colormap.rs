@@ -0,0 +1,324 @@
+//! Rendering of single bands with color maps (`ST_ColorMap`)
+
+use std::str::FromStr;
+use std::fmt;
+use crate::{Raster, RasterBand, RasterDataSource, InMemoryRasterData, PixType};
+
+#[derive(Debug, Clone, PartialEq, PartialOrd)]
+pub enum ColorMapError {
+    /// A line of the color map could not be parsed, contains the offending line
+    InvalidLine(String),
+    /// The color map doesn't contain any value stops
+    NoStops,
+    /// The band index does not exist in the raster
+    BandIndexOutOfRange(usize),
+    /// Band data is stored out-of-db, pixels can't be colored
+    BandIsOffline,
+}
+
+impl fmt::Display for ColorMapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ColorMapError::InvalidLine(line) => write!(f, "invalid color map line: {}", line),
+            ColorMapError::NoStops => write!(f, "color map doesn't contain any value stops"),
+            ColorMapError::BandIndexOutOfRange(band) => write!(f, "band {} does not exist in the raster", band),
+            ColorMapError::BandIsOffline => write!(f, "band is stored out-of-db and can't be colored"),
+        }
+    }
+}
+
+impl std::error::Error for ColorMapError { }
+
+/// Position of a color stop
+#[derive(Debug, Copy, Clone, PartialEq, PartialOrd)]
+pub enum StopValue {
+    /// Absolute pixel value
+    Value(f64),
+    /// Percentage between the smallest (0%) and the largest (100%) valid value of the band
+    Percent(f64),
+}
+
+/// A color at a value of a color map
+#[derive(Debug, Copy, Clone, PartialEq, PartialOrd)]
+pub struct ColorStop {
+    pub value: StopValue,
+    /// RGBA
+    pub color: [u8; 4],
+}
+
+/// How pixel values between the color stops are colored (`method` of `ST_ColorMap`)
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ColorMapMethod {
+    /// Linear interpolation between the two surrounding stops, values outside of the
+    /// stops get the color of the first / last stop
+    Interpolate,
+    /// Only values that exactly match a stop are colored, all other pixels are transparent
+    Exact,
+    /// Color of the stop with the closest value
+    Nearest,
+}
+
+/// Color map in the format of `ST_ColorMap`: one stop per line, consisting of a value
+/// (a number, a percentage like `50%`, or `nv` / `null` / `nodata` for nodata pixels) followed by
+/// one (gray), three (RGB) or four (RGBA) color components from 0 to 255. Values and components
+/// are separated by spaces, tabs, commas or colons.
+///
+/// The presets `grayscale` (or `greyscale`), `pseudocolor`, `fire` and `bluered` can be used by name.
+///
+/// ```rust
+/// use wkb_raster::{ColorMap, StopValue};
+///
+/// let map: ColorMap = "
+///     100% 255 0 0
+///     0%   0 0 255
+///     nv   0 0 0 0
+/// ".parse().unwrap();
+///
+/// assert_eq!(map.stops()[0].value, StopValue::Percent(100.0));
+/// assert_eq!(map.stops()[1].color, [0, 0, 255, 255]);
+/// assert_eq!(map.nodata_color(), Some([0, 0, 0, 0]));
+///
+/// assert_eq!("greyscale".parse::<ColorMap>(), Ok(ColorMap::preset("grayscale").unwrap()));
+/// ```
+#[derive(Debug, Clone, PartialEq, PartialOrd)]
+pub struct ColorMap {
+    stops: Vec<ColorStop>,
+    nodata: Option<[u8; 4]>,
+}
+
+const GRAYSCALE: &str = "
+100% 255 255 255 255
+0% 0 0 0 255
+nv 0 0 0 0
+";
+
+const PSEUDOCOLOR: &str = "
+100% 255 0 0 255
+50% 0 255 0 255
+0% 0 0 255 255
+nv 0 0 0 0
+";
+
+const FIRE: &str = "
+100% 243 255 221 255
+93.75% 242 255 178 255
+84.375% 255 255 135 255
+75% 255 228 96 255
+65.625% 255 187 53 255
+56.25% 255 131 7 255
+46.875% 255 84 0 255
+37.5% 255 28 0 255
+28.125% 255 0 0 255
+18.75% 198 0 0 255
+9.375% 103 0 0 255
+0% 32 0 0 255
+nv 0 0 0 0
+";
+
+/// Diverging ramp from blue over white to red
+const BLUERED: &str = "
+100% 103 0 31 255
+90% 178 24 43 255
+80% 214 96 77 255
+70% 244 165 130 255
+60% 253 219 199 255
+50% 247 247 247 255
+40% 209 229 240 255
+30% 146 197 222 255
+20% 67 147 195 255
+10% 33 102 172 255
+0% 5 48 97 255
+nv 0 0 0 0
+";
+
+impl ColorMap {
+
+    /// Creates a color map, `nodata` is the color of nodata pixels (transparent if `None`)
+    pub fn new(stops: Vec<ColorStop>, nodata: Option<[u8; 4]>) -> Result<Self, ColorMapError> {
+        if stops.is_empty() {
+            return Err(ColorMapError::NoStops);
+        }
+        Ok(ColorMap { stops, nodata })
+    }
+
+    /// Returns one of the named color maps of `ST_ColorMap`
+    pub fn preset(name: &str) -> Option<Self> {
+        let spec = match name.trim().to_ascii_lowercase().as_str() {
+            "grayscale" | "greyscale" => GRAYSCALE,
+            "pseudocolor" => PSEUDOCOLOR,
+            "fire" => FIRE,
+            "bluered" => BLUERED,
+            _ => return None,
+        };
+        parse_color_map(spec).ok()
+    }
+
+    pub fn stops(&self) -> &[ColorStop] {
+        &self.stops
+    }
+
+    /// Color of nodata pixels, if set with a `nv` line
+    pub fn nodata_color(&self) -> Option<[u8; 4]> {
+        self.nodata
+    }
+
+    /// Returns the stops with percentages resolved against the value range `min..=max`,
+    /// sorted by value
+    fn resolve(&self, min: f64, max: f64) -> Vec<(f64, [u8; 4])> {
+        let mut stops = self.stops.iter().map(|s| {
+            let value = match s.value {
+                StopValue::Value(v) => v,
+                StopValue::Percent(p) => min + (max - min) * p / 100.0,
+            };
+            (value, s.color)
+        }).collect::<Vec<_>>();
+        stops.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+        stops
+    }
+}
+
+impl FromStr for ColorMap {
+    type Err = ColorMapError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match ColorMap::preset(s) {
+            Some(preset) => Ok(preset),
+            None => parse_color_map(s),
+        }
+    }
+}
+
+fn parse_color_map(s: &str) -> Result<ColorMap, ColorMapError> {
+
+    let mut stops = Vec::new();
+    let mut nodata = None;
+
+    for line in s.lines().map(|l| l.trim()).filter(|l| !l.is_empty()) {
+
+        let invalid = || ColorMapError::InvalidLine(line.to_string());
+
+        let mut tokens = line
+            .split(|c: char| c.is_whitespace() || c == ',' || c == ':')
+            .filter(|t| !t.is_empty());
+
+        let value = tokens.next().ok_or_else(invalid)?;
+        let components = tokens.map(|t| t.parse::<u8>()).collect::<Result<Vec<_>, _>>().map_err(|_| invalid())?;
+
+        let color = match components[..] {
+            [gray] => [gray, gray, gray, 255],
+            [r, g, b] => [r, g, b, 255],
+            [r, g, b, a] => [r, g, b, a],
+            _ => return Err(invalid()),
+        };
+
+        match value.to_ascii_lowercase().as_str() {
+            "nv" | "null" | "nodata" => nodata = Some(color),
+            v => {
+                let value = match v.strip_suffix('%') {
+                    Some(p) => StopValue::Percent(p.trim().parse().map_err(|_| invalid())?),
+                    None => StopValue::Value(v.parse().map_err(|_| invalid())?),
+                };
+                stops.push(ColorStop { value, color });
+            },
+        }
+    }
+
+    ColorMap::new(stops, nodata)
+}
+
+impl Raster {
+
+    /// Colors band `band` with a color map, returning a raster with the same geotransform and
+    /// four `UInt8` bands (red, green, blue, alpha). Nodata pixels get the `nv` color of the
+    /// color map or are transparent (all components 0).
+    ///
+    /// ```rust
+    /// use wkb_raster::{Raster, RasterBand, RasterDataSource, InMemoryRasterData, Endian, ColorMap, ColorMapMethod};
+    ///
+    /// let raster = Raster {
+    ///     endian: Endian::Big, version: 0,
+    ///     scale_x: 1.0, scale_y: -1.0, ip_x: 0.0, ip_y: 1.0, skew_x: 0.0, skew_y: 0.0,
+    ///     srid: 4326, width: 4, height: 1,
+    ///     bands: vec![RasterBand {
+    ///         is_nodata_value: false,
+    ///         data: RasterDataSource::InMemory(InMemoryRasterData::Int16 {
+    ///             data: vec![vec![-10, 40, 90, -9999]],
+    ///             nodata: Some(-9999),
+    ///         }),
+    ///     }],
+    /// };
+    ///
+    /// let rgba = raster.color_map(0, &ColorMap::preset("grayscale").unwrap(), ColorMapMethod::Interpolate).unwrap();
+    /// assert_eq!(rgba.bands.len(), 4);
+    /// assert_eq!(rgba.bands[0].data, RasterDataSource::InMemory(InMemoryRasterData::UInt8 {
+    ///     data: vec![vec![0, 128, 255, 0]],
+    ///     nodata: None,
+    /// }));
+    /// assert_eq!(rgba.bands[3].data, RasterDataSource::InMemory(InMemoryRasterData::UInt8 {
+    ///     data: vec![vec![255, 255, 255, 0]],
+    ///     nodata: None,
+    /// }));
+    /// ```
+    pub fn color_map(&self, band: usize, color_map: &ColorMap, method: ColorMapMethod) -> Result<Raster, ColorMapError> {
+
+        let data = match &self.bands.get(band).ok_or(ColorMapError::BandIndexOutOfRange(band))?.data {
+            RasterDataSource::InMemory(data) => data,
+            RasterDataSource::Offline(_) => return Err(ColorMapError::BandIsOffline),
+        };
+
+        let width = data.width();
+        let height = data.height();
+
+        let (min, max) = (0..height)
+            .flat_map(|row| (0..width).filter_map(move |col| data.get_value(col, row)))
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), v| (min.min(v), max.max(v)));
+
+        let stops = color_map.resolve(min, max);
+        let nodata_color = color_map.nodata_color().unwrap_or([0; 4]);
+
+        let colors = (0..height).flat_map(|row| (0..width).map(move |col| (col, row))).map(|(col, row)| {
+            match data.get_value(col, row) {
+                Some(v) => color_at(&stops, v, method),
+                None => nodata_color,
+            }
+        }).collect::<Vec<_>>();
+
+        let bands = (0..4).map(|c| RasterBand {
+            is_nodata_value: false,
+            data: RasterDataSource::InMemory(InMemoryRasterData::from_fn_f64(PixType::UInt8(None), width, height, |col, row| {
+                colors[row * width + col][c] as f64
+            })),
+        }).collect();
+
+        Ok(Raster {
+            bands,
+            .. self.clone_without_bands()
+        })
+    }
+}
+
+/// Color of a value, `stops` are sorted by value and not empty
+fn color_at(stops: &[(f64, [u8; 4])], value: f64, method: ColorMapMethod) -> [u8; 4] {
+    match method {
+        ColorMapMethod::Exact => stops.iter().find(|s| s.0 == value).map_or([0; 4], |s| s.1),
+        ColorMapMethod::Nearest => stops.iter()
+            .min_by(|a, b| (a.0 - value).abs().partial_cmp(&(b.0 - value).abs()).unwrap_or(std::cmp::Ordering::Equal))
+            .map_or([0; 4], |s| s.1),
+        ColorMapMethod::Interpolate => {
+            let upper = stops.iter().position(|s| s.0 >= value);
+            match upper {
+                None => stops[stops.len() - 1].1,
+                Some(0) => stops[0].1,
+                Some(i) => {
+                    let ((v0, c0), (v1, c1)) = (stops[i - 1], stops[i]);
+                    let t = (value - v0) / (v1 - v0);
+                    let mut color = [0; 4];
+                    for (c, (a, b)) in color.iter_mut().zip(c0.iter().zip(c1.iter())) {
+                        *c = (*a as f64 + t * (*b as f64 - *a as f64)).round() as u8;
+                    }
+                    color
+                },
+            }
+        },
+    }
+}
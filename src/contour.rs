@@ -0,0 +1,480 @@
+//! Contour lines and filled contour polygons of bands (marching squares)
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use crate::{Raster, RasterDataSource};
+use crate::geometry::{Coord, Geometry};
+use crate::polygonize::{assemble_polygons, signed_area, split_ring};
+
+#[derive(Debug, Copy, Clone, PartialEq, PartialOrd)]
+pub enum ContourError {
+    /// The band index does not exist in the raster
+    BandIndexOutOfRange(usize),
+    /// Band data is stored out-of-db, contours can't be computed
+    BandIsOffline,
+    /// The contour interval is not a positive, finite number
+    InvalidInterval(f64),
+    /// A contour level is not a finite number
+    InvalidLevel(f64),
+    /// The interval yields more than `ContourLevels::MAX_INTERVAL_LEVELS` levels within the range of the band values
+    TooManyLevels { interval: f64, min: f64, max: f64 },
+}
+
+impl fmt::Display for ContourError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ContourError::BandIndexOutOfRange(band) => write!(f, "band {} does not exist in the raster", band),
+            ContourError::BandIsOffline => write!(f, "band is stored out-of-db, contours can't be computed"),
+            ContourError::InvalidInterval(interval) => write!(f, "contour interval {} is not a positive, finite number", interval),
+            ContourError::InvalidLevel(level) => write!(f, "contour level {} is not a finite number", level),
+            ContourError::TooManyLevels { interval, min, max } => write!(f, "contour interval {} yields too many levels between {} and {}", interval, min, max),
+        }
+    }
+}
+
+impl std::error::Error for ContourError { }
+
+/// The values at which contours are generated
+#[derive(Debug, Clone, PartialEq, PartialOrd)]
+pub enum ContourLevels {
+    /// Every `offset + k * interval` within the range of the band values (like `gdal_contour -i -off`),
+    /// at most `ContourLevels::MAX_INTERVAL_LEVELS` levels
+    Interval { interval: f64, offset: f64 },
+    /// Explicit levels (like `gdal_contour -fl`)
+    Fixed(Vec<f64>),
+}
+
+impl ContourLevels {
+
+    /// Maximum number of levels generated by `ContourLevels::Interval`
+    pub const MAX_INTERVAL_LEVELS: usize = 100_000;
+}
+
+/// Contour line of a single level
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContourLine {
+    /// The value along the line
+    pub level: f64,
+    /// SRID of the raster the contours were generated from
+    pub srid: i32,
+    /// `MultiLineString` in world coordinates
+    pub geometry: Geometry,
+}
+
+/// Area where the band values are between two levels
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContourPolygon {
+    /// The lower level (inclusive), or the smallest band value for the lowest polygon
+    pub min: f64,
+    /// The upper level (exclusive), or the largest band value for the highest polygon
+    pub max: f64,
+    /// SRID of the raster the contours were generated from
+    pub srid: i32,
+    /// `Polygon` or `MultiPolygon` in world coordinates
+    pub geometry: Geometry,
+}
+
+/// Sample point of the contour grid: pixel centers and the centers of the cells between them
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+enum Node {
+    Pixel(usize, usize),
+    CellCenter(usize, usize),
+}
+
+impl Node {
+    /// Position in pixel space
+    fn position(self) -> Coord {
+        match self {
+            Node::Pixel(col, row) => (col as f64 + 0.5, row as f64 + 0.5),
+            Node::CellCenter(col, row) => (col as f64 + 1.0, row as f64 + 1.0),
+        }
+    }
+}
+
+/// Vertex of a contour: a sample point, or the point where the edge between two sample
+/// points crosses the level with the given index
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+enum Vertex {
+    Node(Node),
+    Crossing(Node, Node, usize),
+}
+
+impl Vertex {
+    /// Crossing of the edge between `a` and `b`: the nodes are ordered, so both triangles
+    /// next to an edge produce the same vertex
+    fn crossing(a: Node, b: Node, level: usize) -> Vertex {
+        if a < b { Vertex::Crossing(a, b, level) } else { Vertex::Crossing(b, a, level) }
+    }
+}
+
+type Triangle = [(Node, f64); 3];
+
+/// Sampled band values with the triangles of all cells without nodata
+struct ContourGrid {
+    triangles: Vec<Triangle>,
+    values: HashMap<Node, f64>,
+    levels: Vec<f64>,
+}
+
+impl ContourGrid {
+
+    /// Position in pixel space
+    fn position(&self, vertex: Vertex) -> Coord {
+        match vertex {
+            Vertex::Node(node) => node.position(),
+            Vertex::Crossing(a, b, level) => {
+                let (va, vb) = (self.values[&a], self.values[&b]);
+                let t = (self.levels[level] - va) / (vb - va);
+                let (pa, pb) = (a.position(), b.position());
+                (pa.0 + t * (pb.0 - pa.0), pa.1 + t * (pb.1 - pa.1))
+            },
+        }
+    }
+
+    /// Converts vertices to positions in pixel space, dropping repeated positions
+    fn positions(&self, vertices: &[Vertex]) -> Vec<Coord> {
+        let mut coords: Vec<Coord> = Vec::with_capacity(vertices.len());
+        for &v in vertices {
+            let p = self.position(v);
+            if coords.last() != Some(&p) {
+                coords.push(p);
+            }
+        }
+        coords
+    }
+}
+
+impl Raster {
+
+    /// Generates contour lines of band `band`, one `MultiLineString` per level that occurs
+    /// in the band. Contours are interpolated between pixel centers (saddles are resolved
+    /// with the mean of the four surrounding pixels); cells next to nodata pixels are skipped,
+    /// so contours end at nodata gaps.
+    ///
+    /// ```rust
+    /// use wkb_raster::{Raster, RasterBand, RasterDataSource, InMemoryRasterData, Endian, ContourLevels};
+    ///
+    /// let raster = Raster {
+    ///     endian: Endian::Big, version: 0,
+    ///     scale_x: 1.0, scale_y: -1.0, ip_x: 0.0, ip_y: 2.0, skew_x: 0.0, skew_y: 0.0,
+    ///     srid: 4326, width: 3, height: 2,
+    ///     bands: vec![RasterBand {
+    ///         is_nodata_value: false,
+    ///         data: RasterDataSource::InMemory(InMemoryRasterData::UInt8 {
+    ///             data: vec![vec![0, 10, 20], vec![0, 10, 20]],
+    ///             nodata: None,
+    ///         }),
+    ///     }],
+    /// };
+    ///
+    /// let lines = raster.contour_lines(0, &ContourLevels::Fixed(vec![5.0, 15.0])).unwrap();
+    /// assert_eq!(lines.len(), 2);
+    /// assert_eq!((lines[0].level, lines[0].srid), (5.0, 4326));
+    /// assert_eq!(lines[0].geometry.to_wkt(), "MULTILINESTRING((1 0.5,1 1,1 1.5))");
+    /// ```
+    pub fn contour_lines(&self, band: usize, levels: &ContourLevels) -> Result<Vec<ContourLine>, ContourError> {
+
+        let grid = self.contour_grid(band, levels)?;
+        let mut lines = Vec::new();
+
+        for (k, &level) in grid.levels.iter().enumerate() {
+
+            // segments run with the higher values on their right-hand side (in pixel space)
+            let mut segments = Vec::new();
+            for triangle in grid.triangles.iter() {
+                let mut exit = None;
+                let mut enter = None;
+                for e in 0..3 {
+                    let (a, b) = (triangle[e], triangle[(e + 1) % 3]);
+                    match (a.1 >= level, b.1 >= level) {
+                        (true, false) => exit = Some(Vertex::crossing(a.0, b.0, k)),
+                        (false, true) => enter = Some(Vertex::crossing(a.0, b.0, k)),
+                        _ => {},
+                    }
+                }
+                if let (Some(exit), Some(enter)) = (exit, enter) {
+                    segments.push((exit, enter));
+                }
+            }
+
+            let parts = link_segments(&segments)
+                .iter()
+                .map(|line| grid.positions(line))
+                .filter(|line| line.len() > 1)
+                .map(|line| line.into_iter().map(|(c, r)| self.pixel_to_world(c, r)).collect())
+                .collect::<Vec<_>>();
+
+            if !parts.is_empty() {
+                lines.push(ContourLine {
+                    level,
+                    srid: self.srid,
+                    geometry: Geometry::MultiLineString(parts),
+                });
+            }
+        }
+
+        Ok(lines)
+    }
+
+    /// Generates filled contours of band `band`: one polygon (or multipolygon) for the area
+    /// between each pair of consecutive levels, plus the areas below the lowest and above
+    /// the highest level. Areas without any pixels are skipped, nodata gaps become holes.
+    /// Interpolation works like in `contour_lines`, so the polygons share their boundaries
+    /// with the contour lines.
+    ///
+    /// ```rust
+    /// use wkb_raster::{Raster, RasterBand, RasterDataSource, InMemoryRasterData, Endian, ContourLevels, ContourError};
+    ///
+    /// let raster = Raster {
+    ///     endian: Endian::Big, version: 0,
+    ///     scale_x: 1.0, scale_y: -1.0, ip_x: 0.0, ip_y: 2.0, skew_x: 0.0, skew_y: 0.0,
+    ///     srid: 4326, width: 3, height: 2,
+    ///     bands: vec![RasterBand {
+    ///         is_nodata_value: false,
+    ///         data: RasterDataSource::InMemory(InMemoryRasterData::UInt8 {
+    ///             data: vec![vec![0, 10, 20], vec![0, 10, 20]],
+    ///             nodata: None,
+    ///         }),
+    ///     }],
+    /// };
+    ///
+    /// let polygons = raster.contour_polygons(0, &ContourLevels::Interval { interval: 10.0, offset: 5.0 }).unwrap();
+    /// let bounds = polygons.iter().map(|p| (p.min, p.max)).collect::<Vec<_>>();
+    /// assert_eq!(bounds, vec![(0.0, 5.0), (5.0, 15.0), (15.0, 20.0)]);
+    /// assert_eq!(polygons[0].geometry.to_wkt(), "POLYGON((0.5 1.5,0.5 0.5,1 0.5,1 1,1 1.5,0.5 1.5))");
+    ///
+    /// let tiny = ContourLevels::Interval { interval: 1e-9, offset: 0.0 };
+    /// assert_eq!(raster.contour_polygons(0, &tiny), Err(ContourError::TooManyLevels { interval: 1e-9, min: 0.0, max: 20.0 }));
+    /// ```
+    pub fn contour_polygons(&self, band: usize, levels: &ContourLevels) -> Result<Vec<ContourPolygon>, ContourError> {
+
+        let grid = self.contour_grid(band, levels)?;
+
+        let (min_value, max_value) = grid.values.values().fold(
+            (f64::INFINITY, f64::NEG_INFINITY),
+            |(min, max), &v| (min.min(v), max.max(v)),
+        );
+
+        // levels that split the value range; the outer polygons are only bounded by the values
+        let inner = grid.levels.iter()
+            .enumerate()
+            .filter(|(_, &l)| l > min_value && l <= max_value)
+            .map(|(k, &l)| (k, l))
+            .collect::<Vec<_>>();
+
+        let mut bounds = vec![None];
+        bounds.extend(inner.iter().copied().map(Some));
+        bounds.push(None);
+
+        let mut polygons = Vec::new();
+
+        for pair in bounds.windows(2) {
+
+            let (lower, upper) = (pair[0], pair[1]);
+            let inside = |v: f64| lower.map_or(true, |(_, l)| v >= l) && upper.map_or(true, |(_, u)| v < u);
+
+            // boundary edges of the area, edges shared by two triangles cancel out
+            let mut edges = BTreeSet::new();
+
+            for triangle in grid.triangles.iter() {
+
+                let mut ring = Vec::new();
+                for e in 0..3 {
+                    let (a, b) = (triangle[e], triangle[(e + 1) % 3]);
+                    if inside(a.1) {
+                        ring.push(Vertex::Node(a.0));
+                    }
+                    let mut crossings = [lower, upper]
+                        .iter()
+                        .flatten()
+                        .filter(|(_, l)| (a.1 >= *l) != (b.1 >= *l))
+                        .map(|&(k, _)| Vertex::crossing(a.0, b.0, k))
+                        .collect::<Vec<_>>();
+                    if a.1 > b.1 {
+                        crossings.reverse();
+                    }
+                    ring.extend(crossings);
+                }
+
+                if ring.len() < 3 {
+                    continue;
+                }
+
+                for i in 0..ring.len() {
+                    let edge = (ring[i], ring[(i + 1) % ring.len()]);
+                    if !edges.remove(&(edge.1, edge.0)) {
+                        edges.insert(edge);
+                    }
+                }
+            }
+
+            if edges.is_empty() {
+                continue;
+            }
+
+            let rings = link_edges(&edges)
+                .iter()
+                .flat_map(|ring| split_ring(ring))
+                .map(|ring| grid.positions(&ring))
+                .filter(|ring| ring.len() > 3 && signed_area(ring) != 0.0)
+                .collect::<Vec<_>>();
+
+            if rings.is_empty() {
+                continue;
+            }
+
+            polygons.push(ContourPolygon {
+                min: lower.map_or(min_value, |(_, l)| l),
+                max: upper.map_or(max_value, |(_, u)| u),
+                srid: self.srid,
+                geometry: assemble_polygons(self, rings),
+            });
+        }
+
+        Ok(polygons)
+    }
+
+    /// Samples the band and splits every cell between four valid pixel centers into
+    /// four triangles around the cell center
+    fn contour_grid(&self, band: usize, levels: &ContourLevels) -> Result<ContourGrid, ContourError> {
+
+        let data = match &self.bands.get(band).ok_or(ContourError::BandIndexOutOfRange(band))?.data {
+            RasterDataSource::InMemory(data) => data,
+            RasterDataSource::Offline(_) => return Err(ContourError::BandIsOffline),
+        };
+
+        let mut values = HashMap::new();
+        for row in 0..data.height() {
+            for col in 0..data.width() {
+                if let Some(v) = data.get_value(col, row) {
+                    values.insert(Node::Pixel(col, row), v);
+                }
+            }
+        }
+
+        let (min_value, max_value) = values.values().fold(
+            (f64::INFINITY, f64::NEG_INFINITY),
+            |(min, max), &v| (min.min(v), max.max(v)),
+        );
+
+        let mut levels = match levels {
+            ContourLevels::Interval { interval, offset } => {
+                if !(interval.is_finite() && *interval > 0.0) {
+                    return Err(ContourError::InvalidInterval(*interval));
+                }
+                if !offset.is_finite() {
+                    return Err(ContourError::InvalidLevel(*offset));
+                }
+                if values.is_empty() {
+                    Vec::new()
+                } else {
+                    let first = ((min_value - offset) / interval).ceil();
+                    let last = ((max_value - offset) / interval).floor();
+                    if last - first >= ContourLevels::MAX_INTERVAL_LEVELS as f64 {
+                        return Err(ContourError::TooManyLevels { interval: *interval, min: min_value, max: max_value });
+                    }
+                    (first as i64..=last as i64).map(|k| offset + k as f64 * interval).collect()
+                }
+            },
+            ContourLevels::Fixed(levels) => {
+                if let Some(l) = levels.iter().find(|l| !l.is_finite()) {
+                    return Err(ContourError::InvalidLevel(*l));
+                }
+                levels.clone()
+            },
+        };
+        levels.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+        levels.dedup();
+
+        let mut triangles = Vec::new();
+        for row in 0..data.height().saturating_sub(1) {
+            for col in 0..data.width().saturating_sub(1) {
+
+                // clockwise in pixel space
+                let corners = [
+                    Node::Pixel(col, row),
+                    Node::Pixel(col + 1, row),
+                    Node::Pixel(col + 1, row + 1),
+                    Node::Pixel(col, row + 1),
+                ];
+
+                let corner_values = match corners.iter().map(|n| values.get(n).copied()).collect::<Option<Vec<_>>>() {
+                    Some(v) => v,
+                    None => continue,
+                };
+
+                let center = (Node::CellCenter(col, row), corner_values.iter().sum::<f64>() / 4.0);
+                values.insert(center.0, center.1);
+
+                for i in 0..4 {
+                    let j = (i + 1) % 4;
+                    triangles.push([(corners[i], corner_values[i]), (corners[j], corner_values[j]), center]);
+                }
+            }
+        }
+
+        Ok(ContourGrid { triangles, values, levels })
+    }
+}
+
+/// Chains directed segments into lines, open lines first, then closed rings
+fn link_segments(segments: &[(Vertex, Vertex)]) -> Vec<Vec<Vertex>> {
+
+    let next = segments.iter().enumerate().map(|(i, s)| (s.0, i)).collect::<HashMap<_, _>>();
+    let ends = segments.iter().map(|s| s.1).collect::<BTreeSet<_>>();
+
+    let open = (0..segments.len()).filter(|&i| !ends.contains(&segments[i].0));
+    let order = open.chain(0..segments.len()).collect::<Vec<_>>();
+
+    let mut used = vec![false; segments.len()];
+    let mut lines = Vec::new();
+
+    for start in order {
+
+        if used[start] {
+            continue;
+        }
+
+        let mut line = vec![segments[start].0];
+        let mut current = start;
+
+        loop {
+            used[current] = true;
+            line.push(segments[current].1);
+            match next.get(&segments[current].1) {
+                Some(&n) if !used[n] => current = n,
+                _ => break,
+            }
+        }
+
+        lines.push(line);
+    }
+
+    lines
+}
+
+/// Links directed boundary edges into closed rings (which may still touch themselves)
+fn link_edges(edges: &BTreeSet<(Vertex, Vertex)>) -> Vec<Vec<Vertex>> {
+
+    let mut outgoing = BTreeMap::<Vertex, Vec<Vertex>>::new();
+    for &(from, to) in edges.iter().rev() {
+        outgoing.entry(from).or_default().push(to);
+    }
+
+    let starts = outgoing.keys().copied().collect::<Vec<_>>();
+    let mut rings = Vec::new();
+
+    for start in starts {
+        while outgoing.get(&start).map_or(false, |to| !to.is_empty()) {
+            let mut ring = vec![start];
+            let mut current = start;
+            while let Some(to) = outgoing.get_mut(&current).and_then(|to| to.pop()) {
+                ring.push(to);
+                current = to;
+            }
+            rings.push(ring);
+        }
+    }
+
+    rings
+}
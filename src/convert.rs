@@ -0,0 +1,280 @@
+//! Conversion of bands between pixel types
+
+use std::fmt;
+use crate::{RasterBand, RasterDataSource, InMemoryRasterData, PixType};
+
+#[derive(Debug, Clone, PartialEq, PartialOrd)]
+pub enum ConvertError {
+    /// A value does not fit into the target type and `OutOfRange::Error` was requested
+    ValueOutOfRange { col: usize, row: usize, value: f64 },
+    /// The rescale source or target range is empty or not finite
+    InvalidRescaleRange { min: f64, max: f64 },
+    /// Band data is stored out-of-db, pixels can't be converted
+    BandIsOffline,
+}
+
+impl fmt::Display for ConvertError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConvertError::ValueOutOfRange { col, row, value } => write!(f, "value {} at pixel ({}, {}) doesn't fit into the target pixel type", value, col, row),
+            ConvertError::InvalidRescaleRange { min, max } => write!(f, "rescale range {}..{} is empty or not finite", min, max),
+            ConvertError::BandIsOffline => write!(f, "band is stored out-of-db and can't be converted"),
+        }
+    }
+}
+
+impl std::error::Error for ConvertError { }
+
+/// What to do with values that don't fit into the target pixel type
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum OutOfRange {
+    /// Saturate at the minimum / maximum value of the target type
+    Clamp,
+    /// Wrap around (modulo the value range of the target type), like an `as` cast
+    /// between integers does. For floating point targets, this is the same as `Clamp`.
+    Wrap,
+    /// Abort the conversion with `ConvertError::ValueOutOfRange`
+    Error,
+}
+
+/// How fractional values are converted to integer pixel types
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Rounding {
+    /// Round to the nearest integer, half-way cases away from zero
+    Nearest,
+    /// Round towards negative infinity
+    Floor,
+    /// Round towards positive infinity
+    Ceil,
+    /// Round towards zero
+    Truncate,
+}
+
+/// What pixels equal to the source nodata value are converted to
+#[derive(Debug, Copy, Clone, PartialEq, PartialOrd)]
+pub enum NodataMapping {
+    /// The nodata value of the target type, if it has none they are converted like any other value
+    Target,
+    /// The given value, written without rescaling (saturated to the target type)
+    Value(f64),
+    /// Converted like any other value
+    Convert,
+}
+
+/// Linear mapping of a source value range onto a target value range
+#[derive(Debug, Copy, Clone, PartialEq, PartialOrd)]
+pub struct Rescale {
+    /// Source range `(min, max)`, mapped onto `to`
+    pub from: (f64, f64),
+    /// Target range `(min, max)`, `None` for the full value range of the target type
+    pub to: Option<(f64, f64)>,
+}
+
+/// Options for `RasterBand::convert`
+#[derive(Debug, Copy, Clone, PartialEq, PartialOrd)]
+pub struct ConversionPolicy {
+    /// What to do with values outside of the target type range (default: `Clamp`)
+    pub out_of_range: OutOfRange,
+    /// Rounding mode for integer target types (default: `Nearest`)
+    pub rounding: Rounding,
+    /// Optional linear rescaling, applied before rounding (default: `None`)
+    pub rescale: Option<Rescale>,
+    /// Conversion of nodata pixels (default: `Target`)
+    pub nodata: NodataMapping,
+}
+
+impl Default for ConversionPolicy {
+    fn default() -> Self {
+        ConversionPolicy {
+            out_of_range: OutOfRange::Clamp,
+            rounding: Rounding::Nearest,
+            rescale: None,
+            nodata: NodataMapping::Target,
+        }
+    }
+}
+
+impl PixType {
+
+    /// Returns the `(min, max)` values that can be stored in this pixel type
+    pub fn value_range(&self) -> (f64, f64) {
+        use self::PixType::*;
+        match self {
+            Bool1Bit(_) => (0.0, 1.0),
+            UInt2(_) => (0.0, 3.0),
+            UInt4(_) => (0.0, 15.0),
+            Int8(_) => (i8::MIN as f64, i8::MAX as f64),
+            UInt8(_) => (0.0, u8::MAX as f64),
+            Int16(_) => (i16::MIN as f64, i16::MAX as f64),
+            UInt16(_) => (0.0, u16::MAX as f64),
+            Int32(_) => (i32::MIN as f64, i32::MAX as f64),
+            UInt32(_) => (0.0, u32::MAX as f64),
+            Float32(_) => (f32::MIN as f64, f32::MAX as f64),
+            Float64(_) => (f64::MIN, f64::MAX),
+        }
+    }
+}
+
+impl RasterBand {
+
+    /// Converts the band to the pixel type `target`. Pixels equal to the source nodata
+    /// value are converted as given by `policy.nodata`, by default to the nodata value of
+    /// `target`. `NaN` values are out of range for integer types, infinite values are kept
+    /// as they are by floating point types.
+    ///
+    /// ```rust
+    /// use wkb_raster::{RasterBand, RasterDataSource, InMemoryRasterData, PixType};
+    /// use wkb_raster::{ConversionPolicy, ConvertError, OutOfRange, Rescale, NodataMapping};
+    ///
+    /// let band = RasterBand {
+    ///     is_nodata_value: false,
+    ///     data: RasterDataSource::InMemory(InMemoryRasterData::Float64 {
+    ///         data: vec![vec![-1.0, 0.4, 0.6, 300.0, -9999.0]],
+    ///         nodata: Some(-9999.0),
+    ///     }),
+    /// };
+    ///
+    /// let clamped = band.convert(PixType::UInt8(Some(0)), ConversionPolicy::default()).unwrap();
+    /// assert_eq!(clamped.data, RasterDataSource::InMemory(InMemoryRasterData::UInt8 {
+    ///     data: vec![vec![0, 0, 1, 255, 0]],
+    ///     nodata: Some(0),
+    /// }));
+    ///
+    /// let policy = ConversionPolicy { out_of_range: OutOfRange::Error, .. Default::default() };
+    /// assert!(band.convert(PixType::UInt4(None), policy).is_err());
+    ///
+    /// let policy = ConversionPolicy {
+    ///     rescale: Some(Rescale { from: (-1.0, 300.0), to: Some((0.0, 3.0)) }),
+    ///     .. Default::default()
+    /// };
+    /// let rescaled = band.convert(PixType::UInt2(Some(0)), policy).unwrap();
+    /// assert_eq!(rescaled.data, RasterDataSource::InMemory(InMemoryRasterData::UInt2 {
+    ///     data: vec![vec![0, 0, 0, 3, 0]],
+    ///     nodata: Some(0),
+    /// }));
+    ///
+    /// // keep the nodata pixels distinguishable from clamped values
+    /// let policy = ConversionPolicy { nodata: NodataMapping::Value(255.0), .. Default::default() };
+    /// let remapped = band.convert(PixType::UInt8(Some(255)), policy).unwrap();
+    /// assert_eq!(remapped.data, RasterDataSource::InMemory(InMemoryRasterData::UInt8 {
+    ///     data: vec![vec![0, 0, 1, 255, 255]],
+    ///     nodata: Some(255),
+    /// }));
+    ///
+    /// let infinite = RasterBand {
+    ///     is_nodata_value: false,
+    ///     data: RasterDataSource::InMemory(InMemoryRasterData::Float64 {
+    ///         data: vec![vec![f64::INFINITY, 1e300]],
+    ///         nodata: None,
+    ///     }),
+    /// };
+    /// let policy = ConversionPolicy { out_of_range: OutOfRange::Error, .. Default::default() };
+    /// assert_eq!(infinite.convert(PixType::Float32(None), policy), Err(ConvertError::ValueOutOfRange { col: 1, row: 0, value: 1e300 }));
+    /// let float = infinite.convert(PixType::Float32(None), ConversionPolicy::default()).unwrap();
+    /// assert_eq!(float.data, RasterDataSource::InMemory(InMemoryRasterData::Float32 {
+    ///     data: vec![vec![f32::INFINITY, f32::MAX]],
+    ///     nodata: None,
+    /// }));
+    /// ```
+    pub fn convert(&self, target: PixType, policy: ConversionPolicy) -> Result<RasterBand, ConvertError> {
+
+        let data = match &self.data {
+            RasterDataSource::InMemory(data) => data,
+            RasterDataSource::Offline(_) => return Err(ConvertError::BandIsOffline),
+        };
+
+        let (type_min, type_max) = target.value_range();
+        let is_float = target.is_floating_point();
+        let target_nodata = target.nodata_f64();
+        let nodata_value = match policy.nodata {
+            NodataMapping::Target => target_nodata,
+            NodataMapping::Value(value) => Some(value),
+            NodataMapping::Convert => None,
+        };
+
+        let rescale = match policy.rescale {
+            Some(Rescale { from, to }) => {
+                let to = to.unwrap_or((type_min, type_max));
+                for &(min, max) in &[from, to] {
+                    if !min.is_finite() || !max.is_finite() || min == max {
+                        return Err(ConvertError::InvalidRescaleRange { min, max });
+                    }
+                }
+                Some((from, to))
+            },
+            None => None,
+        };
+
+        let mut error = None;
+
+        let new_data = InMemoryRasterData::from_fn_f64(target, data.width(), data.height(), |col, row| {
+
+            let value = data.get_f64(col, row).unwrap_or_default();
+
+            if let Some(nodata) = nodata_value {
+                if data.get_value(col, row).is_none() {
+                    return nodata;
+                }
+            }
+
+            let value = match rescale {
+                Some(((from_min, from_max), (to_min, to_max))) => {
+                    to_min + (value - from_min) * (to_max - to_min) / (from_max - from_min)
+                },
+                None => value,
+            };
+
+            if value.is_nan() {
+                if is_float {
+                    return value;
+                }
+                if policy.out_of_range == OutOfRange::Error && error.is_none() {
+                    error = Some(ConvertError::ValueOutOfRange { col, row, value });
+                }
+                return target_nodata.unwrap_or(0.0);
+            }
+
+            // infinities are valid floating point values
+            if is_float && value.is_infinite() {
+                return value;
+            }
+
+            let value = if is_float {
+                value
+            } else {
+                match policy.rounding {
+                    Rounding::Nearest => value.round(),
+                    Rounding::Floor => value.floor(),
+                    Rounding::Ceil => value.ceil(),
+                    Rounding::Truncate => value.trunc(),
+                }
+            };
+
+            if value >= type_min && value <= type_max {
+                return value;
+            }
+
+            match policy.out_of_range {
+                OutOfRange::Wrap if !is_float && value.is_finite() => {
+                    let span = type_max - type_min + 1.0;
+                    type_min + (value - type_min).rem_euclid(span)
+                },
+                OutOfRange::Clamp | OutOfRange::Wrap => value.max(type_min).min(type_max),
+                OutOfRange::Error => {
+                    if error.is_none() {
+                        error = Some(ConvertError::ValueOutOfRange { col, row, value });
+                    }
+                    0.0
+                },
+            }
+        });
+
+        match error {
+            Some(e) => Err(e),
+            None => Ok(RasterBand {
+                is_nodata_value: self.is_nodata_value,
+                data: RasterDataSource::InMemory(new_data),
+            }),
+        }
+    }
+}
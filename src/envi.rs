@@ -0,0 +1,537 @@
+//! ENVI raw rasters (band sequential, band interleaved by line or by pixel) with `.hdr` headers
+
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::fmt;
+use crate::{Raster, RasterBand, RasterDataSource, InMemoryRasterData, PixType, Endian};
+use crate::mmap::RawLayout;
+
+const DATA_TYPE_BYTE: u32 = 1;
+const DATA_TYPE_INT16: u32 = 2;
+const DATA_TYPE_INT32: u32 = 3;
+const DATA_TYPE_FLOAT32: u32 = 4;
+const DATA_TYPE_FLOAT64: u32 = 5;
+const DATA_TYPE_UINT16: u32 = 12;
+const DATA_TYPE_UINT32: u32 = 13;
+
+/// `scale_x`, `scale_y`, `ip_x`, `ip_y`, `skew_x`, `skew_y`
+type GeoTransform = (f64, f64, f64, f64, f64, f64);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EnviError {
+    /// The header doesn't start with `ENVI`
+    InvalidHeader,
+    /// The header doesn't contain the given key
+    MissingHeader(&'static str),
+    /// The value of the given header key is invalid
+    InvalidHeaderValue(String),
+    /// Only the data types 1 (byte), 2, 3, 12 and 13 (integers) and 4 and 5 (floats) are supported
+    UnsupportedDataType(u32),
+    /// The data is shorter than the size given by the header
+    Truncated,
+    /// The raster is larger than 65535 pixels in one direction
+    TooLarge { width: usize, height: usize },
+    /// The header has more than 65535 bands
+    TooManyBands(usize),
+    /// Rasters without bands can't be encoded
+    NoBands,
+    /// Band at the given index is stored out-of-db
+    BandIsOffline(usize),
+    /// All bands have to have the same pixel type
+    MixedPixTypes,
+    /// All bands have to have the same nodata value (`data ignore value` applies to all bands)
+    MixedNodataValues,
+    /// The geotransform isn't a (rotated) grid with perpendicular pixel edges
+    UnsupportedGeoTransform,
+}
+
+impl fmt::Display for EnviError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EnviError::InvalidHeader => write!(f, "header is not an ENVI header (missing ENVI signature)"),
+            EnviError::MissingHeader(key) => write!(f, "ENVI header has no {}", key),
+            EnviError::InvalidHeaderValue(key) => write!(f, "ENVI header {} has an invalid value", key),
+            EnviError::UnsupportedDataType(t) => write!(f, "ENVI data type {} is not supported", t),
+            EnviError::Truncated => write!(f, "ENVI data is shorter than described by the header"),
+            EnviError::TooLarge { width, height } => write!(f, "ENVI image of {}x{} pixels is too large for a raster (at most 65535x65535)", width, height),
+            EnviError::TooManyBands(bands) => write!(f, "ENVI image has {} bands, a raster has at most 65535", bands),
+            EnviError::NoBands => write!(f, "ENVI requires at least one band"),
+            EnviError::BandIsOffline(band) => write!(f, "band {} is stored out-of-db and can't be encoded as ENVI", band),
+            EnviError::MixedPixTypes => write!(f, "ENVI requires all bands to have the same pixel type"),
+            EnviError::MixedNodataValues => write!(f, "ENVI requires all bands to have the same nodata value"),
+            EnviError::UnsupportedGeoTransform => write!(f, "ENVI map info can't describe the geotransform (only rotated grids)"),
+        }
+    }
+}
+
+impl std::error::Error for EnviError { }
+
+/// Arrangement of the bands in the data file
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Interleave {
+    /// Band sequential: all rows of the first band, then all rows of the second band, ...
+    Bsq,
+    /// Band interleaved by line: the first row of every band, then the second row of every band, ...
+    Bil,
+    /// Band interleaved by pixel: all bands of the first pixel, then all bands of the second pixel, ...
+    Bip,
+}
+
+impl Interleave {
+
+    /// Index of a sample in the data file
+    fn index(&self, band: usize, col: usize, row: usize, width: usize, height: usize, bands: usize) -> usize {
+        match self {
+            Interleave::Bsq => (band * height + row) * width + col,
+            Interleave::Bil => (row * bands + band) * width + col,
+            Interleave::Bip => (row * width + col) * bands + band,
+        }
+    }
+
+    /// Value of the `interleave` header key, also used as file extension
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Interleave::Bsq => "bsq",
+            Interleave::Bil => "bil",
+            Interleave::Bip => "bip",
+        }
+    }
+}
+
+/// Parses `key = value` lines, values in braces can span several lines; keys are lowercased
+fn parse_header(header: &str) -> Result<BTreeMap<String, String>, EnviError> {
+
+    let mut lines = header.lines();
+    if lines.next().map(|l| l.trim()) != Some("ENVI") {
+        return Err(EnviError::InvalidHeader);
+    }
+
+    let mut fields = BTreeMap::new();
+    while let Some(line) = lines.next() {
+        let (key, value) = match line.split_once('=') {
+            Some(kv) => kv,
+            None => continue,
+        };
+        let mut value = value.trim().to_string();
+        if value.starts_with('{') {
+            while !value.ends_with('}') {
+                match lines.next() {
+                    Some(next) => {
+                        value.push(' ');
+                        value.push_str(next.trim());
+                    },
+                    None => return Err(EnviError::InvalidHeaderValue(key.trim().to_string())),
+                }
+            }
+            value = value[1..value.len() - 1].trim().to_string();
+        }
+        let key = key.split_whitespace().collect::<Vec<_>>().join(" ").to_ascii_lowercase();
+        fields.insert(key, value);
+    }
+
+    Ok(fields)
+}
+
+/// EPSG code of the outermost `AUTHORITY` of a WKT coordinate system string
+fn wkt_epsg(wkt: &str) -> Option<i32> {
+    let start = wkt.rfind("AUTHORITY[\"EPSG\"")? + "AUTHORITY[\"EPSG\"".len();
+    wkt[start..].trim_start_matches([',', ' ', '"']).split('"').next()?.parse().ok()
+}
+
+/// Size, sample layout and georeference described by an ENVI header
+struct Image {
+    width: usize,
+    height: usize,
+    bands: usize,
+    /// Byte offset of the first sample in the data file
+    offset: usize,
+    /// Pixel type of the samples, with the `data ignore value`
+    pixtype: PixType,
+    /// Bytes per sample
+    size: usize,
+    interleave: Interleave,
+    endian: Endian,
+    /// Raster without bands with the geotransform and SRID of the header
+    grid: Raster,
+}
+
+impl Image {
+
+    /// Size of the samples of all bands in bytes, `None` if it doesn't fit into a `usize`
+    fn data_len(&self) -> Option<usize> {
+        self.width.checked_mul(self.height)?.checked_mul(self.bands)?.checked_mul(self.size)
+    }
+}
+
+/// Parses the header keys needed to read the data file
+fn read_image(header: &str) -> Result<Image, EnviError> {
+
+    let fields = parse_header(header)?;
+
+    let number = |key: &'static str| -> Result<Option<usize>, EnviError> {
+        fields.get(key)
+            .map(|v| v.parse::<usize>().map_err(|_| EnviError::InvalidHeaderValue(key.to_string())))
+            .transpose()
+    };
+
+    let width = number("samples")?.ok_or(EnviError::MissingHeader("samples"))?;
+    let height = number("lines")?.ok_or(EnviError::MissingHeader("lines"))?;
+    let bands = number("bands")?.ok_or(EnviError::MissingHeader("bands"))?;
+    let offset = number("header offset")?.unwrap_or(0);
+    if width > u16::MAX as usize || height > u16::MAX as usize {
+        return Err(EnviError::TooLarge { width, height });
+    }
+    if bands > u16::MAX as usize {
+        return Err(EnviError::TooManyBands(bands));
+    }
+
+    let data_type = number("data type")?.ok_or(EnviError::MissingHeader("data type"))?;
+    let data_type = u32::try_from(data_type).map_err(|_| EnviError::InvalidHeaderValue("data type".to_string()))?;
+    let (pixtype, size) = match data_type {
+        DATA_TYPE_BYTE => (PixType::UInt8(None), 1),
+        DATA_TYPE_INT16 => (PixType::Int16(None), 2),
+        DATA_TYPE_UINT16 => (PixType::UInt16(None), 2),
+        DATA_TYPE_INT32 => (PixType::Int32(None), 4),
+        DATA_TYPE_UINT32 => (PixType::UInt32(None), 4),
+        DATA_TYPE_FLOAT32 => (PixType::Float32(None), 4),
+        DATA_TYPE_FLOAT64 => (PixType::Float64(None), 8),
+        other => return Err(EnviError::UnsupportedDataType(other)),
+    };
+
+    let interleave = match fields.get("interleave").map(|v| v.to_ascii_lowercase()).as_deref() {
+        None | Some("bsq") => Interleave::Bsq,
+        Some("bil") => Interleave::Bil,
+        Some("bip") => Interleave::Bip,
+        Some(_) => return Err(EnviError::InvalidHeaderValue("interleave".to_string())),
+    };
+
+    let endian = match number("byte order")? {
+        None | Some(0) => Endian::Little,
+        Some(1) => Endian::Big,
+        Some(_) => return Err(EnviError::InvalidHeaderValue("byte order".to_string())),
+    };
+
+    let nodata = fields.get("data ignore value")
+        .map(|v| v.parse::<f64>().map_err(|_| EnviError::InvalidHeaderValue("data ignore value".to_string())))
+        .transpose()?;
+
+    let (map_geotransform, map_srid) = match fields.get("map info") {
+        Some(map_info) => parse_map_info(map_info)?,
+        None => ((1.0, 1.0, 0.0, 0.0, 0.0, 0.0), 0),
+    };
+    let (scale_x, scale_y, ip_x, ip_y, skew_x, skew_y) = map_geotransform;
+    let srid = fields.get("coordinate system string").and_then(|wkt| wkt_epsg(wkt)).unwrap_or(map_srid);
+
+    Ok(Image {
+        width,
+        height,
+        bands,
+        offset,
+        pixtype: pixtype.with_nodata_f64(nodata),
+        size,
+        interleave,
+        endian,
+        grid: Raster {
+            endian,
+            version: 0,
+            scale_x,
+            scale_y,
+            ip_x,
+            ip_y,
+            skew_x,
+            skew_y,
+            srid,
+            width: width as u16,
+            height: height as u16,
+            bands: Vec::new(),
+        },
+    })
+}
+
+/// Sample locations of all bands in a data file of `len` bytes
+pub(crate) fn raw_layouts(header: &str, len: usize) -> Result<Vec<RawLayout>, EnviError> {
+
+    let image = read_image(header)?;
+    let data_len = image.data_len().ok_or(EnviError::Truncated)?;
+    let Image { width, height, bands, offset, pixtype, size, interleave, endian, grid } = image;
+    if len < offset || len - offset < data_len {
+        return Err(EnviError::Truncated);
+    }
+
+    let (pixel_stride, row_stride) = match interleave {
+        Interleave::Bsq => (size, width * size),
+        Interleave::Bil => (size, bands * width * size),
+        Interleave::Bip => (bands * size, bands * width * size),
+    };
+
+    // the whole band is a single chunk
+    Ok((0..bands).map(|band| RawLayout {
+        grid: grid.clone_without_bands(),
+        pixtype,
+        big_endian: endian == Endian::Big,
+        chunk_offsets: vec![Some((offset + interleave.index(band, 0, 0, width, height, bands) * size) as u64)],
+        chunk_width: width,
+        chunk_height: height,
+        chunks_across: 1,
+        pixel_stride,
+        row_stride,
+    }).collect())
+}
+
+impl Raster {
+
+    /// Reads an ENVI raster from its header (the contents of the `.hdr` file) and the raw data
+    /// file, with one band per ENVI band and the `endian` of the `byte order`.
+    ///
+    /// The geotransform is taken from `map info` (including `rotation`, counterclockwise in
+    /// degrees), the SRID from the EPSG authority of the `coordinate system string` or from
+    /// `UTM` / `Geographic Lat/Lon` map infos with the `WGS-84` datum (0 otherwise), and the
+    /// nodata value of all bands from `data ignore value`.
+    ///
+    /// ```rust
+    /// use wkb_raster::{Raster, RasterDataSource, InMemoryRasterData, Endian, EnviError};
+    ///
+    /// let header = "ENVI
+    /// samples = 2
+    /// lines = 1
+    /// bands = 2
+    /// data type = 2
+    /// interleave = bip
+    /// byte order = 1
+    /// map info = {UTM, 1.0, 1.0, 440720.0, 3751320.0, 30.0, 30.0, 11, North, WGS-84, units=Meters}
+    /// ";
+    /// let data = [0, 1, 0, 2, 0, 3, 0, 4];
+    ///
+    /// let raster = Raster::from_envi(header, &data).unwrap();
+    /// assert_eq!(raster.endian, Endian::Big);
+    /// assert_eq!((raster.ip_x, raster.ip_y, raster.scale_x, raster.scale_y), (440720.0, 3751320.0, 30.0, -30.0));
+    /// assert_eq!(raster.srid, 32611);
+    /// assert_eq!(raster.bands[1].data, RasterDataSource::InMemory(InMemoryRasterData::Int16 {
+    ///     data: vec![vec![2, 4]],
+    ///     nodata: None,
+    /// }));
+    ///
+    /// // header sizes are checked before reading the data
+    /// let huge = header.replace("bands = 2", "bands = 100000");
+    /// assert_eq!(Raster::from_envi(&huge, &data), Err(EnviError::TooManyBands(100000)));
+    /// let truncated = header.replace("bands = 2", "bands = 3");
+    /// assert_eq!(Raster::from_envi(&truncated, &data), Err(EnviError::Truncated));
+    /// ```
+    pub fn from_envi(header: &str, data: &[u8]) -> Result<Raster, EnviError> {
+
+        let image = read_image(header)?;
+        let data_len = image.data_len().ok_or(EnviError::Truncated)?;
+        let Image { width, height, bands, offset, pixtype, size, interleave, endian, grid } = image;
+
+        let data = data.get(offset..).ok_or(EnviError::Truncated)?;
+        if data.len() < data_len {
+            return Err(EnviError::Truncated);
+        }
+
+        let sample = |band: usize, col: usize, row: usize| -> f64 {
+            let i = interleave.index(band, col, row, width, height, bands) * size;
+            let mut bytes = [0; 8];
+            bytes[..size].copy_from_slice(&data[i..i + size]);
+            if endian == Endian::Big {
+                bytes[..size].reverse();
+            }
+            match pixtype {
+                PixType::Int16(_) => i16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+                PixType::UInt16(_) => u16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+                PixType::Int32(_) => i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64,
+                PixType::UInt32(_) => u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64,
+                PixType::Float32(_) => f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64,
+                PixType::Float64(_) => f64::from_le_bytes(bytes),
+                _ => bytes[0] as f64,
+            }
+        };
+
+        Ok(Raster {
+            bands: (0..bands).map(|band| RasterBand {
+                is_nodata_value: false,
+                data: RasterDataSource::InMemory(InMemoryRasterData::from_fn_f64(pixtype, width, height, |col, row| {
+                    sample(band, col, row)
+                })),
+            }).collect(),
+            .. grid
+        })
+    }
+
+    /// Writes all bands as ENVI raster, returning the header (the contents of the `.hdr` file)
+    /// and the data file with the given interleave and the byte order of `self.endian`.
+    ///
+    /// Band types without ENVI data type are widened: `Bool1Bit`, `UInt2` and `UInt4` are
+    /// written as byte and `Int8` as 16-bit integer. `map info` is written as `UTM` with
+    /// `WGS-84` datum for SRIDs 32601 to 32660 and 32701 to 32760, as `Geographic Lat/Lon`
+    /// for SRID 4326 and as `Arbitrary` otherwise.
+    ///
+    /// ```rust
+    /// use wkb_raster::{Raster, RasterBand, RasterDataSource, InMemoryRasterData, Endian, Interleave};
+    ///
+    /// let band = |data: Vec<Vec<f32>>| RasterBand {
+    ///     is_nodata_value: false,
+    ///     data: RasterDataSource::InMemory(InMemoryRasterData::Float32 { data, nodata: Some(-1.0) }),
+    /// };
+    /// let raster = Raster {
+    ///     endian: Endian::Little, version: 0,
+    ///     scale_x: 0.5, scale_y: -0.5, ip_x: 8.0, ip_y: 47.0, skew_x: 0.0, skew_y: 0.0,
+    ///     srid: 4326, width: 2, height: 2,
+    ///     bands: vec![band(vec![vec![1.0, 2.0], vec![3.0, -1.0]]), band(vec![vec![0.5, 0.25], vec![-1.0, 8.0]])],
+    /// };
+    ///
+    /// let (header, data) = raster.to_envi(Interleave::Bil).unwrap();
+    /// assert!(header.contains("map info = {Geographic Lat/Lon, 1, 1, 8, 47, 0.5, 0.5, WGS-84, units=Degrees}"));
+    /// assert_eq!(data.len(), 2 * 2 * 2 * 4);
+    ///
+    /// assert_eq!(Raster::from_envi(&header, &data).unwrap(), raster);
+    /// ```
+    pub fn to_envi(&self, interleave: Interleave) -> Result<(String, Vec<u8>), EnviError> {
+
+        let bands = self.bands.iter().enumerate().map(|(i, band)| match &band.data {
+            RasterDataSource::InMemory(data) => Ok(data),
+            RasterDataSource::Offline(_) => Err(EnviError::BandIsOffline(i)),
+        }).collect::<Result<Vec<_>, _>>()?;
+
+        let first = bands.first().ok_or(EnviError::NoBands)?;
+        let pixtype = first.get_pixtype();
+        if bands.iter().any(|b| b.get_pixtype().with_nodata_f64(None) != pixtype.with_nodata_f64(None)) {
+            return Err(EnviError::MixedPixTypes);
+        }
+        let nodata = pixtype.nodata_f64();
+        let same_nodata = |other: Option<f64>| match (nodata, other) {
+            (Some(a), Some(b)) => a == b || (a.is_nan() && b.is_nan()),
+            (a, b) => a.is_none() && b.is_none(),
+        };
+        if !bands.iter().all(|b| same_nodata(b.get_pixtype().nodata_f64())) {
+            return Err(EnviError::MixedNodataValues);
+        }
+
+        let map_info = map_info(self)?;
+
+        let (data_type, size) = match pixtype {
+            PixType::Bool1Bit(_) | PixType::UInt2(_) | PixType::UInt4(_) | PixType::UInt8(_) => (DATA_TYPE_BYTE, 1),
+            PixType::Int8(_) | PixType::Int16(_) => (DATA_TYPE_INT16, 2),
+            PixType::UInt16(_) => (DATA_TYPE_UINT16, 2),
+            PixType::Int32(_) => (DATA_TYPE_INT32, 4),
+            PixType::UInt32(_) => (DATA_TYPE_UINT32, 4),
+            PixType::Float32(_) => (DATA_TYPE_FLOAT32, 4),
+            PixType::Float64(_) => (DATA_TYPE_FLOAT64, 8),
+        };
+
+        let (width, height, count) = (first.width(), first.height(), bands.len());
+        let mut data = vec![0; width * height * count * size];
+        for (b, band) in bands.iter().enumerate() {
+            for row in 0..height {
+                for col in 0..width {
+                    let value = band.get_f64(col, row).unwrap_or(0.0);
+                    let mut bytes = match data_type {
+                        DATA_TYPE_INT16 => (value as i16).to_le_bytes().to_vec(),
+                        DATA_TYPE_UINT16 => (value as u16).to_le_bytes().to_vec(),
+                        DATA_TYPE_INT32 => (value as i32).to_le_bytes().to_vec(),
+                        DATA_TYPE_UINT32 => (value as u32).to_le_bytes().to_vec(),
+                        DATA_TYPE_FLOAT32 => (value as f32).to_le_bytes().to_vec(),
+                        DATA_TYPE_FLOAT64 => value.to_le_bytes().to_vec(),
+                        _ => vec![value as u8],
+                    };
+                    if self.endian == Endian::Big {
+                        bytes.reverse();
+                    }
+                    let i = interleave.index(b, col, row, width, height, count) * size;
+                    data[i..i + size].copy_from_slice(&bytes);
+                }
+            }
+        }
+
+        let mut header = String::from("ENVI\n");
+        header.push_str(&format!("samples = {}\n", width));
+        header.push_str(&format!("lines = {}\n", height));
+        header.push_str(&format!("bands = {}\n", count));
+        header.push_str("header offset = 0\n");
+        header.push_str("file type = ENVI Standard\n");
+        header.push_str(&format!("data type = {}\n", data_type));
+        header.push_str(&format!("interleave = {}\n", interleave.name()));
+        header.push_str(&format!("byte order = {}\n", if self.endian == Endian::Big { 1 } else { 0 }));
+        header.push_str(&format!("map info = {{{}}}\n", map_info));
+        if let Some(nodata) = nodata {
+            header.push_str(&format!("data ignore value = {}\n", if nodata.is_nan() { "NaN".to_string() } else { format!("{:?}", nodata) }));
+        }
+
+        Ok((header, data))
+    }
+}
+
+/// Geotransform and SRID of a `map info`:
+/// projection name, reference pixel (1-based, x and y), its easting and northing, pixel size
+/// (x and y), projection parameters and `key=value` options like `rotation`
+fn parse_map_info(map_info: &str) -> Result<(GeoTransform, i32), EnviError> {
+
+    let invalid = || EnviError::InvalidHeaderValue("map info".to_string());
+
+    let items = map_info.split(',').map(|s| s.trim()).collect::<Vec<_>>();
+    let (values, options): (Vec<&str>, Vec<&str>) = items.iter().partition(|s| !s.contains('='));
+    if values.len() < 7 {
+        return Err(invalid());
+    }
+    let number = |i: usize| values[i].parse::<f64>().map_err(|_| invalid());
+    let (ref_x, ref_y, easting, northing, size_x, size_y) = (number(1)?, number(2)?, number(3)?, number(4)?, number(5)?, number(6)?);
+
+    let rotation = options.iter()
+        .filter_map(|o| o.split_once('='))
+        .find(|(k, _)| k.trim().eq_ignore_ascii_case("rotation"))
+        .map(|(_, v)| v.trim().parse::<f64>().map_err(|_| invalid()))
+        .transpose()?
+        .unwrap_or(0.0);
+
+    // column axis rotated counterclockwise from east, row axis from south
+    let (sin, cos) = rotation.to_radians().sin_cos();
+    let (scale_x, skew_y) = (size_x * cos, size_x * sin);
+    let (skew_x, scale_y) = (size_y * sin, -size_y * cos);
+    let ip_x = easting - (ref_x - 1.0) * scale_x - (ref_y - 1.0) * skew_x;
+    let ip_y = northing - (ref_x - 1.0) * skew_y - (ref_y - 1.0) * scale_y;
+
+    let projection = values[0].to_ascii_lowercase();
+    let wgs84 = |i: usize| values.get(i).map_or(false, |d| d.eq_ignore_ascii_case("WGS-84"));
+    let srid = match projection.as_str() {
+        "utm" if wgs84(9) => {
+            let zone = values.get(7).and_then(|z| z.parse::<i32>().ok()).filter(|z| (1..=60).contains(z));
+            let north = values.get(8).map(|h| h.eq_ignore_ascii_case("north"));
+            match (zone, north) {
+                (Some(zone), Some(true)) => 32600 + zone,
+                (Some(zone), Some(false)) => 32700 + zone,
+                _ => 0,
+            }
+        },
+        "geographic lat/lon" if wgs84(7) => 4326,
+        _ => 0,
+    };
+
+    Ok(((scale_x, scale_y, ip_x, ip_y, skew_x, skew_y), srid))
+}
+
+/// `map info` of the raster with the upper left corner as reference pixel
+fn map_info(raster: &Raster) -> Result<String, EnviError> {
+
+    let size_x = raster.scale_x.hypot(raster.skew_y);
+    let size_y = raster.skew_x.hypot(raster.scale_y);
+    let rotation = raster.skew_y.atan2(raster.scale_x);
+
+    // the row axis has to be the column axis turned clockwise by 90°
+    let (sin, cos) = rotation.sin_cos();
+    let tolerance = 1e-9 * size_y.max(f64::MIN_POSITIVE);
+    if (raster.skew_x - size_y * sin).abs() > tolerance || (raster.scale_y + size_y * cos).abs() > tolerance {
+        return Err(EnviError::UnsupportedGeoTransform);
+    }
+
+    let reference = format!("1, 1, {}, {}, {}, {}", raster.ip_x, raster.ip_y, size_x, size_y);
+    let mut map_info = match raster.srid {
+        4326 => format!("Geographic Lat/Lon, {}, WGS-84, units=Degrees", reference),
+        srid @ 32601..=32660 => format!("UTM, {}, {}, North, WGS-84, units=Meters", reference, srid - 32600),
+        srid @ 32701..=32760 => format!("UTM, {}, {}, South, WGS-84, units=Meters", reference, srid - 32700),
+        _ => format!("Arbitrary, {}", reference),
+    };
+    if rotation != 0.0 {
+        map_info.push_str(&format!(", rotation={}", rotation.to_degrees()));
+    }
+
+    Ok(map_info)
+}
@@ -0,0 +1,418 @@
+//! Minimal vector geometry type with WKB / EWKB / WKT encoding, used to exchange
+//! geometries with the vector operations (clip, polygonize, rasterize, contours)
+
+use std::fmt::Write;
+use std::fmt;
+use crate::Endian;
+
+#[derive(Debug, Clone, PartialEq, PartialOrd, Hash, Eq, Ord)]
+pub enum GeometryError {
+    /// Input ended before the geometry was fully parsed, contains the offset
+    UnexpectedEnd(usize),
+    /// The byte order marker is neither 0 nor 1
+    InvalidByteOrder(u8),
+    /// The WKB geometry type is not supported
+    UnsupportedGeometryType(u32),
+    /// The hex string has an odd length or contains a non-hex character
+    InvalidHexString,
+    /// Geometry collections are nested deeper than `Geometry::MAX_NESTING_DEPTH`, contains the offset
+    NestingTooDeep(usize),
+}
+
+impl fmt::Display for GeometryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GeometryError::UnexpectedEnd(offset) => write!(f, "geometry WKB ended unexpectedly at offset {}", offset),
+            GeometryError::InvalidByteOrder(b) => write!(f, "invalid WKB byte order marker {}", b),
+            GeometryError::UnsupportedGeometryType(t) => write!(f, "WKB geometry type {} is not supported", t),
+            GeometryError::InvalidHexString => write!(f, "geometry hex string has an odd length or contains a non-hex character"),
+            GeometryError::NestingTooDeep(offset) => write!(f, "geometry collections nested too deeply at offset {}", offset),
+        }
+    }
+}
+
+impl std::error::Error for GeometryError { }
+
+/// X / Y coordinate
+pub type Coord = (f64, f64);
+
+/// Vector geometry (2D, Z and M values are dropped when parsing)
+#[derive(Debug, Clone, PartialEq, PartialOrd)]
+pub enum Geometry {
+    Point(Coord),
+    LineString(Vec<Coord>),
+    /// Exterior ring followed by the interior rings (holes)
+    Polygon(Vec<Vec<Coord>>),
+    MultiPoint(Vec<Coord>),
+    MultiLineString(Vec<Vec<Coord>>),
+    MultiPolygon(Vec<Vec<Vec<Coord>>>),
+    GeometryCollection(Vec<Geometry>),
+}
+
+const EWKB_Z_FLAG: u32 = 0x8000_0000;
+const EWKB_M_FLAG: u32 = 0x4000_0000;
+const EWKB_SRID_FLAG: u32 = 0x2000_0000;
+
+struct WkbReader<'a> {
+    input: &'a [u8],
+    pos: usize,
+    depth: usize,
+}
+
+impl<'a> WkbReader<'a> {
+
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], GeometryError> {
+        let bytes = self.input
+            .get(self.pos..self.pos + N)
+            .ok_or(GeometryError::UnexpectedEnd(self.pos))?;
+        self.pos += N;
+        let mut out = [0; N];
+        out.copy_from_slice(bytes);
+        Ok(out)
+    }
+
+    fn u8(&mut self) -> Result<u8, GeometryError> {
+        Ok(self.take::<1>()?[0])
+    }
+
+    fn u32(&mut self, endian: Endian) -> Result<u32, GeometryError> {
+        let b = self.take::<4>()?;
+        Ok(match endian {
+            Endian::Big => u32::from_be_bytes(b),
+            Endian::Little => u32::from_le_bytes(b),
+        })
+    }
+
+    fn f64(&mut self, endian: Endian) -> Result<f64, GeometryError> {
+        let b = self.take::<8>()?;
+        Ok(match endian {
+            Endian::Big => f64::from_be_bytes(b),
+            Endian::Little => f64::from_le_bytes(b),
+        })
+    }
+
+    fn geometry(&mut self) -> Result<Geometry, GeometryError> {
+
+        let endian = match self.u8()? {
+            0 => Endian::Big,
+            1 => Endian::Little,
+            other => return Err(GeometryError::InvalidByteOrder(other)),
+        };
+
+        let raw_type = self.u32(endian)?;
+        let mut dims = 2;
+        if raw_type & EWKB_Z_FLAG != 0 { dims += 1; }
+        if raw_type & EWKB_M_FLAG != 0 { dims += 1; }
+        if raw_type & EWKB_SRID_FLAG != 0 {
+            let _srid = self.u32(endian)?;
+        }
+
+        // ISO WKB: 1000 = Z, 2000 = M, 3000 = ZM
+        let iso_type = raw_type & 0x0FFF_FFFF;
+        let (geometry_type, iso_dims) = (iso_type % 1000, match iso_type / 1000 {
+            0 => 0,
+            1 | 2 => 1,
+            3 => 2,
+            _ => return Err(GeometryError::UnsupportedGeometryType(raw_type)),
+        });
+        dims += iso_dims;
+
+        Ok(match geometry_type {
+            1 => Geometry::Point(self.coord(endian, dims)?),
+            2 => Geometry::LineString(self.coords(endian, dims)?),
+            3 => Geometry::Polygon(self.rings(endian, dims)?),
+            4 => Geometry::MultiPoint(self.children(endian, |g| match g {
+                Geometry::Point(p) => Some(p),
+                _ => None,
+            })?),
+            5 => Geometry::MultiLineString(self.children(endian, |g| match g {
+                Geometry::LineString(l) => Some(l),
+                _ => None,
+            })?),
+            6 => Geometry::MultiPolygon(self.children(endian, |g| match g {
+                Geometry::Polygon(p) => Some(p),
+                _ => None,
+            })?),
+            7 => Geometry::GeometryCollection(self.children(endian, Some)?),
+            _ => return Err(GeometryError::UnsupportedGeometryType(raw_type)),
+        })
+    }
+
+    fn coord(&mut self, endian: Endian, dims: usize) -> Result<Coord, GeometryError> {
+        let x = self.f64(endian)?;
+        let y = self.f64(endian)?;
+        for _ in 2..dims {
+            self.f64(endian)?;
+        }
+        Ok((x, y))
+    }
+
+    fn coords(&mut self, endian: Endian, dims: usize) -> Result<Vec<Coord>, GeometryError> {
+        let n = self.u32(endian)? as usize;
+        // don't trust the count for the allocation
+        let mut coords = Vec::with_capacity(n.min(self.input.len() / 16));
+        for _ in 0..n {
+            coords.push(self.coord(endian, dims)?);
+        }
+        Ok(coords)
+    }
+
+    fn rings(&mut self, endian: Endian, dims: usize) -> Result<Vec<Vec<Coord>>, GeometryError> {
+        let n = self.u32(endian)? as usize;
+        let mut rings = Vec::with_capacity(n.min(self.input.len() / 4));
+        for _ in 0..n {
+            rings.push(self.coords(endian, dims)?);
+        }
+        Ok(rings)
+    }
+
+    fn children<T, F: Fn(Geometry) -> Option<T>>(&mut self, endian: Endian, extract: F) -> Result<Vec<T>, GeometryError> {
+        if self.depth >= Geometry::MAX_NESTING_DEPTH {
+            return Err(GeometryError::NestingTooDeep(self.pos));
+        }
+        let n = self.u32(endian)? as usize;
+        let mut children = Vec::with_capacity(n.min(self.input.len() / 9));
+        self.depth += 1;
+        for _ in 0..n {
+            let child = self.geometry()?;
+            let child_type = child.wkb_type();
+            children.push(extract(child).ok_or(GeometryError::UnsupportedGeometryType(child_type))?);
+        }
+        self.depth -= 1;
+        Ok(children)
+    }
+}
+
+impl Geometry {
+
+    /// Maximum number of nested multi-geometries and geometry collections accepted when parsing WKB
+    pub const MAX_NESTING_DEPTH: usize = 64;
+
+    /// Parses a binary WKB (ISO or PostGIS EWKB) geometry. Z and M values and the SRID are ignored.
+    ///
+    /// ```rust
+    /// use wkb_raster::{Geometry, GeometryError};
+    ///
+    /// // GEOMETRYCOLLECTION(GEOMETRYCOLLECTION(...)) nested 100 times
+    /// let nested = [1, 7, 0, 0, 0, 1, 0, 0, 0].repeat(100);
+    /// assert_eq!(Geometry::from_wkb(&nested), Err(GeometryError::NestingTooDeep(64 * 9 + 5)));
+    /// ```
+    pub fn from_wkb(input: &[u8]) -> Result<Self, GeometryError> {
+        WkbReader { input, pos: 0, depth: 0 }.geometry()
+    }
+
+    /// Parses a hex-encoded WKB or EWKB geometry, as returned by PostGIS for `geometry` columns
+    pub fn from_wkb_string(string_bytes: &[u8]) -> Result<Self, GeometryError> {
+        if string_bytes.len() % 2 != 0 || !string_bytes.iter().all(|b| b.is_ascii_hexdigit()) {
+            return Err(GeometryError::InvalidHexString);
+        }
+        let bytes = string_bytes
+            .chunks_exact(2)
+            .map(|c| crate::big_endian::hex_chars_to_byte_be([c[0], c[1]]))
+            .collect::<Vec<_>>();
+        Self::from_wkb(&bytes)
+    }
+
+    fn wkb_type(&self) -> u32 {
+        match self {
+            Geometry::Point(_) => 1,
+            Geometry::LineString(_) => 2,
+            Geometry::Polygon(_) => 3,
+            Geometry::MultiPoint(_) => 4,
+            Geometry::MultiLineString(_) => 5,
+            Geometry::MultiPolygon(_) => 6,
+            Geometry::GeometryCollection(_) => 7,
+        }
+    }
+
+    /// Encodes the geometry as binary 2D WKB
+    pub fn to_wkb(&self, endian: Endian) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.write_wkb(&mut out, endian, None);
+        out
+    }
+
+    /// Encodes the geometry as binary PostGIS EWKB with an embedded SRID
+    pub fn to_ewkb(&self, endian: Endian, srid: i32) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.write_wkb(&mut out, endian, Some(srid));
+        out
+    }
+
+    /// Encodes the geometry as hex EWKB string, ready to be used in SQL statements
+    /// (i.e. `'...'::geometry`)
+    pub fn to_ewkb_string(&self, endian: Endian, srid: i32) -> String {
+        let mut s = Vec::new();
+        for byte in self.to_ewkb(endian, srid) {
+            s.extend_from_slice(&crate::big_endian::byte_to_hex_chars_be(byte));
+        }
+        unsafe { String::from_utf8_unchecked(s) }
+    }
+
+    fn write_wkb(&self, out: &mut Vec<u8>, endian: Endian, srid: Option<i32>) {
+
+        let u32_bytes = |v: u32| match endian {
+            Endian::Big => v.to_be_bytes(),
+            Endian::Little => v.to_le_bytes(),
+        };
+        let f64_bytes = |v: f64| match endian {
+            Endian::Big => v.to_be_bytes(),
+            Endian::Little => v.to_le_bytes(),
+        };
+        let write_coords = |out: &mut Vec<u8>, coords: &[Coord]| {
+            out.extend_from_slice(&u32_bytes(coords.len() as u32));
+            for (x, y) in coords {
+                out.extend_from_slice(&f64_bytes(*x));
+                out.extend_from_slice(&f64_bytes(*y));
+            }
+        };
+        let write_rings = |out: &mut Vec<u8>, rings: &[Vec<Coord>]| {
+            out.extend_from_slice(&u32_bytes(rings.len() as u32));
+            for ring in rings {
+                write_coords(out, ring);
+            }
+        };
+
+        out.push(endian as u8);
+        match srid {
+            Some(srid) => {
+                out.extend_from_slice(&u32_bytes(self.wkb_type() | EWKB_SRID_FLAG));
+                out.extend_from_slice(&u32_bytes(srid as u32));
+            },
+            None => out.extend_from_slice(&u32_bytes(self.wkb_type())),
+        }
+
+        match self {
+            Geometry::Point((x, y)) => {
+                out.extend_from_slice(&f64_bytes(*x));
+                out.extend_from_slice(&f64_bytes(*y));
+            },
+            Geometry::LineString(coords) => write_coords(out, coords),
+            Geometry::Polygon(rings) => write_rings(out, rings),
+            Geometry::MultiPoint(points) => {
+                out.extend_from_slice(&u32_bytes(points.len() as u32));
+                for p in points {
+                    Geometry::Point(*p).write_wkb(out, endian, None);
+                }
+            },
+            Geometry::MultiLineString(lines) => {
+                out.extend_from_slice(&u32_bytes(lines.len() as u32));
+                for l in lines {
+                    Geometry::LineString(l.clone()).write_wkb(out, endian, None);
+                }
+            },
+            Geometry::MultiPolygon(polygons) => {
+                out.extend_from_slice(&u32_bytes(polygons.len() as u32));
+                for p in polygons {
+                    Geometry::Polygon(p.clone()).write_wkb(out, endian, None);
+                }
+            },
+            Geometry::GeometryCollection(children) => {
+                out.extend_from_slice(&u32_bytes(children.len() as u32));
+                for c in children {
+                    c.write_wkb(out, endian, None);
+                }
+            },
+        }
+    }
+
+    /// Encodes the geometry as WKT, i.e. `POLYGON((0 0,1 0,1 1,0 0))`
+    pub fn to_wkt(&self) -> String {
+
+        fn coords(s: &mut String, coords: &[Coord]) {
+            s.push('(');
+            for (i, (x, y)) in coords.iter().enumerate() {
+                if i != 0 { s.push(','); }
+                let _ = write!(s, "{} {}", x, y);
+            }
+            s.push(')');
+        }
+
+        fn rings(s: &mut String, rings: &[Vec<Coord>]) {
+            s.push('(');
+            for (i, ring) in rings.iter().enumerate() {
+                if i != 0 { s.push(','); }
+                coords(s, ring);
+            }
+            s.push(')');
+        }
+
+        let mut s = String::new();
+        match self {
+            Geometry::Point(p) => { s.push_str("POINT"); coords(&mut s, &[*p]); },
+            Geometry::LineString(l) => { s.push_str("LINESTRING"); coords(&mut s, l); },
+            Geometry::Polygon(p) => { s.push_str("POLYGON"); rings(&mut s, p); },
+            Geometry::MultiPoint(points) => {
+                s.push_str("MULTIPOINT(");
+                for (i, p) in points.iter().enumerate() {
+                    if i != 0 { s.push(','); }
+                    coords(&mut s, &[*p]);
+                }
+                s.push(')');
+            },
+            Geometry::MultiLineString(l) => { s.push_str("MULTILINESTRING"); rings(&mut s, l); },
+            Geometry::MultiPolygon(polygons) => {
+                s.push_str("MULTIPOLYGON(");
+                for (i, p) in polygons.iter().enumerate() {
+                    if i != 0 { s.push(','); }
+                    rings(&mut s, p);
+                }
+                s.push(')');
+            },
+            Geometry::GeometryCollection(children) => {
+                s.push_str("GEOMETRYCOLLECTION(");
+                for (i, c) in children.iter().enumerate() {
+                    if i != 0 { s.push(','); }
+                    s.push_str(&c.to_wkt());
+                }
+                s.push(')');
+            },
+        }
+        s
+    }
+
+    /// Returns all polygons (as lists of rings) contained in the geometry
+    pub fn polygons(&self) -> Vec<&[Vec<Coord>]> {
+        match self {
+            Geometry::Polygon(p) => vec![&p[..]],
+            Geometry::MultiPolygon(p) => p.iter().map(|p| &p[..]).collect(),
+            Geometry::GeometryCollection(c) => c.iter().flat_map(|g| g.polygons()).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Returns all lines contained in the geometry (not including polygon rings)
+    pub fn lines(&self) -> Vec<&[Coord]> {
+        match self {
+            Geometry::LineString(l) => vec![&l[..]],
+            Geometry::MultiLineString(l) => l.iter().map(|l| &l[..]).collect(),
+            Geometry::GeometryCollection(c) => c.iter().flat_map(|g| g.lines()).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Returns all points contained in the geometry (not including line or polygon vertices)
+    pub fn points(&self) -> Vec<Coord> {
+        match self {
+            Geometry::Point(p) => vec![*p],
+            Geometry::MultiPoint(p) => p.clone(),
+            Geometry::GeometryCollection(c) => c.iter().flat_map(|g| g.points()).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Returns the bounding box `(min_x, min_y, max_x, max_y)` of all coordinates,
+    /// or `None` if the geometry is empty
+    pub fn bounding_box(&self) -> Option<(f64, f64, f64, f64)> {
+        let mut all = self.points();
+        all.extend(self.lines().iter().flat_map(|l| l.iter().copied()));
+        all.extend(self.polygons().iter().flat_map(|p| p.iter().flat_map(|r| r.iter().copied())));
+        if all.is_empty() {
+            return None;
+        }
+        Some(all.iter().fold(
+            (f64::INFINITY, f64::INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY),
+            |(min_x, min_y, max_x, max_y), &(x, y)| (min_x.min(x), min_y.min(y), max_x.max(x), max_y.max(y)),
+        ))
+    }
+}
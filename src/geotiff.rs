@@ -0,0 +1,1141 @@
+//! GeoTIFF decoding and encoding
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::Write;
+use crate::{Raster, RasterBand, RasterDataSource, InMemoryRasterData, PixType, Endian};
+use crate::mmap::RawLayout;
+
+const TAG_IMAGE_WIDTH: u16 = 256;
+const TAG_IMAGE_LENGTH: u16 = 257;
+const TAG_BITS_PER_SAMPLE: u16 = 258;
+const TAG_COMPRESSION: u16 = 259;
+const TAG_PHOTOMETRIC_INTERPRETATION: u16 = 262;
+const TAG_STRIP_OFFSETS: u16 = 273;
+const TAG_SAMPLES_PER_PIXEL: u16 = 277;
+const TAG_ROWS_PER_STRIP: u16 = 278;
+const TAG_STRIP_BYTE_COUNTS: u16 = 279;
+const TAG_PLANAR_CONFIGURATION: u16 = 284;
+const TAG_PREDICTOR: u16 = 317;
+const TAG_TILE_WIDTH: u16 = 322;
+const TAG_TILE_LENGTH: u16 = 323;
+const TAG_TILE_OFFSETS: u16 = 324;
+const TAG_TILE_BYTE_COUNTS: u16 = 325;
+const TAG_EXTRA_SAMPLES: u16 = 338;
+const TAG_SAMPLE_FORMAT: u16 = 339;
+const TAG_MODEL_PIXEL_SCALE: u16 = 33550;
+const TAG_MODEL_TIEPOINT: u16 = 33922;
+const TAG_MODEL_TRANSFORMATION: u16 = 34264;
+const TAG_GEO_KEY_DIRECTORY: u16 = 34735;
+const TAG_GDAL_NODATA: u16 = 42113;
+
+const COMPRESSION_NONE: u16 = 1;
+const COMPRESSION_LZW: u16 = 5;
+const COMPRESSION_DEFLATE: u16 = 8;
+const COMPRESSION_DEFLATE_OLD: u16 = 32946;
+const COMPRESSION_PACKBITS: u16 = 32773;
+
+const PREDICTOR_NONE: u16 = 1;
+const PREDICTOR_HORIZONTAL: u16 = 2;
+const PREDICTOR_FLOATING_POINT: u16 = 3;
+
+const SAMPLE_FORMAT_UINT: u16 = 1;
+const SAMPLE_FORMAT_INT: u16 = 2;
+const SAMPLE_FORMAT_FLOAT: u16 = 3;
+
+const GEO_KEY_MODEL_TYPE: u16 = 1024;
+const GEO_KEY_RASTER_TYPE: u16 = 1025;
+const GEO_KEY_GEOGRAPHIC_TYPE: u16 = 2048;
+const GEO_KEY_PROJECTED_CS_TYPE: u16 = 3072;
+const MODEL_TYPE_PROJECTED: u16 = 1;
+const MODEL_TYPE_GEOGRAPHIC: u16 = 2;
+const RASTER_PIXEL_IS_AREA: u16 = 1;
+const RASTER_PIXEL_IS_POINT: u16 = 2;
+
+const PHOTOMETRIC_MIN_IS_BLACK: u16 = 1;
+
+/// Uncompressed size of the strips written by `write_geotiff`
+const STRIP_SIZE: usize = 8192;
+
+/// EPSG codes of common geographic 2D CRSs, written as geographic unless `GeoTiffOptions::crs_kind`
+/// says otherwise: WGS 84, WGS 72, NAD27, NAD83, NAD83(CSRS), NAD83(HARN), NAD83(2011), ETRS89,
+/// ED50, OSGB36, DHDN, CH1903, CH1903+, RGF93, GDA94, GDA2020, NZGD49, NZGD2000, JGD2000,
+/// JGD2011, SIRGAS 2000, CGCS2000, Hartebeesthoek94, Tokyo and Pulkovo 1942
+const GEOGRAPHIC_SRIDS: [i32; 25] = [
+    4326, 4322, 4267, 4269, 4617, 4152, 6318, 4258, 4230, 4277, 4314, 4149, 4150, 4171,
+    4283, 7844, 4272, 4167, 4612, 6668, 4674, 4490, 4148, 4301, 4284,
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GeoTiffError {
+    /// The data doesn't start with a TIFF or BigTIFF header
+    InvalidHeader,
+    /// An IFD, a tag value or an image chunk extends past the end of the data
+    Truncated,
+    /// The tag with the given number is missing or has an invalid value
+    InvalidTag(u16),
+    /// Only uncompressed, LZW, Deflate and PackBits images can be decoded
+    UnsupportedCompression(u16),
+    /// Only horizontal differencing and the floating point predictor are supported
+    UnsupportedPredictor(u16),
+    /// The combination of `SampleFormat` and `BitsPerSample` has no `PixType`
+    UnsupportedSampleFormat { format: u16, bits: u16 },
+    /// All samples of a pixel must have the same `SampleFormat` and `BitsPerSample`
+    MixedSampleFormats,
+    /// A compressed image chunk is invalid or has the wrong size
+    InvalidImageData,
+    /// The image is larger than 65535 pixels in one direction
+    TooLarge { width: u64, height: u64 },
+    /// The pixels of the image can't be allocated
+    OutOfMemory { width: usize, height: usize, samples: usize },
+    /// Rasters without bands can't be encoded
+    NoBands,
+    /// TIFF images can't be empty
+    EmptyRaster,
+    /// Band at the given index is stored out-of-db
+    BandIsOffline(usize),
+    /// All bands have to have the same pixel type
+    MixedPixTypes,
+    /// All bands have to have the same nodata value (`GDAL_NODATA` applies to all bands)
+    MixedNodataValues,
+    /// Tile width and height have to be non-zero multiples of 16
+    InvalidTileSize { width: u32, height: u32 },
+    /// Writing the image failed, contains the error message
+    Io(String),
+}
+
+impl fmt::Display for GeoTiffError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GeoTiffError::InvalidHeader => write!(f, "data is not a TIFF image (invalid header)"),
+            GeoTiffError::Truncated => write!(f, "TIFF structure extends past the end of the data"),
+            GeoTiffError::InvalidTag(tag) => write!(f, "TIFF tag {} is missing or invalid", tag),
+            GeoTiffError::UnsupportedCompression(c) => write!(f, "TIFF compression {} is not supported (only none, LZW, Deflate and PackBits)", c),
+            GeoTiffError::UnsupportedPredictor(p) => write!(f, "TIFF predictor {} is not supported for this sample format", p),
+            GeoTiffError::UnsupportedSampleFormat { format, bits } => write!(f, "TIFF sample format {} with {} bits per sample is not supported", format, bits),
+            GeoTiffError::MixedSampleFormats => write!(f, "TIFF samples with different formats or bit depths are not supported"),
+            GeoTiffError::InvalidImageData => write!(f, "TIFF image data is invalid"),
+            GeoTiffError::TooLarge { width, height } => write!(f, "TIFF image of {}x{} pixels is too large for a raster (at most 65535x65535)", width, height),
+            GeoTiffError::OutOfMemory { width, height, samples } => write!(f, "TIFF image of {}x{} pixels with {} samples per pixel doesn't fit into memory", width, height, samples),
+            GeoTiffError::NoBands => write!(f, "TIFF requires at least one band"),
+            GeoTiffError::EmptyRaster => write!(f, "TIFF requires a width and height of at least one pixel"),
+            GeoTiffError::BandIsOffline(band) => write!(f, "band {} is stored out-of-db and can't be encoded as TIFF", band),
+            GeoTiffError::MixedPixTypes => write!(f, "TIFF requires all bands to have the same pixel type"),
+            GeoTiffError::MixedNodataValues => write!(f, "GeoTIFF requires all bands to have the same nodata value"),
+            GeoTiffError::InvalidTileSize { width, height } => write!(f, "TIFF tile size {}x{} is not a multiple of 16", width, height),
+            GeoTiffError::Io(message) => write!(f, "writing TIFF failed: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for GeoTiffError { }
+
+/// Options for `Raster::write_geotiff`
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct GeoTiffOptions {
+    /// Internal tile size `(width, height)`, both multiples of 16; strips if `None` (default: `None`)
+    pub tile_size: Option<(u32, u32)>,
+    /// Deflate compression level from 1 (fastest) to 9 (smallest), higher levels are treated
+    /// as 9; uncompressed if `None` or `Some(0)` (default: `Some(6)`)
+    pub deflate_level: Option<u8>,
+    /// Whether the SRID is written as geographic or projected CRS; if `None`, common geographic
+    /// CRSs like 4326 are detected by their EPSG code and all others are written as projected
+    /// (default: `None`)
+    pub crs_kind: Option<CrsKind>,
+}
+
+impl Default for GeoTiffOptions {
+    fn default() -> Self {
+        GeoTiffOptions {
+            tile_size: None,
+            deflate_level: Some(6),
+            crs_kind: None,
+        }
+    }
+}
+
+/// Kind of the coordinate reference system of the SRID, written as GeoTIFF model type
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum CrsKind {
+    /// Longitude / latitude, the SRID is written as `GeographicTypeGeoKey`
+    Geographic,
+    /// Projected coordinates, the SRID is written as `ProjectedCSTypeGeoKey`
+    Projected,
+}
+
+/// A directory entry: field type and the bytes of the values
+struct Entry<'a> {
+    field_type: u16,
+    data: &'a [u8],
+}
+
+/// Byte order aware access to the TIFF data
+#[derive(Copy, Clone)]
+struct Reader<'a> {
+    bytes: &'a [u8],
+    big_endian: bool,
+}
+
+impl<'a> Reader<'a> {
+
+    fn slice(&self, offset: u64, len: u64) -> Result<&'a [u8], GeoTiffError> {
+        let end = offset.checked_add(len).ok_or(GeoTiffError::Truncated)?;
+        self.bytes.get(offset as usize..end as usize).ok_or(GeoTiffError::Truncated)
+    }
+
+    fn uint(&self, bytes: &[u8]) -> u64 {
+        let fold = |v: u64, b: &u8| (v << 8) | *b as u64;
+        if self.big_endian {
+            bytes.iter().fold(0, fold)
+        } else {
+            bytes.iter().rev().fold(0, fold)
+        }
+    }
+
+    fn read(&self, offset: u64, len: u64) -> Result<u64, GeoTiffError> {
+        Ok(self.uint(self.slice(offset, len)?))
+    }
+
+    /// Integer values of an entry (`BYTE`, `SHORT`, `LONG` or `LONG8`)
+    fn uints(&self, entry: &Entry) -> Option<Vec<u64>> {
+        let size = match entry.field_type {
+            1 | 7 => 1,
+            3 => 2,
+            4 => 4,
+            16 => 8,
+            _ => return None,
+        };
+        Some(entry.data.chunks(size).map(|v| self.uint(v)).collect())
+    }
+
+    /// Numeric values of an entry as `f64`
+    fn floats(&self, entry: &Entry) -> Option<Vec<f64>> {
+        match entry.field_type {
+            11 => Some(entry.data.chunks(4).map(|v| f32::from_bits(self.uint(v) as u32) as f64).collect()),
+            12 => Some(entry.data.chunks(8).map(|v| f64::from_bits(self.uint(v))).collect()),
+            5 => Some(entry.data.chunks(8).map(|v| self.uint(&v[..4]) as f64 / self.uint(&v[4..]) as f64).collect()),
+            _ => self.uints(entry).map(|v| v.into_iter().map(|v| v as f64).collect()),
+        }
+    }
+}
+
+/// Size in bytes of a value of a TIFF field type
+fn field_size(field_type: u16) -> Option<usize> {
+    match field_type {
+        1 | 2 | 6 | 7 => Some(1),
+        3 | 8 => Some(2),
+        4 | 9 | 11 | 13 => Some(4),
+        5 | 10 | 12 | 16 | 17 | 18 => Some(8),
+        _ => None,
+    }
+}
+
+/// Parses the header and the first image file directory
+fn read_ifd(bytes: &[u8]) -> Result<(Reader<'_>, BTreeMap<u16, Entry<'_>>), GeoTiffError> {
+
+    let big_endian = match bytes.get(..2) {
+        Some(b"II") => false,
+        Some(b"MM") => true,
+        _ => return Err(GeoTiffError::InvalidHeader),
+    };
+    let reader = Reader { bytes, big_endian };
+
+    let big_tiff = match reader.read(2, 2).map_err(|_| GeoTiffError::InvalidHeader)? {
+        42 => false,
+        43 => true,
+        _ => return Err(GeoTiffError::InvalidHeader),
+    };
+
+    // classic TIFF: 16-bit entry count, 12-byte entries with 4 value bytes;
+    // BigTIFF: 64-bit entry count, 20-byte entries with 8 value bytes
+    let (ifd_offset, count_size, entry_size, value_size) = if big_tiff {
+        (reader.read(8, 8).map_err(|_| GeoTiffError::InvalidHeader)?, 8, 20, 8)
+    } else {
+        (reader.read(4, 4).map_err(|_| GeoTiffError::InvalidHeader)?, 2, 12, 4)
+    };
+
+    let entry_count = reader.read(ifd_offset, count_size)?;
+    let table = reader.slice(ifd_offset + count_size, entry_count.saturating_mul(entry_size))?;
+
+    let mut entries = BTreeMap::new();
+    for raw in table.chunks(entry_size as usize) {
+        let tag = reader.uint(&raw[0..2]) as u16;
+        let field_type = reader.uint(&raw[2..4]) as u16;
+        let (count, value) = raw[4..].split_at(value_size);
+        let count = reader.uint(count);
+        let size = match field_size(field_type) {
+            Some(size) => size as u64,
+            None => continue,
+        };
+        let len = count.checked_mul(size).ok_or(GeoTiffError::Truncated)?;
+        let data = if len <= value.len() as u64 {
+            &value[..len as usize]
+        } else {
+            reader.slice(reader.uint(value), len)?
+        };
+        entries.insert(tag, Entry { field_type, data });
+    }
+
+    Ok((reader, entries))
+}
+
+/// The first image of a TIFF file: sample layout, chunk locations and georeference
+struct Image<'a> {
+    reader: Reader<'a>,
+    width: usize,
+    height: usize,
+    samples: usize,
+    bits: usize,
+    format: u16,
+    /// Pixel type of the samples, with the `GDAL_NODATA` value
+    pixtype: PixType,
+    compression: u16,
+    predictor: u16,
+    planar: bool,
+    tiled: bool,
+    chunk_width: usize,
+    chunk_height: usize,
+    chunks_across: usize,
+    chunks_per_plane: usize,
+    offsets: Vec<u64>,
+    byte_counts: Vec<u64>,
+    /// Raster without bands with the geotransform and SRID of the image
+    grid: Raster,
+}
+
+/// Parses the first image file directory and checks that the image can be decoded
+fn read_image(bytes: &[u8]) -> Result<Image<'_>, GeoTiffError> {
+
+    let (reader, entries) = read_ifd(bytes)?;
+
+    let uints = |tag: u16| entries.get(&tag).and_then(|e| reader.uints(e));
+    let floats = |tag: u16| entries.get(&tag).and_then(|e| reader.floats(e));
+    let uint = |tag: u16, default: u64| match uints(tag) {
+        Some(values) => values.first().copied().ok_or(GeoTiffError::InvalidTag(tag)),
+        None if entries.contains_key(&tag) => Err(GeoTiffError::InvalidTag(tag)),
+        None => Ok(default),
+    };
+
+    let width = uint(TAG_IMAGE_WIDTH, 0)?;
+    let height = uint(TAG_IMAGE_LENGTH, 0)?;
+    if width == 0 {
+        return Err(GeoTiffError::InvalidTag(TAG_IMAGE_WIDTH));
+    }
+    if height == 0 {
+        return Err(GeoTiffError::InvalidTag(TAG_IMAGE_LENGTH));
+    }
+    if width > u16::MAX as u64 || height > u16::MAX as u64 {
+        return Err(GeoTiffError::TooLarge { width, height });
+    }
+    let (width, height) = (width as usize, height as usize);
+
+    let samples = uint(TAG_SAMPLES_PER_PIXEL, 1)? as usize;
+    if samples == 0 || samples > u16::MAX as usize {
+        return Err(GeoTiffError::InvalidTag(TAG_SAMPLES_PER_PIXEL));
+    }
+
+    // per-sample tags, all samples have to agree
+    let per_sample = |tag: u16, default: u64| -> Result<u16, GeoTiffError> {
+        let values = uints(tag).unwrap_or_else(|| vec![default]);
+        let first = *values.first().ok_or(GeoTiffError::InvalidTag(tag))?;
+        if values.iter().take(samples).any(|v| *v != first) {
+            return Err(GeoTiffError::MixedSampleFormats);
+        }
+        Ok(first as u16)
+    };
+    let bits = per_sample(TAG_BITS_PER_SAMPLE, 1)?;
+    let format = per_sample(TAG_SAMPLE_FORMAT, SAMPLE_FORMAT_UINT as u64)?;
+
+    let pixtype = match (format, bits) {
+        (SAMPLE_FORMAT_UINT, 1) => PixType::Bool1Bit(None),
+        (SAMPLE_FORMAT_UINT, 2) => PixType::UInt2(None),
+        (SAMPLE_FORMAT_UINT, 4) => PixType::UInt4(None),
+        (SAMPLE_FORMAT_UINT, 8) => PixType::UInt8(None),
+        (SAMPLE_FORMAT_INT, 8) => PixType::Int8(None),
+        (SAMPLE_FORMAT_UINT, 16) => PixType::UInt16(None),
+        (SAMPLE_FORMAT_INT, 16) => PixType::Int16(None),
+        (SAMPLE_FORMAT_UINT, 32) => PixType::UInt32(None),
+        (SAMPLE_FORMAT_INT, 32) => PixType::Int32(None),
+        (SAMPLE_FORMAT_FLOAT, 32) => PixType::Float32(None),
+        (SAMPLE_FORMAT_FLOAT, 64) => PixType::Float64(None),
+        (format, bits) => return Err(GeoTiffError::UnsupportedSampleFormat { format, bits }),
+    };
+
+    let compression = uint(TAG_COMPRESSION, COMPRESSION_NONE as u64)? as u16;
+    if ![COMPRESSION_NONE, COMPRESSION_LZW, COMPRESSION_DEFLATE, COMPRESSION_DEFLATE_OLD, COMPRESSION_PACKBITS].contains(&compression) {
+        return Err(GeoTiffError::UnsupportedCompression(compression));
+    }
+
+    let predictor = uint(TAG_PREDICTOR, PREDICTOR_NONE as u64)? as u16;
+    match predictor {
+        PREDICTOR_NONE => {},
+        PREDICTOR_HORIZONTAL if bits >= 8 => {},
+        PREDICTOR_FLOATING_POINT if format == SAMPLE_FORMAT_FLOAT => {},
+        other => return Err(GeoTiffError::UnsupportedPredictor(other)),
+    }
+
+    let planar = match uint(TAG_PLANAR_CONFIGURATION, 1)? {
+        1 => false,
+        2 => true,
+        _ => return Err(GeoTiffError::InvalidTag(TAG_PLANAR_CONFIGURATION)),
+    };
+
+    // chunk (strip or tile) size and the tags with the chunk locations
+    let tiled = entries.contains_key(&TAG_TILE_WIDTH);
+    let (chunk_width, chunk_height, offsets_tag, byte_counts_tag) = if tiled {
+        let tile_width = uint(TAG_TILE_WIDTH, 0)? as usize;
+        let tile_height = uint(TAG_TILE_LENGTH, 0)? as usize;
+        if tile_width == 0 || tile_width > 1 << 16 {
+            return Err(GeoTiffError::InvalidTag(TAG_TILE_WIDTH));
+        }
+        if tile_height == 0 || tile_height > 1 << 16 {
+            return Err(GeoTiffError::InvalidTag(TAG_TILE_LENGTH));
+        }
+        (tile_width, tile_height, TAG_TILE_OFFSETS, TAG_TILE_BYTE_COUNTS)
+    } else {
+        let rows_per_strip = uint(TAG_ROWS_PER_STRIP, height as u64)?.clamp(1, height as u64) as usize;
+        (width, rows_per_strip, TAG_STRIP_OFFSETS, TAG_STRIP_BYTE_COUNTS)
+    };
+
+    let chunks_across = (width + chunk_width - 1) / chunk_width;
+    let chunks_per_plane = chunks_across * ((height + chunk_height - 1) / chunk_height);
+    let planes = if planar { samples } else { 1 };
+
+    let offsets = uints(offsets_tag).ok_or(GeoTiffError::InvalidTag(offsets_tag))?;
+    let byte_counts = uints(byte_counts_tag).ok_or(GeoTiffError::InvalidTag(byte_counts_tag))?;
+    if offsets.len() < chunks_per_plane * planes {
+        return Err(GeoTiffError::InvalidTag(offsets_tag));
+    }
+    if byte_counts.len() < chunks_per_plane * planes {
+        return Err(GeoTiffError::InvalidTag(byte_counts_tag));
+    }
+
+    let nodata = entries.get(&TAG_GDAL_NODATA)
+        .filter(|e| e.field_type == 2)
+        .and_then(|e| std::str::from_utf8(e.data).ok())
+        .and_then(|s| s.trim_end_matches('\0').trim().parse::<f64>().ok());
+
+    let (scale_x, scale_y, ip_x, ip_y, skew_x, skew_y) = geotransform(
+        floats(TAG_MODEL_TRANSFORMATION),
+        floats(TAG_MODEL_TIEPOINT),
+        floats(TAG_MODEL_PIXEL_SCALE),
+        uints(TAG_GEO_KEY_DIRECTORY).unwrap_or_default(),
+    );
+
+    Ok(Image {
+        reader,
+        width,
+        height,
+        samples,
+        bits: bits as usize,
+        format,
+        pixtype: pixtype.with_nodata_f64(nodata),
+        compression,
+        predictor,
+        planar,
+        tiled,
+        chunk_width,
+        chunk_height,
+        chunks_across,
+        chunks_per_plane,
+        offsets,
+        byte_counts,
+        grid: Raster {
+            endian: Endian::Little,
+            version: 0,
+            scale_x,
+            scale_y,
+            ip_x,
+            ip_y,
+            skew_x,
+            skew_y,
+            srid: srid(&uints(TAG_GEO_KEY_DIRECTORY).unwrap_or_default()),
+            width: width as u16,
+            height: height as u16,
+            bands: Vec::new(),
+        },
+    })
+}
+
+/// Sample locations of all bands of an uncompressed image without predictor and with at
+/// least 8 bits per sample, `None` for other images
+pub(crate) fn raw_layouts(bytes: &[u8]) -> Result<Option<Vec<RawLayout>>, GeoTiffError> {
+
+    let image = read_image(bytes)?;
+    if image.compression != COMPRESSION_NONE || image.predictor != PREDICTOR_NONE || image.bits < 8 {
+        return Ok(None);
+    }
+
+    let size = image.bits / 8;
+    let (planes, chunk_samples) = if image.planar { (image.samples, 1) } else { (1, image.samples) };
+    let row_stride = image.chunk_width * chunk_samples * size;
+
+    // every chunk has to contain all of its rows, missing (sparse) chunks are nodata
+    let mut chunks = Vec::with_capacity(image.chunks_per_plane * planes);
+    for (chunk, (&offset, &byte_count)) in image.offsets.iter().zip(image.byte_counts.iter()).take(image.chunks_per_plane * planes).enumerate() {
+        if byte_count == 0 {
+            chunks.push(None);
+            continue;
+        }
+        let y0 = (chunk % image.chunks_per_plane / image.chunks_across) * image.chunk_height;
+        let rows = if image.tiled { image.chunk_height } else { image.chunk_height.min(image.height - y0) };
+        if (rows * row_stride) as u64 > byte_count {
+            return Err(GeoTiffError::InvalidImageData);
+        }
+        image.reader.slice(offset, byte_count)?;
+        chunks.push(Some(offset));
+    }
+
+    Ok(Some((0..image.samples).map(|band| {
+        // planar images have a set of chunks per band, chunky images interleave the samples
+        let (first, shift) = if image.planar { (band * image.chunks_per_plane, 0) } else { (0, band * size) };
+        RawLayout {
+            grid: image.grid.clone_without_bands(),
+            pixtype: image.pixtype,
+            big_endian: image.reader.big_endian,
+            chunk_offsets: chunks[first..first + image.chunks_per_plane].iter().map(|c| c.map(|o| o + shift as u64)).collect(),
+            chunk_width: image.chunk_width,
+            chunk_height: image.chunk_height,
+            chunks_across: image.chunks_across,
+            pixel_stride: chunk_samples * size,
+            row_stride,
+        }
+    }).collect()))
+}
+
+impl Raster {
+
+    /// Decodes the first image of a TIFF or BigTIFF file, with one band per sample.
+    ///
+    /// Strip and tile layouts (chunky or planar), no compression, LZW, Deflate and PackBits
+    /// with or without predictor and all sample formats that have a `PixType` are supported.
+    ///
+    /// The geotransform is taken from `ModelTransformation` or from `ModelTiepoint` and
+    /// `ModelPixelScale` (shifted by half a pixel for `PixelIsPoint` rasters), the SRID from
+    /// the EPSG code of the projected or geographic CRS key (0 if the CRS is user-defined),
+    /// and the nodata value of all bands from `GDAL_NODATA`. Images without georeferencing
+    /// get a pixel size of 1 and the origin at 0, like in GDAL.
+    ///
+    /// ```rust
+    /// use wkb_raster::{Raster, InMemoryRasterData, RasterDataSource};
+    ///
+    /// // little endian, one strip with 2x2 UInt8 pixels, ModelPixelScale (10, 10) and
+    /// // ModelTiepoint (0, 0) -> (500, 900) and EPSG:32633
+    /// let mut tiff = vec![b'I', b'I', 42, 0, 8, 0, 0, 0, 10, 0];
+    /// let entries: [(u16, u16, u32, u32); 10] = [
+    ///     (256, 3, 1, 2), (257, 3, 1, 2), (258, 3, 1, 8), (273, 4, 1, 134),
+    ///     (277, 3, 1, 1), (278, 3, 1, 2), (279, 4, 1, 4),
+    ///     (33550, 12, 3, 138), (33922, 12, 6, 162), (34735, 3, 8, 210),
+    /// ];
+    /// for (tag, field_type, count, value) in entries {
+    ///     tiff.extend(tag.to_le_bytes());
+    ///     tiff.extend(field_type.to_le_bytes());
+    ///     tiff.extend(count.to_le_bytes());
+    ///     tiff.extend(value.to_le_bytes());
+    /// }
+    /// tiff.extend([0, 0, 0, 0]);
+    /// tiff.extend([1, 2, 3, 0]);
+    /// for v in [10.0f64, 10.0, 0.0, 0.0, 0.0, 0.0, 500.0, 900.0, 0.0] {
+    ///     tiff.extend(v.to_le_bytes());
+    /// }
+    /// for v in [1u16, 1, 0, 1, 3072, 0, 1, 32633] {
+    ///     tiff.extend(v.to_le_bytes());
+    /// }
+    ///
+    /// let raster = Raster::from_geotiff(&tiff).unwrap();
+    /// assert_eq!((raster.ip_x, raster.ip_y, raster.scale_x, raster.scale_y), (500.0, 900.0, 10.0, -10.0));
+    /// assert_eq!(raster.srid, 32633);
+    /// assert_eq!(raster.bands[0].data, RasterDataSource::InMemory(InMemoryRasterData::UInt8 {
+    ///     data: vec![vec![1, 2], vec![3, 0]],
+    ///     nodata: None,
+    /// }));
+    /// ```
+    pub fn from_geotiff(bytes: &[u8]) -> Result<Raster, GeoTiffError> {
+
+        let Image {
+            reader, width, height, samples, bits, format, pixtype, compression, predictor, planar, tiled,
+            chunk_width, chunk_height, chunks_across, chunks_per_plane, offsets, byte_counts, grid,
+        } = read_image(bytes)?;
+        let (planes, chunk_samples) = if planar { (samples, 1) } else { (1, samples) };
+
+        // the size comes from the header, a few bytes can declare billions of pixels
+        let out_of_memory = || GeoTiffError::OutOfMemory { width, height, samples };
+        let band_len = width * height;
+        let mut values = Vec::new();
+        values.try_reserve_exact(band_len.checked_mul(samples).ok_or_else(out_of_memory)?).map_err(|_| out_of_memory())?;
+        // missing (sparse) chunks are nodata
+        values.resize(band_len * samples, pixtype.nodata_f64().unwrap_or(0.0));
+
+        let row_len = chunk_width * chunk_samples;
+        let stride = (row_len * bits + 7) / 8;
+        // decoded size of a complete chunk, compressed chunks decoding to more data are invalid
+        let max_len = chunk_height * stride;
+
+        for (chunk, (&offset, &byte_count)) in offsets.iter().zip(byte_counts.iter()).take(chunks_per_plane * planes).enumerate() {
+
+            if byte_count == 0 {
+                continue;
+            }
+
+            let plane = chunk / chunks_per_plane;
+            let x0 = (chunk % chunks_per_plane % chunks_across) * chunk_width;
+            let y0 = (chunk % chunks_per_plane / chunks_across) * chunk_height;
+            // tiles are always complete, the last strip may be shorter
+            let rows = if tiled { chunk_height } else { chunk_height.min(height - y0) };
+
+            let compressed = reader.slice(offset, byte_count)?;
+            let data = match compression {
+                COMPRESSION_NONE => Some(compressed.to_vec()),
+                COMPRESSION_LZW => lzw_decode(compressed, max_len),
+                COMPRESSION_PACKBITS => packbits_decode(compressed, max_len),
+                _ => miniz_oxide::inflate::decompress_to_vec_zlib_with_limit(compressed, max_len).ok(),
+            }.ok_or(GeoTiffError::InvalidImageData)?;
+            if data.len() < rows * stride {
+                return Err(GeoTiffError::InvalidImageData);
+            }
+
+            for (r, row) in data.chunks(stride).take(rows).enumerate() {
+                let y = y0 + r;
+                if y >= height {
+                    break;
+                }
+                let raw = decode_row(row, row_len, bits, reader.big_endian, predictor, chunk_samples);
+                for (c, pixel) in raw.chunks(chunk_samples).enumerate().take(width.saturating_sub(x0)) {
+                    for (s, value) in pixel.iter().enumerate() {
+                        values[(plane + s) * band_len + y * width + x0 + c] = sample_value(*value, format, bits);
+                    }
+                }
+            }
+        }
+
+        Ok(Raster {
+            bands: values.chunks(band_len).map(|values| RasterBand {
+                is_nodata_value: false,
+                data: RasterDataSource::InMemory(InMemoryRasterData::from_fn_f64(pixtype, width, height, |col, row| {
+                    values[row * width + col]
+                })),
+            }).collect(),
+            .. grid
+        })
+    }
+
+    /// Writes all bands as a pixel-interleaved TIFF with one sample per band, readable by GDAL
+    /// and QGIS. Files larger than 4 GiB are written as BigTIFF.
+    ///
+    /// The geotransform is stored as `ModelPixelScale` and `ModelTiepoint` (or as
+    /// `ModelTransformation` for skewed or south-up rasters), the SRID as EPSG code of a
+    /// geographic or projected CRS (see `GeoTiffOptions::crs_kind`), and the nodata value,
+    /// which has to be the same for all bands, as `GDAL_NODATA`.
+    ///
+    /// ```rust
+    /// use wkb_raster::{Raster, RasterBand, RasterDataSource, InMemoryRasterData, Endian, GeoTiffOptions};
+    ///
+    /// let band = |data: Vec<Vec<i16>>| RasterBand {
+    ///     is_nodata_value: false,
+    ///     data: RasterDataSource::InMemory(InMemoryRasterData::Int16 { data, nodata: Some(-9999) }),
+    /// };
+    /// let raster = Raster {
+    ///     endian: Endian::Little, version: 0,
+    ///     scale_x: 30.0, scale_y: -30.0, ip_x: 440720.0, ip_y: 3751320.0, skew_x: 0.0, skew_y: 0.0,
+    ///     srid: 32611, width: 3, height: 2,
+    ///     bands: vec![
+    ///         band(vec![vec![1, 2, 3], vec![4, 5, -9999]]),
+    ///         band(vec![vec![-1, -2, -3], vec![-4, -5, -9999]]),
+    ///     ],
+    /// };
+    ///
+    /// let options = GeoTiffOptions { tile_size: Some((16, 16)), .. GeoTiffOptions::default() };
+    /// let mut tiff = Vec::new();
+    /// raster.write_geotiff(&mut tiff, options).unwrap();
+    ///
+    /// assert_eq!(Raster::from_geotiff(&tiff).unwrap(), raster);
+    ///
+    /// // deflate level 0 writes an uncompressed file
+    /// let (mut stored, mut plain) = (Vec::new(), Vec::new());
+    /// raster.write_geotiff(&mut stored, GeoTiffOptions { deflate_level: Some(0), .. options }).unwrap();
+    /// raster.write_geotiff(&mut plain, GeoTiffOptions { deflate_level: None, .. options }).unwrap();
+    /// assert_eq!(stored, plain);
+    /// ```
+    pub fn write_geotiff<W: Write>(&self, mut writer: W, options: GeoTiffOptions) -> Result<(), GeoTiffError> {
+
+        let bands = self.bands.iter().enumerate().map(|(i, band)| match &band.data {
+            RasterDataSource::InMemory(data) => Ok(data),
+            RasterDataSource::Offline(_) => Err(GeoTiffError::BandIsOffline(i)),
+        }).collect::<Result<Vec<_>, _>>()?;
+
+        let first = bands.first().ok_or(GeoTiffError::NoBands)?;
+        let pixtype = first.get_pixtype();
+        if bands.iter().any(|b| b.get_pixtype().with_nodata_f64(None) != pixtype.with_nodata_f64(None)) {
+            return Err(GeoTiffError::MixedPixTypes);
+        }
+        let nodata = pixtype.nodata_f64();
+        let same_nodata = |other: Option<f64>| match (nodata, other) {
+            (Some(a), Some(b)) => a == b || (a.is_nan() && b.is_nan()),
+            (a, b) => a.is_none() && b.is_none(),
+        };
+        if !bands.iter().all(|b| same_nodata(b.get_pixtype().nodata_f64())) {
+            return Err(GeoTiffError::MixedNodataValues);
+        }
+
+        let (width, height) = (first.width(), first.height());
+        if width == 0 || height == 0 {
+            return Err(GeoTiffError::EmptyRaster);
+        }
+
+        let (format, bits) = sample_format(pixtype);
+        let samples = bands.len();
+        let pixel_bits = samples * bits as usize;
+
+        let (chunk_width, chunk_height) = match options.tile_size {
+            Some((tile_width, tile_height)) => {
+                if tile_width == 0 || tile_height == 0 || tile_width % 16 != 0 || tile_height % 16 != 0 {
+                    return Err(GeoTiffError::InvalidTileSize { width: tile_width, height: tile_height });
+                }
+                (tile_width as usize, tile_height as usize)
+            },
+            None => (width, (STRIP_SIZE / ((width * pixel_bits + 7) / 8)).clamp(1, height)),
+        };
+        let stride = (chunk_width * pixel_bits + 7) / 8;
+        let chunks_across = (width + chunk_width - 1) / chunk_width;
+        let chunk_count = chunks_across * ((height + chunk_height - 1) / chunk_height);
+
+        // level 0 would store deflate blocks without compression, write a plain file instead
+        let deflate_level = options.deflate_level.filter(|&level| level > 0).map(|level| level.min(9));
+
+        let chunks = (0..chunk_count).map(|chunk| {
+            let x0 = chunk % chunks_across * chunk_width;
+            let y0 = chunk / chunks_across * chunk_height;
+            // tiles are always complete (padded with 0), the last strip may be shorter
+            let rows = if options.tile_size.is_some() { chunk_height } else { chunk_height.min(height - y0) };
+            let mut data = Vec::with_capacity(rows * stride);
+            for y in y0..y0 + rows {
+                let samples = (x0..x0 + chunk_width).flat_map(|x| bands.iter().map(move |band| {
+                    if x < width && y < height { band.get_f64(x, y).unwrap_or(0.0) } else { 0.0 }
+                }));
+                encode_row(&mut data, samples, pixtype, bits as usize);
+            }
+            match deflate_level {
+                Some(level) => miniz_oxide::deflate::compress_to_vec_zlib(&data, level),
+                None => data,
+            }
+        }).collect::<Vec<_>>();
+
+        let mut entries = vec![
+            IfdEntry::longs(TAG_IMAGE_WIDTH, &[width as u64], false),
+            IfdEntry::longs(TAG_IMAGE_LENGTH, &[height as u64], false),
+            IfdEntry::shorts(TAG_BITS_PER_SAMPLE, &vec![bits; samples]),
+            IfdEntry::shorts(TAG_COMPRESSION, &[if deflate_level.is_some() { COMPRESSION_DEFLATE } else { COMPRESSION_NONE }]),
+            IfdEntry::shorts(TAG_PHOTOMETRIC_INTERPRETATION, &[PHOTOMETRIC_MIN_IS_BLACK]),
+            IfdEntry::shorts(TAG_SAMPLES_PER_PIXEL, &[samples as u16]),
+            IfdEntry::shorts(TAG_PLANAR_CONFIGURATION, &[1]),
+            IfdEntry::shorts(TAG_SAMPLE_FORMAT, &vec![format; samples]),
+        ];
+        if samples > 1 {
+            // unspecified extra samples
+            entries.push(IfdEntry::shorts(TAG_EXTRA_SAMPLES, &vec![0; samples - 1]));
+        }
+        if options.tile_size.is_some() {
+            entries.push(IfdEntry::longs(TAG_TILE_WIDTH, &[chunk_width as u64], false));
+            entries.push(IfdEntry::longs(TAG_TILE_LENGTH, &[chunk_height as u64], false));
+        } else {
+            entries.push(IfdEntry::longs(TAG_ROWS_PER_STRIP, &[chunk_height as u64], false));
+        }
+
+        if self.skew_x == 0.0 && self.skew_y == 0.0 && self.scale_y < 0.0 {
+            entries.push(IfdEntry::doubles(TAG_MODEL_PIXEL_SCALE, &[self.scale_x, -self.scale_y, 0.0]));
+            entries.push(IfdEntry::doubles(TAG_MODEL_TIEPOINT, &[0.0, 0.0, 0.0, self.ip_x, self.ip_y, 0.0]));
+        } else {
+            entries.push(IfdEntry::doubles(TAG_MODEL_TRANSFORMATION, &[
+                self.scale_x, self.skew_x, 0.0, self.ip_x,
+                self.skew_y, self.scale_y, 0.0, self.ip_y,
+                0.0, 0.0, 0.0, 0.0,
+                0.0, 0.0, 0.0, 1.0,
+            ]));
+        }
+
+        let mut geo_keys = vec![(GEO_KEY_RASTER_TYPE, RASTER_PIXEL_IS_AREA)];
+        if self.srid > 0 && self.srid < 32767 {
+            let crs_kind = options.crs_kind.unwrap_or(if GEOGRAPHIC_SRIDS.contains(&self.srid) {
+                CrsKind::Geographic
+            } else {
+                CrsKind::Projected
+            });
+            if crs_kind == CrsKind::Geographic {
+                geo_keys.push((GEO_KEY_MODEL_TYPE, MODEL_TYPE_GEOGRAPHIC));
+                geo_keys.push((GEO_KEY_GEOGRAPHIC_TYPE, self.srid as u16));
+            } else {
+                geo_keys.push((GEO_KEY_MODEL_TYPE, MODEL_TYPE_PROJECTED));
+                geo_keys.push((GEO_KEY_PROJECTED_CS_TYPE, self.srid as u16));
+            }
+        }
+        geo_keys.sort();
+        // header (version 1.1.0, key count) and one (key, location, count, value) per key
+        let mut directory = vec![1, 1, 0, geo_keys.len() as u16];
+        for (key, value) in geo_keys {
+            directory.extend([key, 0, 1, value]);
+        }
+        entries.push(IfdEntry::shorts(TAG_GEO_KEY_DIRECTORY, &directory));
+
+        if let Some(nodata) = nodata {
+            let text = if nodata.is_nan() { "nan".to_string() } else { nodata.to_string() };
+            entries.push(IfdEntry::ascii(TAG_GDAL_NODATA, &text));
+        }
+
+        // offsets are filled in once the layout is known
+        let byte_counts = chunks.iter().map(|c| c.len() as u64).collect::<Vec<_>>();
+        let (offsets_tag, byte_counts_tag) = if options.tile_size.is_some() {
+            (TAG_TILE_OFFSETS, TAG_TILE_BYTE_COUNTS)
+        } else {
+            (TAG_STRIP_OFFSETS, TAG_STRIP_BYTE_COUNTS)
+        };
+
+        let big_tiff = classic_tiff_size(&entries, &byte_counts) > u32::MAX as u64;
+
+        entries.push(IfdEntry::longs(offsets_tag, &vec![0; chunk_count], big_tiff));
+        entries.push(IfdEntry::longs(byte_counts_tag, &byte_counts, big_tiff));
+        entries.sort_by_key(|e| e.tag);
+
+        let (header_len, value_len) = if big_tiff { (16, 8) } else { (8, 4) };
+        let ifd_len = if big_tiff { 8 + 20 * entries.len() + 8 } else { 2 + 12 * entries.len() + 4 };
+        let extra_len = entries.iter().map(|e| e.data.len()).filter(|len| *len > value_len).map(|len| len + len % 2).sum::<usize>();
+
+        let mut offset = (header_len + ifd_len + extra_len) as u64;
+        let offsets = byte_counts.iter().map(|count| {
+            let chunk_offset = offset;
+            offset += count;
+            chunk_offset
+        }).collect::<Vec<_>>();
+        if let Some(entry) = entries.iter_mut().find(|e| e.tag == offsets_tag) {
+            *entry = IfdEntry::longs(offsets_tag, &offsets, big_tiff);
+        }
+
+        let mut head = Vec::with_capacity(header_len + ifd_len + extra_len);
+        head.extend_from_slice(b"II");
+        if big_tiff {
+            head.extend_from_slice(&43u16.to_le_bytes());
+            head.extend_from_slice(&8u16.to_le_bytes());
+            head.extend_from_slice(&0u16.to_le_bytes());
+            head.extend_from_slice(&(header_len as u64).to_le_bytes());
+            head.extend_from_slice(&(entries.len() as u64).to_le_bytes());
+        } else {
+            head.extend_from_slice(&42u16.to_le_bytes());
+            head.extend_from_slice(&(header_len as u32).to_le_bytes());
+            head.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+        }
+
+        let mut extra = Vec::with_capacity(extra_len);
+        let extra_start = (header_len + ifd_len) as u64;
+        for entry in &entries {
+            head.extend_from_slice(&entry.tag.to_le_bytes());
+            head.extend_from_slice(&entry.field_type.to_le_bytes());
+            let mut value = vec![0; value_len];
+            if entry.data.len() <= value_len {
+                value[..entry.data.len()].copy_from_slice(&entry.data);
+            } else {
+                value.copy_from_slice(&(extra_start + extra.len() as u64).to_le_bytes()[..value_len]);
+                extra.extend_from_slice(&entry.data);
+                if extra.len() % 2 == 1 {
+                    extra.push(0);
+                }
+            }
+            head.extend_from_slice(&entry.count.to_le_bytes()[..value_len]);
+            head.extend_from_slice(&value);
+        }
+        // no further IFDs
+        head.extend(std::iter::repeat(0).take(value_len));
+        head.extend_from_slice(&extra);
+
+        let io_error = |e: std::io::Error| GeoTiffError::Io(e.to_string());
+        writer.write_all(&head).map_err(io_error)?;
+        for chunk in &chunks {
+            writer.write_all(chunk).map_err(io_error)?;
+        }
+        writer.flush().map_err(io_error)
+    }
+}
+
+/// Value of a GeoKey that is stored directly in the GeoKey directory
+fn geo_key(directory: &[u64], key: u16) -> Option<u64> {
+    directory.get(4..)?.chunks_exact(4)
+        .find(|entry| entry[0] == key as u64 && entry[1] == 0)
+        .map(|entry| entry[3])
+}
+
+/// EPSG code of the projected or geographic CRS, 0 if missing or user-defined
+fn srid(directory: &[u64]) -> i32 {
+    [GEO_KEY_PROJECTED_CS_TYPE, GEO_KEY_GEOGRAPHIC_TYPE].iter()
+        .filter_map(|key| geo_key(directory, *key))
+        .find(|code| (1..32767).contains(code))
+        .map_or(0, |code| code as i32)
+}
+
+/// Geotransform (`scale_x`, `scale_y`, `ip_x`, `ip_y`, `skew_x`, `skew_y`) of the upper left corner
+fn geotransform(transformation: Option<Vec<f64>>, tiepoint: Option<Vec<f64>>, pixel_scale: Option<Vec<f64>>, directory: Vec<u64>) -> (f64, f64, f64, f64, f64, f64) {
+
+    let (scale_x, scale_y, mut ip_x, mut ip_y, skew_x, skew_y) = match (transformation, tiepoint, pixel_scale) {
+        (Some(m), _, _) if m.len() >= 16 => (m[0], m[5], m[3], m[7], m[1], m[4]),
+        (_, Some(t), Some(s)) if t.len() >= 6 && s.len() >= 2 => {
+            (s[0], -s[1], t[3] - t[0] * s[0], t[4] + t[1] * s[1], 0.0, 0.0)
+        },
+        _ => return (1.0, 1.0, 0.0, 0.0, 0.0, 0.0),
+    };
+
+    // the model coordinates refer to the pixel center
+    if geo_key(&directory, GEO_KEY_RASTER_TYPE) == Some(RASTER_PIXEL_IS_POINT as u64) {
+        ip_x -= 0.5 * (scale_x + skew_x);
+        ip_y -= 0.5 * (skew_y + scale_y);
+    }
+
+    (scale_x, scale_y, ip_x, ip_y, skew_x, skew_y)
+}
+
+/// Bit patterns of the first `count` samples of a decompressed row, with the predictor undone
+fn decode_row(row: &[u8], count: usize, bits: usize, big_endian: bool, predictor: u16, samples_per_pixel: usize) -> Vec<u64> {
+
+    if predictor == PREDICTOR_FLOATING_POINT {
+        // bytes are differenced and stored as planes, most significant byte first
+        let bytes_per_sample = bits / 8;
+        let mut bytes = row[..count * bytes_per_sample].to_vec();
+        for i in samples_per_pixel..bytes.len() {
+            bytes[i] = bytes[i].wrapping_add(bytes[i - samples_per_pixel]);
+        }
+        return (0..count).map(|k| {
+            (0..bytes_per_sample).fold(0, |v, b| (v << 8) | bytes[b * count + k] as u64)
+        }).collect();
+    }
+
+    let mut values = if bits < 8 {
+        let mask = (1 << bits) - 1;
+        (0..count).map(|k| {
+            let bit = k * bits;
+            ((row[bit / 8] >> (8 - bits - bit % 8)) & mask) as u64
+        }).collect::<Vec<_>>()
+    } else {
+        let reader = Reader { bytes: row, big_endian };
+        row.chunks(bits / 8).take(count).map(|v| reader.uint(v)).collect()
+    };
+
+    if predictor == PREDICTOR_HORIZONTAL {
+        let mask = if bits == 64 { u64::MAX } else { (1 << bits) - 1 };
+        for i in samples_per_pixel..values.len() {
+            values[i] = values[i].wrapping_add(values[i - samples_per_pixel]) & mask;
+        }
+    }
+
+    values
+}
+
+/// Interprets the bit pattern of a sample
+fn sample_value(raw: u64, format: u16, bits: usize) -> f64 {
+    match (format, bits) {
+        (SAMPLE_FORMAT_INT, 8) => raw as u8 as i8 as f64,
+        (SAMPLE_FORMAT_INT, 16) => raw as u16 as i16 as f64,
+        (SAMPLE_FORMAT_INT, 32) => raw as u32 as i32 as f64,
+        (SAMPLE_FORMAT_FLOAT, 32) => f32::from_bits(raw as u32) as f64,
+        (SAMPLE_FORMAT_FLOAT, 64) => f64::from_bits(raw),
+        _ => raw as f64,
+    }
+}
+
+/// Decodes PackBits run-length encoded data, `None` if the data decodes to more than `max_len` bytes
+fn packbits_decode(input: &[u8], max_len: usize) -> Option<Vec<u8>> {
+    let mut output = Vec::new();
+    let mut i = 0;
+    while i < input.len() {
+        if output.len() > max_len {
+            return None;
+        }
+        let header = input[i] as i8;
+        i += 1;
+        if header >= 0 {
+            let end = (i + header as usize + 1).min(input.len());
+            output.extend_from_slice(&input[i..end]);
+            i = end;
+        } else if header != -128 {
+            if let Some(&byte) = input.get(i) {
+                output.extend(std::iter::repeat(byte).take((1 - header as isize) as usize));
+            }
+            i += 1;
+        }
+    }
+    if output.len() > max_len {
+        return None;
+    }
+    Some(output)
+}
+
+/// Decodes TIFF LZW data (MSB-first codes of 9 to 12 bits with early change), `None` if the
+/// data is invalid or decodes to more than `max_len` bytes
+fn lzw_decode(input: &[u8], max_len: usize) -> Option<Vec<u8>> {
+
+    const CLEAR: usize = 256;
+    const END_OF_INFORMATION: usize = 257;
+    const FIRST_CODE: usize = 258;
+    const MAX_CODES: usize = 4096;
+
+    // string table as (prefix code, last byte, length)
+    let mut table: Vec<(usize, u8, usize)> = (0..FIRST_CODE).map(|b| (usize::MAX, b as u8, 1)).collect();
+    let mut output = Vec::new();
+    let mut code_width = 9;
+    let mut previous: Option<usize> = None;
+
+    let (mut buffer, mut buffered_bits, mut pos) = (0u32, 0, 0);
+
+    // appends the string of `code` to the output, returns its first byte
+    let write = |output: &mut Vec<u8>, table: &[(usize, u8, usize)], mut code: usize| -> u8 {
+        let start = output.len();
+        output.resize(start + table[code].2, 0);
+        for i in (start..output.len()).rev() {
+            output[i] = table[code].1;
+            code = table[code].0;
+        }
+        output[start]
+    };
+
+    loop {
+        if output.len() > max_len {
+            return None;
+        }
+        while buffered_bits < code_width {
+            let byte = match input.get(pos) {
+                Some(&byte) => byte,
+                None => return Some(output),
+            };
+            buffer = (buffer << 8) | byte as u32;
+            buffered_bits += 8;
+            pos += 1;
+        }
+        let code = ((buffer >> (buffered_bits - code_width)) & ((1 << code_width) - 1)) as usize;
+        buffered_bits -= code_width;
+
+        match code {
+            END_OF_INFORMATION => return Some(output),
+            CLEAR => {
+                table.truncate(FIRST_CODE);
+                code_width = 9;
+                previous = None;
+                continue;
+            },
+            _ => {},
+        }
+
+        match previous {
+            None if code < CLEAR => {
+                output.push(code as u8);
+            },
+            None => return None,
+            Some(previous) => {
+                let first = if code < table.len() {
+                    write(&mut output, &table, code)
+                } else if code == table.len() {
+                    let first = write(&mut output, &table, previous);
+                    output.push(first);
+                    first
+                } else {
+                    return None;
+                };
+                if table.len() < MAX_CODES {
+                    table.push((previous, first, table[previous].2 + 1));
+                }
+            },
+        }
+        previous = Some(code);
+
+        if table.len() + 1 >= 1 << code_width && code_width < 12 {
+            code_width += 1;
+        }
+    }
+}
+
+/// An entry of the written image file directory, with little endian values
+struct IfdEntry {
+    tag: u16,
+    field_type: u16,
+    count: u64,
+    data: Vec<u8>,
+}
+
+impl IfdEntry {
+
+    fn shorts(tag: u16, values: &[u16]) -> Self {
+        IfdEntry { tag, field_type: 3, count: values.len() as u64, data: values.iter().flat_map(|v| v.to_le_bytes()).collect() }
+    }
+
+    /// `LONG8` values in BigTIFF files, `LONG` values otherwise
+    fn longs(tag: u16, values: &[u64], big_tiff: bool) -> Self {
+        let data = if big_tiff {
+            values.iter().flat_map(|v| v.to_le_bytes()).collect()
+        } else {
+            values.iter().flat_map(|v| (*v as u32).to_le_bytes()).collect()
+        };
+        IfdEntry { tag, field_type: if big_tiff { 16 } else { 4 }, count: values.len() as u64, data }
+    }
+
+    fn doubles(tag: u16, values: &[f64]) -> Self {
+        IfdEntry { tag, field_type: 12, count: values.len() as u64, data: values.iter().flat_map(|v| v.to_le_bytes()).collect() }
+    }
+
+    /// NUL-terminated `ASCII` value
+    fn ascii(tag: u16, value: &str) -> Self {
+        let mut data = value.as_bytes().to_vec();
+        data.push(0);
+        IfdEntry { tag, field_type: 2, count: data.len() as u64, data }
+    }
+}
+
+/// Upper bound of the size of a classic TIFF file with the given entries plus the chunk
+/// offsets and byte counts
+fn classic_tiff_size(entries: &[IfdEntry], byte_counts: &[u64]) -> u64 {
+    let extra_len = entries.iter().map(|e| e.data.len()).filter(|len| *len > 4).map(|len| len + 1).sum::<usize>();
+    let head_len = 8 + 2 + (entries.len() + 2) * 12 + 4 + extra_len + 2 * 4 * byte_counts.len();
+    head_len as u64 + byte_counts.iter().sum::<u64>()
+}
+
+/// `SampleFormat` and `BitsPerSample` of a pixel type
+fn sample_format(pixtype: PixType) -> (u16, u16) {
+    match pixtype {
+        PixType::Bool1Bit(_) => (SAMPLE_FORMAT_UINT, 1),
+        PixType::UInt2(_) => (SAMPLE_FORMAT_UINT, 2),
+        PixType::UInt4(_) => (SAMPLE_FORMAT_UINT, 4),
+        PixType::Int8(_) => (SAMPLE_FORMAT_INT, 8),
+        PixType::UInt8(_) => (SAMPLE_FORMAT_UINT, 8),
+        PixType::Int16(_) => (SAMPLE_FORMAT_INT, 16),
+        PixType::UInt16(_) => (SAMPLE_FORMAT_UINT, 16),
+        PixType::Int32(_) => (SAMPLE_FORMAT_INT, 32),
+        PixType::UInt32(_) => (SAMPLE_FORMAT_UINT, 32),
+        PixType::Float32(_) => (SAMPLE_FORMAT_FLOAT, 32),
+        PixType::Float64(_) => (SAMPLE_FORMAT_FLOAT, 64),
+    }
+}
+
+/// Appends the samples of one row, little endian or packed MSB-first for less than 8 bits
+fn encode_row<I: Iterator<Item = f64>>(output: &mut Vec<u8>, samples: I, pixtype: PixType, bits: usize) {
+
+    let raw = samples.map(|v| match pixtype {
+        PixType::Float32(_) => (v as f32).to_bits() as u64,
+        PixType::Float64(_) => v.to_bits(),
+        PixType::Int8(_) | PixType::Int16(_) | PixType::Int32(_) => v as i64 as u64,
+        _ => v as u64,
+    });
+
+    if bits >= 8 {
+        for value in raw {
+            output.extend_from_slice(&value.to_le_bytes()[..bits / 8]);
+        }
+        return;
+    }
+
+    let (mut byte, mut used) = (0u8, 0);
+    for value in raw {
+        byte |= (value as u8 & ((1 << bits) - 1)) << (8 - bits - used);
+        used += bits;
+        if used == 8 {
+            output.push(byte);
+            byte = 0;
+            used = 0;
+        }
+    }
+    if used > 0 {
+        output.push(byte);
+    }
+}
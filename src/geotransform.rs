@@ -0,0 +1,89 @@
+//! Conversion between pixel and world coordinates
+
+use crate::Raster;
+
+/// Georeference for image formats that don't store one themselves
+#[derive(Debug, Copy, Clone, PartialEq, PartialOrd)]
+pub enum GeoReference<'a> {
+    /// Geotransform with the same meaning as the fields of `Raster`
+    GeoTransform { scale_x: f64, scale_y: f64, ip_x: f64, ip_y: f64, skew_x: f64, skew_y: f64 },
+    /// Contents of an ESRI world file (`.pgw`, `.tfw`, `.wld`, ...): six lines with the pixel
+    /// size in x, the rotation terms, the pixel size in y and the center of the upper-left pixel
+    WorldFile(&'a str),
+}
+
+impl GeoReference<'_> {
+
+    /// Returns `(scale_x, scale_y, ip_x, ip_y, skew_x, skew_y)`, or `None` if the world
+    /// file doesn't consist of six numbers
+    pub(crate) fn geotransform(&self) -> Option<(f64, f64, f64, f64, f64, f64)> {
+        match *self {
+            GeoReference::GeoTransform { scale_x, scale_y, ip_x, ip_y, skew_x, skew_y } => {
+                Some((scale_x, scale_y, ip_x, ip_y, skew_x, skew_y))
+            },
+            GeoReference::WorldFile(contents) => {
+                let values = contents
+                    .split_whitespace()
+                    .map(|v| v.parse::<f64>().ok())
+                    .collect::<Option<Vec<_>>>()?;
+                match values[..] {
+                    // world files reference the center of the upper-left pixel, not its corner
+                    [scale_x, skew_y, skew_x, scale_y, center_x, center_y] => Some((
+                        scale_x,
+                        scale_y,
+                        center_x - 0.5 * scale_x - 0.5 * skew_x,
+                        center_y - 0.5 * skew_y - 0.5 * scale_y,
+                        skew_x,
+                        skew_y,
+                    )),
+                    _ => None,
+                }
+            },
+        }
+    }
+}
+
+impl Raster {
+
+    /// Converts a pixel coordinate (column, row) to a world coordinate (x, y),
+    /// like `ST_RasterToWorldCoord`. `(0.0, 0.0)` is the upper-left corner of
+    /// the upper-left pixel, `(0.5, 0.5)` its center.
+    pub fn pixel_to_world(&self, col: f64, row: f64) -> (f64, f64) {
+        (
+            self.ip_x + col * self.scale_x + row * self.skew_x,
+            self.ip_y + col * self.skew_y + row * self.scale_y,
+        )
+    }
+
+    /// Converts a world coordinate (x, y) to a fractional pixel coordinate (column, row),
+    /// like `ST_WorldToRasterCoord`. Returns `None` if the geotransform can't be
+    /// inverted (i.e. if the scale is zero).
+    pub fn world_to_pixel(&self, x: f64, y: f64) -> Option<(f64, f64)> {
+        let det = self.scale_x * self.scale_y - self.skew_x * self.skew_y;
+        if det == 0.0 || !det.is_finite() {
+            return None;
+        }
+        let dx = x - self.ip_x;
+        let dy = y - self.ip_y;
+        Some((
+            (self.scale_y * dx - self.skew_x * dy) / det,
+            (self.scale_x * dy - self.skew_y * dx) / det,
+        ))
+    }
+
+    /// Returns the bounding box `(min_x, min_y, max_x, max_y)` of the raster in world coordinates
+    pub fn extent(&self) -> (f64, f64, f64, f64) {
+        let w = self.width as f64;
+        let h = self.height as f64;
+        let corners = [
+            self.pixel_to_world(0.0, 0.0),
+            self.pixel_to_world(w, 0.0),
+            self.pixel_to_world(0.0, h),
+            self.pixel_to_world(w, h),
+        ];
+        corners.iter().fold(
+            (f64::INFINITY, f64::INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY),
+            |(min_x, min_y, max_x, max_y), &(x, y)| (min_x.min(x), min_y.min(y), max_x.max(x), max_y.max(y)),
+        )
+    }
+}
@@ -32,18 +32,18 @@
 //!     width: 2,               // pixel columns
 //!     height: 2,              // rows
 //!     bands: vec![RasterBand {
-//!         is_nodata_value: false           // true only if entire band is NODATA
+//!         is_nodata_value: false,          // true only if entire band is NODATA
 //!         data: RasterDataSource::InMemory(
 //!             InMemoryRasterData::UInt8 {
 //!                 data: bytes,
-//!                 nodata
+//!                 nodata: None,
 //!             }
 //!         ),
 //!     }],
 //! };
 //! 
 //! assert_eq!(
-//!     raster.to_wkb_string(), 
+//!     raster.try_to_wkb_string().unwrap(),
 //!     String::from("00000000013FF00000000000003FF00000000000000000000000000000000000000000000000000000000000000000000000000000000010E600020002040000010100")
 //! );
 //! ```
@@ -78,7 +78,7 @@
 //!         data: RasterDataSource::InMemory(
 //!             InMemoryRasterData::UInt8 {
 //!                 data: bytes,
-//!                 nodata
+//!                 nodata: None,
 //!             }
 //!         ),
 //!     }],
@@ -203,13 +203,101 @@
 //  +---------------+-------------+-----------------------------------+
 // ```
 
-use std::path::PathBuf;
+use std::fmt;
+use std::path::{Path, PathBuf};
 use std::ffi::CString;
 
+/// Matches on all variants of an `InMemoryRasterData`, binding the
+/// pixel rows to `$data` (used for operations that don't depend on the type)
+macro_rules! match_in_memory_data {
+    ($value:expr, $data:ident => $body:expr) => {
+        match $value {
+            InMemoryRasterData::Bool1Bit { data: $data, .. } => $body,
+            InMemoryRasterData::UInt2 { data: $data, .. } => $body,
+            InMemoryRasterData::UInt4 { data: $data, .. } => $body,
+            InMemoryRasterData::Int8 { data: $data, .. } => $body,
+            InMemoryRasterData::UInt8 { data: $data, .. } => $body,
+            InMemoryRasterData::Int16 { data: $data, .. } => $body,
+            InMemoryRasterData::UInt16 { data: $data, .. } => $body,
+            InMemoryRasterData::Int32 { data: $data, .. } => $body,
+            InMemoryRasterData::UInt32 { data: $data, .. } => $body,
+            InMemoryRasterData::Float32 { data: $data, .. } => $body,
+            InMemoryRasterData::Float64 { data: $data, .. } => $body,
+        }
+    };
+}
+
+/// Rebuilds an `InMemoryRasterData` of the same variant (and nodata value),
+/// with the pixel rows bound to `$data` and replaced by the result of `$body`
+macro_rules! map_in_memory_data {
+    ($value:expr, $data:ident => $body:expr) => {
+        match $value {
+            InMemoryRasterData::Bool1Bit { data: $data, nodata } => InMemoryRasterData::Bool1Bit { data: $body, nodata: *nodata },
+            InMemoryRasterData::UInt2 { data: $data, nodata } => InMemoryRasterData::UInt2 { data: $body, nodata: *nodata },
+            InMemoryRasterData::UInt4 { data: $data, nodata } => InMemoryRasterData::UInt4 { data: $body, nodata: *nodata },
+            InMemoryRasterData::Int8 { data: $data, nodata } => InMemoryRasterData::Int8 { data: $body, nodata: *nodata },
+            InMemoryRasterData::UInt8 { data: $data, nodata } => InMemoryRasterData::UInt8 { data: $body, nodata: *nodata },
+            InMemoryRasterData::Int16 { data: $data, nodata } => InMemoryRasterData::Int16 { data: $body, nodata: *nodata },
+            InMemoryRasterData::UInt16 { data: $data, nodata } => InMemoryRasterData::UInt16 { data: $body, nodata: *nodata },
+            InMemoryRasterData::Int32 { data: $data, nodata } => InMemoryRasterData::Int32 { data: $body, nodata: *nodata },
+            InMemoryRasterData::UInt32 { data: $data, nodata } => InMemoryRasterData::UInt32 { data: $body, nodata: *nodata },
+            InMemoryRasterData::Float32 { data: $data, nodata } => InMemoryRasterData::Float32 { data: $body, nodata: *nodata },
+            InMemoryRasterData::Float64 { data: $data, nodata } => InMemoryRasterData::Float64 { data: $body, nodata: *nodata },
+        }
+    };
+}
+
 #[macro_use]
 mod parse_memory_data;
 mod big_endian;
 mod little_endian;
+mod reclass;
+mod convert;
+mod geotransform;
+mod resample;
+mod alignment;
+mod mosaic;
+mod geometry;
+mod rasterize;
+mod clip;
+mod window;
+mod polygonize;
+mod contour;
+mod proximity;
+mod zonal;
+mod colormap;
+mod png;
+mod geotiff;
+mod aaigrid;
+mod envi;
+mod offline;
+mod mmap;
+mod typed;
+mod builder;
+
+pub use crate::reclass::{ReclassError, ReclassExpr, ReclassRange};
+pub use crate::convert::{ConvertError, ConversionPolicy, OutOfRange, Rounding, Rescale, NodataMapping};
+pub use crate::resample::{ResampleError, ResampleAlgorithm};
+pub use crate::alignment::{Misalignment, same_alignment};
+pub use crate::geotransform::GeoReference;
+pub use crate::mosaic::{MosaicError, MergePolicy, mosaic};
+pub use crate::geometry::{Geometry, GeometryError, Coord};
+pub use crate::rasterize::{BurnRule, RasterizeError};
+pub use crate::clip::ClipError;
+pub use crate::window::WindowError;
+pub use crate::polygonize::{PolygonizeError, Connectivity};
+pub use crate::contour::{ContourError, ContourLevels, ContourLine, ContourPolygon};
+pub use crate::proximity::{ProximityError, DistanceUnits};
+pub use crate::zonal::{ZonalError, ZonalStats, PixelWeight};
+pub use crate::colormap::{ColorMapError, ColorMap, ColorMapMethod, ColorStop, StopValue};
+pub use crate::png::PngError;
+pub use crate::geotiff::{GeoTiffError, GeoTiffOptions, CrsKind};
+pub use crate::aaigrid::AaiGridError;
+pub use crate::envi::{EnviError, Interleave};
+pub use crate::offline::{MaterializeError, OfflineResolver, GeoTiffResolver, EnviResolver, FileResolver, OutDbError, OutDbFormat};
+pub use crate::mmap::{MapError, MappedBand};
+pub use crate::typed::{Pixel, TypedBand, UInt2, UInt4};
+pub use crate::builder::{BuildError, RasterBuilder};
 
 #[derive(Debug, Clone, PartialEq, PartialOrd, Hash, Eq, Ord)]
 pub enum ParseError {
@@ -221,6 +309,60 @@ pub enum ParseError {
     PathContainsNonUTF8Chars(CString),
 }
 
+#[derive(Debug, Clone, PartialEq, PartialOrd, Hash, Eq, Ord)]
+pub enum EncodeError {
+    /// The out-db path of the band at the given index contains a NUL byte (WKB paths are
+    /// NUL-terminated)
+    PathContainsNul { band: usize, path: PathBuf },
+    /// The out-db path of the band at the given index is not valid Unicode (only on platforms
+    /// where paths aren't byte strings)
+    PathContainsNonUTF8Chars { band: usize, path: PathBuf },
+}
+
+impl fmt::Display for EncodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EncodeError::PathContainsNul { band, path } => write!(f, "out-db path {:?} of band {} contains a NUL byte", path, band),
+            EncodeError::PathContainsNonUTF8Chars { band, path } => write!(f, "out-db path {:?} of band {} is not valid Unicode", path, band),
+        }
+    }
+}
+
+impl std::error::Error for EncodeError { }
+
+/// Bytes of an out-db path as stored in WKB: the bytes of the `OsStr` on Unix (file names
+/// don't have to be UTF-8), UTF-8 on other platforms
+#[cfg(unix)]
+fn path_to_bytes(path: &Path) -> Option<Vec<u8>> {
+    use std::os::unix::ffi::OsStrExt;
+    Some(path.as_os_str().as_bytes().to_vec())
+}
+
+#[cfg(not(unix))]
+fn path_to_bytes(path: &Path) -> Option<Vec<u8>> {
+    path.to_str().map(|s| s.as_bytes().to_vec())
+}
+
+/// Inverse of `path_to_bytes`
+#[cfg(unix)]
+fn path_from_bytes(bytes: &[u8]) -> Option<PathBuf> {
+    use std::os::unix::ffi::OsStrExt;
+    Some(PathBuf::from(std::ffi::OsStr::from_bytes(bytes)))
+}
+
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: &[u8]) -> Option<PathBuf> {
+    std::str::from_utf8(bytes).ok().map(PathBuf::from)
+}
+
+/// NUL-terminated out-db path of the band at index `band`, or an error if the path can't be encoded
+fn offline_path_cstring(band: usize, path: PathBuf) -> Result<CString, EncodeError> {
+    match path_to_bytes(&path) {
+        None => Err(EncodeError::PathContainsNonUTF8Chars { band, path }),
+        Some(bytes) => CString::new(bytes).map_err(|_| EncodeError::PathContainsNul { band, path }),
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, PartialOrd, Hash, Eq, Ord)]
 pub struct BoolParseError([u8;2], u8);
 
@@ -346,10 +488,56 @@ fn take_slice_8_bytes(input: &[u8]) -> Result<([u8;16], &[u8]), ParseError> {
 impl Raster {
 
     /// Outputs the raster as a Well-Known-Binary string, ready to be used in SQL statements
+    ///
+    /// # Panics
+    ///
+    /// Panics if an out-db path contains a NUL byte or, on platforms other than Unix, isn't
+    /// valid Unicode. Use `try_to_wkb_string` to get an `EncodeError` instead.
+    #[deprecated(note = "panics on out-db paths that can't be encoded, use `try_to_wkb_string`")]
     pub fn to_wkb_string(self) -> String {
+        match self.try_to_wkb_string() {
+            Ok(string) => string,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Outputs the raster as a Well-Known-Binary string, or an error if an out-db path contains
+    /// a NUL byte or, on platforms other than Unix, isn't valid Unicode.
+    ///
+    /// On Unix, out-db paths are written as their raw bytes, so file names that aren't UTF-8
+    /// (e.g. Latin-1) survive the round trip through `from_wkb_string`.
+    ///
+    /// ```rust
+    /// use std::path::PathBuf;
+    /// use wkb_raster::{Raster, RasterBand, RasterDataSource, OfflineRasterData, PixType, Endian, EncodeError};
+    ///
+    /// let raster = |path: PathBuf| Raster {
+    ///     endian: Endian::Big, version: 0,
+    ///     scale_x: 1.0, scale_y: -1.0, ip_x: 0.0, ip_y: 0.0, skew_x: 0.0, skew_y: 0.0,
+    ///     srid: 4326, width: 1, height: 1,
+    ///     bands: vec![RasterBand {
+    ///         is_nodata_value: false,
+    ///         data: RasterDataSource::Offline(OfflineRasterData { band: 0, path, pixtype: PixType::UInt8(None) }),
+    ///     }],
+    /// };
+    ///
+    /// let invalid = raster(PathBuf::from("/data/a\0b.tif"));
+    /// assert_eq!(invalid.try_to_wkb_string(), Err(EncodeError::PathContainsNul { band: 0, path: PathBuf::from("/data/a\0b.tif") }));
+    ///
+    /// # #[cfg(unix)] {
+    /// use std::ffi::OsStr;
+    /// use std::os::unix::ffi::OsStrExt;
+    ///
+    /// // "/data/zürich.tif" in Latin-1
+    /// let latin1 = raster(PathBuf::from(OsStr::from_bytes(b"/data/z\xfcrich.tif")));
+    /// let wkb = latin1.clone().try_to_wkb_string().unwrap();
+    /// assert_eq!(Raster::from_wkb_string(wkb.as_bytes()).unwrap(), latin1);
+    /// # }
+    /// ```
+    pub fn try_to_wkb_string(self) -> Result<String, EncodeError> {
         match self.endian {
-            Endian::Big => self.to_wkb_string_big_endian(),
-            Endian::Little => self.to_wkb_string_little_endian(),
+            Endian::Big => self.into_wkb_string_big_endian(),
+            Endian::Little => self.into_wkb_string_little_endian(),
         }
     }
 
@@ -483,7 +671,7 @@ impl Raster {
         })
     }
 
-    fn to_wkb_string_big_endian(self) -> String {
+    fn into_wkb_string_big_endian(self) -> Result<String, EncodeError> {
 
         use crate::big_endian::*;
 
@@ -510,14 +698,14 @@ impl Raster {
         // write height
         write_u16_be(&mut string_bytes, self.height);
 
-        for band in self.bands {
+        for (i, band) in self.bands.into_iter().enumerate() {
 
             // write band config (1 byte)    
-            let config = 0 |
+            // (bit 4 is reserved and always 0)
+            let config =
                 (band.data.is_offline() as u8) << 7 |
                 (band.data.get_pixtype().has_nodata_value() as u8) << 6 |
                 (band.is_nodata_value as u8) << 5 |
-                (0_u8 << 4) |
                 band.data.get_pixtype().get_type() & 0b00001111;
 
             write_u8_be(&mut string_bytes, config);
@@ -526,13 +714,13 @@ impl Raster {
             string_bytes.append(&mut band.data.get_pixtype().get_nodata_value_as_string_big_endian());
 
             // write raster data
-            string_bytes.append(&mut band.data.to_wkb_string_big_endian());
+            string_bytes.append(&mut band.data.into_wkb_string_big_endian(i)?);
         }
 
-        unsafe { String::from_utf8_unchecked(string_bytes) }
+        Ok(unsafe { String::from_utf8_unchecked(string_bytes) })
     }
 
-    fn to_wkb_string_little_endian(self) -> String {
+    fn into_wkb_string_little_endian(self) -> Result<String, EncodeError> {
         
         use self::little_endian::*;
 
@@ -559,17 +747,17 @@ impl Raster {
         // write height
         write_u16_le(&mut string_bytes, self.height);
 
-        for band in self.bands {
+        for (i, band) in self.bands.into_iter().enumerate() {
 
             // expected: 0x45 = 69 = 01000101
             // got: 00 = 0 = 01000101
 
             // write band config (1 byte)    
-            let config = 0 |
+            // (bit 4 is reserved and always 0)
+            let config =
                 (band.data.is_offline() as u8) << 7 |
                 (band.data.get_pixtype().has_nodata_value() as u8) << 6 |
                 (band.is_nodata_value as u8) << 5 |
-                (0_u8 << 4) |
                 band.data.get_pixtype().get_type() & 0b00001111;
 
             write_u8_le(&mut string_bytes, config);
@@ -578,10 +766,10 @@ impl Raster {
             string_bytes.append(&mut band.data.get_pixtype().get_nodata_value_as_string_little_endian());
 
             // write raster data
-            string_bytes.append(&mut band.data.to_wkb_string_little_endian());
+            string_bytes.append(&mut band.data.into_wkb_string_little_endian(i)?);
         }
 
-        unsafe { String::from_utf8_unchecked(string_bytes) }
+        Ok(unsafe { String::from_utf8_unchecked(string_bytes) })
     }
 }
 
@@ -972,9 +1160,8 @@ impl RasterDataSource {
 
         let path = CStr::from_bytes_with_nul(&path_bytes)
         .map_err(|_| ParseError::FromBytesWithNulError(path_bytes.clone()))?;
-        let path = CString::from(path);
-        let path = PathBuf::from(path.clone().into_string()
-            .map_err(|_| ParseError::PathContainsNonUTF8Chars(path.to_owned()))?);
+        let path = path_from_bytes(path.to_bytes())
+            .ok_or_else(|| ParseError::PathContainsNonUTF8Chars(CString::from(path)))?;
         
         Ok((RasterDataSource::Offline(OfflineRasterData {
             band,
@@ -1007,9 +1194,8 @@ impl RasterDataSource {
 
         let path = CStr::from_bytes_with_nul(&path_bytes)
         .map_err(|_| ParseError::FromBytesWithNulError(path_bytes.clone()))?;
-        let path = CString::from(path);
-        let path = PathBuf::from(path.clone().into_string()
-            .map_err(|_| ParseError::PathContainsNonUTF8Chars(path.to_owned()))?);
+        let path = path_from_bytes(path.to_bytes())
+            .ok_or_else(|| ParseError::PathContainsNonUTF8Chars(CString::from(path)))?;
         
         Ok((RasterDataSource::Offline(OfflineRasterData {
             band,
@@ -1160,9 +1346,49 @@ impl PixType {
         }
     }
 
+    /// Returns the nodata value converted to a `f64`, if any
+    pub fn nodata_f64(&self) -> Option<f64> {
+        use self::PixType::*;
+        match *self {
+            Bool1Bit(n) => n.map(|v| v as u8 as f64),
+            UInt2(n) | UInt4(n) | UInt8(n) => n.map(|v| v as f64),
+            Int8(n) => n.map(|v| v as f64),
+            Int16(n) => n.map(|v| v as f64),
+            UInt16(n) => n.map(|v| v as f64),
+            Int32(n) => n.map(|v| v as f64),
+            UInt32(n) => n.map(|v| v as f64),
+            Float32(n) => n.map(|v| v as f64),
+            Float64(n) => n,
+        }
+    }
+
+    /// Returns the same pixel type with the nodata value set to `nodata`
+    /// (converted with a saturating cast) or removed if `nodata` is `None`
+    pub fn with_nodata_f64(&self, nodata: Option<f64>) -> PixType {
+        use self::PixType::*;
+        match *self {
+            Bool1Bit(_) => Bool1Bit(nodata.map(|v| v != 0.0)),
+            UInt2(_) => UInt2(nodata.map(|v| (v as u8).min(3))),
+            UInt4(_) => UInt4(nodata.map(|v| (v as u8).min(15))),
+            Int8(_) => Int8(nodata.map(|v| v as i8)),
+            UInt8(_) => UInt8(nodata.map(|v| v as u8)),
+            Int16(_) => Int16(nodata.map(|v| v as i16)),
+            UInt16(_) => UInt16(nodata.map(|v| v as u16)),
+            Int32(_) => Int32(nodata.map(|v| v as i32)),
+            UInt32(_) => UInt32(nodata.map(|v| v as u32)),
+            Float32(_) => Float32(nodata.map(|v| v as f32)),
+            Float64(_) => Float64(nodata),
+        }
+    }
+
+    /// Returns `true` for `Float32` and `Float64`
+    pub fn is_floating_point(&self) -> bool {
+        matches!(self, PixType::Float32(_) | PixType::Float64(_))
+    }
+
     pub fn has_nodata_value(&self) -> bool {
         use self::PixType::*;
-        match self {
+        matches!(self,
             | Bool1Bit(Some(_))
             | UInt2(Some(_))
             | UInt4(Some(_))
@@ -1173,9 +1399,8 @@ impl PixType {
             | Int32(Some(_))
             | UInt32(Some(_))
             | Float32(Some(_))
-            | Float64(Some(_)) => true,
-            _ => false,
-        }
+            | Float64(Some(_))
+        )
     }
 
     #[inline]
@@ -1247,7 +1472,7 @@ pub enum RasterDataSource {
 pub struct OfflineRasterData {
     /// 0-based band number to use from the set available in the external file                             
     pub band: i8, 
-    /// Path to data file
+    /// Path to data file, relative paths are resolved by the `OfflineResolver`
     pub path: PathBuf,
     /// Type of the pixels to read
     pub pixtype: PixType,
@@ -1286,12 +1511,93 @@ impl InMemoryRasterData {
             InMemoryRasterData::Float64 { nodata, .. } => PixType::Float64(*nodata),
         }
     }
+
+    /// Returns the number of pixel columns (0 if the band has no rows)
+    pub fn width(&self) -> usize {
+        match_in_memory_data!(self, data => data.first().map(|r| r.len()).unwrap_or(0))
+    }
+
+    /// Returns the number of pixel rows
+    pub fn height(&self) -> usize {
+        match_in_memory_data!(self, data => data.len())
+    }
+
+    /// Returns the pixel value at `(col, row)` as a `f64`, regardless of
+    /// whether the value is the nodata value. Returns `None` if out of bounds.
+    pub fn get_f64(&self, col: usize, row: usize) -> Option<f64> {
+        match self {
+            InMemoryRasterData::Bool1Bit { data, .. } => data.get(row)?.get(col).map(|v| *v as u8 as f64),
+            InMemoryRasterData::UInt2 { data, .. } => data.get(row)?.get(col).map(|v| *v as f64),
+            InMemoryRasterData::UInt4 { data, .. } => data.get(row)?.get(col).map(|v| *v as f64),
+            InMemoryRasterData::Int8 { data, .. } => data.get(row)?.get(col).map(|v| *v as f64),
+            InMemoryRasterData::UInt8 { data, .. } => data.get(row)?.get(col).map(|v| *v as f64),
+            InMemoryRasterData::Int16 { data, .. } => data.get(row)?.get(col).map(|v| *v as f64),
+            InMemoryRasterData::UInt16 { data, .. } => data.get(row)?.get(col).map(|v| *v as f64),
+            InMemoryRasterData::Int32 { data, .. } => data.get(row)?.get(col).map(|v| *v as f64),
+            InMemoryRasterData::UInt32 { data, .. } => data.get(row)?.get(col).map(|v| *v as f64),
+            InMemoryRasterData::Float32 { data, .. } => data.get(row)?.get(col).map(|v| *v as f64),
+            InMemoryRasterData::Float64 { data, .. } => data.get(row)?.get(col).copied(),
+        }
+    }
+
+    /// Returns the pixel value at `(col, row)` as a `f64`, or `None` if the pixel
+    /// is out of bounds or equal to the nodata value of the band
+    pub fn get_value(&self, col: usize, row: usize) -> Option<f64> {
+        let value = self.get_f64(col, row)?;
+        match self.get_pixtype().nodata_f64() {
+            Some(nodata) if nodata == value || (nodata.is_nan() && value.is_nan()) => None,
+            _ => Some(value),
+        }
+    }
+
+    /// Returns the pixels in the window starting at `(col_off, row_off)` with the given
+    /// size, or `None` if the window is not completely inside of the band
+    pub fn subset(&self, col_off: usize, row_off: usize, width: usize, height: usize) -> Option<Self> {
+        let fits = |off: usize, len: usize, size: usize| off.checked_add(len).map_or(false, |end| end <= size);
+        if !fits(col_off, width, self.width()) || !fits(row_off, height, self.height()) {
+            return None;
+        }
+        Some(map_in_memory_data!(self, data => {
+            data[row_off..row_off + height]
+                .iter()
+                .map(|row| row[col_off..col_off + width].to_vec())
+                .collect()
+        }))
+    }
+
+    /// Creates a new band of `pixtype` (including its nodata value) with
+    /// `width * height` pixels, calling `f(col, row)` for each pixel.
+    ///
+    /// Values are converted with a saturating cast, fractional parts are truncated.
+    /// `UInt2` and `UInt4` values are saturated at 3 and 15, `Bool1Bit` values are
+    /// `true` for any non-zero value.
+    pub fn from_fn_f64<F: FnMut(usize, usize) -> f64>(pixtype: PixType, width: usize, height: usize, mut f: F) -> Self {
+
+        fn gen<T, F: FnMut(usize, usize) -> f64, C: Fn(f64) -> T>(width: usize, height: usize, f: &mut F, cast: C) -> Vec<Vec<T>> {
+            (0..height).map(|row| (0..width).map(|col| cast(f(col, row))).collect()).collect()
+        }
+
+        match pixtype {
+            PixType::Bool1Bit(nodata) => InMemoryRasterData::Bool1Bit { data: gen(width, height, &mut f, |v| v != 0.0), nodata },
+            PixType::UInt2(nodata) => InMemoryRasterData::UInt2 { data: gen(width, height, &mut f, |v| (v as u8).min(3)), nodata },
+            PixType::UInt4(nodata) => InMemoryRasterData::UInt4 { data: gen(width, height, &mut f, |v| (v as u8).min(15)), nodata },
+            PixType::Int8(nodata) => InMemoryRasterData::Int8 { data: gen(width, height, &mut f, |v| v as i8), nodata },
+            PixType::UInt8(nodata) => InMemoryRasterData::UInt8 { data: gen(width, height, &mut f, |v| v as u8), nodata },
+            PixType::Int16(nodata) => InMemoryRasterData::Int16 { data: gen(width, height, &mut f, |v| v as i16), nodata },
+            PixType::UInt16(nodata) => InMemoryRasterData::UInt16 { data: gen(width, height, &mut f, |v| v as u16), nodata },
+            PixType::Int32(nodata) => InMemoryRasterData::Int32 { data: gen(width, height, &mut f, |v| v as i32), nodata },
+            PixType::UInt32(nodata) => InMemoryRasterData::UInt32 { data: gen(width, height, &mut f, |v| v as u32), nodata },
+            PixType::Float32(nodata) => InMemoryRasterData::Float32 { data: gen(width, height, &mut f, |v| v as f32), nodata },
+            PixType::Float64(nodata) => InMemoryRasterData::Float64 { data: gen(width, height, &mut f, |v| v), nodata },
+        }
+    }
 }
 
 impl RasterDataSource {
     
-    /// Outputs the string to put in the SQL query (big endian)
-    fn to_wkb_string_big_endian(self) -> Vec<u8> {
+    /// Outputs the string to put in the SQL query (big endian), `index` is the band index
+    /// reported in errors
+    fn into_wkb_string_big_endian(self, index: usize) -> Result<Vec<u8>, EncodeError> {
 
         use self::RasterDataSource::*;
         use crate::big_endian::*;
@@ -1303,8 +1609,7 @@ impl RasterDataSource {
                 // write band id
                 write_i8_be(&mut s, band);
                 // write file path
-                let path: Vec<u8> = path.as_os_str().to_string_lossy().as_bytes().to_vec();
-                let cstring = unsafe { CString::from_vec_unchecked(path) };
+                let cstring = offline_path_cstring(index, path)?;
                 for byte in cstring.to_bytes_with_nul() {
                     write_u8_be(&mut s, *byte);
                 }
@@ -1326,11 +1631,12 @@ impl RasterDataSource {
             },
         }
 
-        s
+        Ok(s)
     }
 
-    /// Outputs the string to put in the SQL query (little endian)
-    fn to_wkb_string_little_endian(self) -> Vec<u8> {
+    /// Outputs the string to put in the SQL query (little endian), `index` is the band index
+    /// reported in errors
+    fn into_wkb_string_little_endian(self, index: usize) -> Result<Vec<u8>, EncodeError> {
 
         use self::RasterDataSource::*;
         use self::little_endian::*;
@@ -1342,8 +1648,7 @@ impl RasterDataSource {
                 // write band id
                 write_i8_le(&mut s, band);
                 // write file path
-                let path: Vec<u8> = path.as_os_str().to_string_lossy().as_bytes().to_vec();
-                let cstring = unsafe { CString::from_vec_unchecked(path) };
+                let cstring = offline_path_cstring(index, path)?;
                 for byte in cstring.into_bytes_with_nul() {
                     let byte: u8 = byte; // rustc is dumb
                     write_u8_le(&mut s, byte);
@@ -1366,6 +1671,6 @@ impl RasterDataSource {
             },
         }
 
-        s
+        Ok(s)
     }
 }
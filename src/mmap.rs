@@ -0,0 +1,350 @@
+//! Memory-mapped pixel access to uncompressed out-db files
+
+use std::fmt;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use memmap2::Mmap;
+use crate::{Raster, RasterDataSource, InMemoryRasterData, PixType, Misalignment, FileResolver};
+use crate::{GeoTiffError, EnviError};
+use crate::offline::{is_tiff_path, envi_header_path};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MapError {
+    /// The band index does not exist in the raster
+    BandIndexOutOfRange(usize),
+    /// Band at the given index is stored in-db, there is no file to map
+    BandIsInMemory(usize),
+    /// The out-db band number is negative
+    InvalidBandNumber(i8),
+    /// Opening, reading or mapping the file failed, contains the error message
+    Io { path: PathBuf, message: String },
+    /// The file is not a valid GeoTIFF
+    GeoTiff { path: PathBuf, error: GeoTiffError },
+    /// The file is not a valid ENVI raster
+    Envi { path: PathBuf, error: EnviError },
+    /// Only uncompressed GeoTIFFs without predictor and with at least 8 bits per sample can be mapped
+    UnsupportedLayout { path: PathBuf },
+    /// The file doesn't have a band with the given 0-based number
+    FileBandOutOfRange { path: PathBuf, band: usize },
+    /// The pixel grid of the file isn't aligned with the raster
+    NotAligned { path: PathBuf, misalignment: Misalignment },
+    /// The samples of the file don't have the pixel type of the out-db band
+    PixTypeMismatch { path: PathBuf, expected: PixType, found: PixType },
+}
+
+impl fmt::Display for MapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MapError::BandIndexOutOfRange(band) => write!(f, "band {} does not exist", band),
+            MapError::BandIsInMemory(band) => write!(f, "band {} is stored in-db and has no file to map", band),
+            MapError::InvalidBandNumber(band) => write!(f, "out-db band number {} is negative", band),
+            MapError::Io { path, message } => write!(f, "mapping {} failed: {}", path.display(), message),
+            MapError::GeoTiff { path, error } => write!(f, "{}: {}", path.display(), error),
+            MapError::Envi { path, error } => write!(f, "{}: {}", path.display(), error),
+            MapError::UnsupportedLayout { path } => write!(f, "{} is compressed or has less than 8 bits per sample and can't be mapped", path.display()),
+            MapError::FileBandOutOfRange { path, band } => write!(f, "{} has no band {}", path.display(), band),
+            MapError::NotAligned { path, misalignment } => write!(f, "pixel grid of {} is not aligned with the raster: {:?}", path.display(), misalignment),
+            MapError::PixTypeMismatch { path, expected, found } => write!(f, "{} has samples of type {:?}, the out-db band is {:?}", path.display(), found, expected),
+        }
+    }
+}
+
+impl std::error::Error for MapError { }
+
+/// Location of the samples of one band in an uncompressed file
+#[derive(Debug)]
+pub(crate) struct RawLayout {
+    /// Raster without bands with the georeference of the file
+    pub(crate) grid: Raster,
+    /// Sample type with the nodata value of the file
+    pub(crate) pixtype: PixType,
+    pub(crate) big_endian: bool,
+    /// Byte offset of the first sample of the band in every chunk (strip, tile or the whole
+    /// band), row by row; `None` for missing chunks
+    pub(crate) chunk_offsets: Vec<Option<u64>>,
+    pub(crate) chunk_width: usize,
+    pub(crate) chunk_height: usize,
+    pub(crate) chunks_across: usize,
+    /// Bytes from one sample of the band to the next one in the same row
+    pub(crate) pixel_stride: usize,
+    /// Bytes from one row of a chunk to the next one
+    pub(crate) row_stride: usize,
+}
+
+/// A band of an uncompressed GeoTIFF or ENVI file, read on access from a memory map.
+///
+/// The band is a window on the file; pixels of the window outside of the file and nodata
+/// pixels of the file are nodata (or 0 without nodata value). Clones and subsets share the map.
+///
+/// Mapping is `unsafe`: the file must not be modified while it is mapped, see `MappedBand::open`.
+#[derive(Debug, Clone)]
+pub struct MappedBand {
+    map: Arc<Mmap>,
+    layout: Arc<RawLayout>,
+    path: PathBuf,
+    pixtype: PixType,
+    col_off: i64,
+    row_off: i64,
+    width: usize,
+    height: usize,
+}
+
+impl MappedBand {
+
+    /// Maps band `band` (0-based) of the GeoTIFF (`.tif` / `.tiff`) or ENVI file (all other
+    /// extensions, header as for `EnviResolver`) at `path`, with the pixel type and size of the file
+    ///
+    /// # Safety
+    ///
+    /// The file must not be modified or truncated, by this or any other process, as long as the
+    /// returned band or one of its clones or subsets exists. Pixels are read from the mapped
+    /// memory without copying, so changes to the file are undefined behavior (truncating it
+    /// typically crashes the process with `SIGBUS`).
+    pub unsafe fn open<P: AsRef<Path>>(path: P, band: usize) -> Result<MappedBand, MapError> {
+
+        let path = path.as_ref();
+        let io_error = |path: &Path, e: std::io::Error| MapError::Io { path: path.to_path_buf(), message: e.to_string() };
+
+        let file = File::open(path).map_err(|e| io_error(path, e))?;
+        // sound as long as the caller upholds the contract of `open`
+        let map = Mmap::map(&file).map_err(|e| io_error(path, e))?;
+
+        let mut layouts = if is_tiff_path(path) {
+            crate::geotiff::raw_layouts(&map)
+                .map_err(|error| MapError::GeoTiff { path: path.to_path_buf(), error })?
+                .ok_or_else(|| MapError::UnsupportedLayout { path: path.to_path_buf() })?
+        } else {
+            let header_path = envi_header_path(path);
+            let header = std::fs::read(&header_path).map_err(|e| io_error(&header_path, e))?;
+            crate::envi::raw_layouts(&String::from_utf8_lossy(&header), map.len())
+                .map_err(|error| MapError::Envi { path: path.to_path_buf(), error })?
+        };
+
+        if band >= layouts.len() {
+            return Err(MapError::FileBandOutOfRange { path: path.to_path_buf(), band });
+        }
+        let layout = layouts.swap_remove(band);
+
+        Ok(MappedBand {
+            map: Arc::new(map),
+            pixtype: layout.pixtype,
+            width: layout.grid.width as usize,
+            height: layout.grid.height as usize,
+            layout: Arc::new(layout),
+            path: path.to_path_buf(),
+            col_off: 0,
+            row_off: 0,
+        })
+    }
+
+    /// Returns the path of the mapped file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the pixel type of the band
+    pub fn get_pixtype(&self) -> PixType {
+        self.pixtype
+    }
+
+    /// Returns the number of pixel columns
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Returns the number of pixel rows
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Returns the pixel value at `(col, row)` as a `f64`, regardless of
+    /// whether the value is the nodata value. Nodata pixels of the file are returned as the
+    /// nodata value of the band. Returns `None` if out of bounds.
+    pub fn get_f64(&self, col: usize, row: usize) -> Option<f64> {
+
+        if col >= self.width || row >= self.height {
+            return None;
+        }
+
+        let nodata = self.pixtype.nodata_f64().unwrap_or(0.0);
+        let layout = &self.layout;
+        let (x, y) = (col as i64 + self.col_off, row as i64 + self.row_off);
+        if x < 0 || y < 0 || x >= layout.grid.width as i64 || y >= layout.grid.height as i64 {
+            return Some(nodata);
+        }
+        let (x, y) = (x as usize, y as usize);
+
+        let chunk = (y / layout.chunk_height) * layout.chunks_across + x / layout.chunk_width;
+        let offset = match layout.chunk_offsets[chunk] {
+            Some(offset) => offset as usize,
+            None => return Some(nodata),
+        };
+        let i = offset + (y % layout.chunk_height) * layout.row_stride + (x % layout.chunk_width) * layout.pixel_stride;
+        let value = sample(&self.map[i..], layout.pixtype, layout.big_endian);
+        // the out-db band may declare another nodata value than the file
+        match layout.pixtype.nodata_f64() {
+            Some(file_nodata) if is_nodata(value, file_nodata) => Some(nodata),
+            _ => Some(value),
+        }
+    }
+
+    /// Returns the pixel value at `(col, row)` as a `f64`, or `None` if the pixel
+    /// is out of bounds or equal to the nodata value of the band
+    pub fn get_value(&self, col: usize, row: usize) -> Option<f64> {
+        let value = self.get_f64(col, row)?;
+        match self.pixtype.nodata_f64() {
+            Some(nodata) if is_nodata(value, nodata) => None,
+            _ => Some(value),
+        }
+    }
+
+    /// Returns the window starting at `(col_off, row_off)` with the given size, or `None`
+    /// if the window is not completely inside of the band; no pixels are read
+    pub fn subset(&self, col_off: usize, row_off: usize, width: usize, height: usize) -> Option<Self> {
+        let fits = |off: usize, len: usize, size: usize| off.checked_add(len).map_or(false, |end| end <= size);
+        if !fits(col_off, width, self.width) || !fits(row_off, height, self.height) {
+            return None;
+        }
+        Some(MappedBand {
+            col_off: self.col_off + col_off as i64,
+            row_off: self.row_off + row_off as i64,
+            width,
+            height,
+            .. self.clone()
+        })
+    }
+
+    /// Reads all pixels of the band
+    pub fn to_in_memory(&self) -> InMemoryRasterData {
+        InMemoryRasterData::from_fn_f64(self.pixtype, self.width, self.height, |col, row| {
+            self.get_f64(col, row).unwrap_or(0.0)
+        })
+    }
+}
+
+fn is_nodata(value: f64, nodata: f64) -> bool {
+    value == nodata || (value.is_nan() && nodata.is_nan())
+}
+
+/// Decodes the sample at the start of `bytes`
+fn sample(bytes: &[u8], pixtype: PixType, big_endian: bool) -> f64 {
+    let size = match pixtype {
+        PixType::Int16(_) | PixType::UInt16(_) => 2,
+        PixType::Int32(_) | PixType::UInt32(_) | PixType::Float32(_) => 4,
+        PixType::Float64(_) => 8,
+        _ => 1,
+    };
+    let mut b = [0; 8];
+    b[..size].copy_from_slice(&bytes[..size]);
+    if big_endian {
+        b[..size].reverse();
+    }
+    match pixtype {
+        PixType::Int8(_) => b[0] as i8 as f64,
+        PixType::Int16(_) => i16::from_le_bytes([b[0], b[1]]) as f64,
+        PixType::UInt16(_) => u16::from_le_bytes([b[0], b[1]]) as f64,
+        PixType::Int32(_) => i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+        PixType::UInt32(_) => u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+        PixType::Float32(_) => f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+        PixType::Float64(_) => f64::from_le_bytes(b),
+        _ => b[0] as f64,
+    }
+}
+
+impl Raster {
+
+    /// Maps out-db band `band` through the path mappings of `resolver`, as a window with the
+    /// extent of the raster and the pixel type of the out-db band (see `MappedBand::open`).
+    ///
+    /// The pixel grid of the file has to be aligned with the raster and its samples have to be
+    /// of the out-db pixel type; unlike `materialize` only the pixels that are accessed are read.
+    ///
+    /// # Safety
+    ///
+    /// The file must not be modified or truncated while the returned band exists, as for
+    /// `MappedBand::open`.
+    ///
+    /// ```rust
+    /// use std::path::PathBuf;
+    /// use wkb_raster::{Raster, RasterBand, RasterDataSource, InMemoryRasterData, OfflineRasterData, PixType, Endian};
+    /// use wkb_raster::{FileResolver, GeoTiffOptions};
+    ///
+    /// let file = Raster {
+    ///     endian: Endian::Little, version: 0,
+    ///     scale_x: 10.0, scale_y: -10.0, ip_x: 0.0, ip_y: 30.0, skew_x: 0.0, skew_y: 0.0,
+    ///     srid: 32632, width: 3, height: 3,
+    ///     bands: vec![RasterBand {
+    ///         is_nodata_value: false,
+    ///         data: RasterDataSource::InMemory(InMemoryRasterData::Int16 {
+    ///             data: vec![vec![1, 2, 3], vec![4, 5, -1], vec![7, 8, 9]],
+    ///             nodata: Some(-1),
+    ///         }),
+    ///     }],
+    /// };
+    /// let dir = std::env::temp_dir();
+    /// let options = GeoTiffOptions { deflate_level: None, .. GeoTiffOptions::default() };
+    /// file.write_geotiff(std::fs::File::create(dir.join("map_band_doc.tif")).unwrap(), options).unwrap();
+    ///
+    /// // 2x2 tile overlapping the right edge of the file
+    /// let tile = Raster {
+    ///     ip_x: 20.0, ip_y: 20.0, width: 2, height: 2,
+    ///     bands: vec![RasterBand {
+    ///         is_nodata_value: false,
+    ///         data: RasterDataSource::Offline(OfflineRasterData {
+    ///             band: 0,
+    ///             path: PathBuf::from("/mnt/rasters/map_band_doc.tif"),
+    ///             pixtype: PixType::Int16(Some(-9)),
+    ///         }),
+    ///     }],
+    ///     .. file.clone()
+    /// };
+    ///
+    /// let resolver = FileResolver::new().with_path_mapping("/mnt/rasters", &dir);
+    /// // the file isn't modified while it is mapped
+    /// let band = unsafe { tile.map_band(0, &resolver) }.unwrap();
+    /// assert_eq!((band.width(), band.height()), (2, 2));
+    /// assert_eq!(band.get_value(0, 1), Some(9.0));
+    /// assert_eq!(band.get_value(1, 1), None);
+    /// // the nodata pixel of the file becomes the nodata value of the out-db band
+    /// assert_eq!(band.get_f64(0, 0), Some(-9.0));
+    /// assert_eq!(band.get_value(0, 0), None);
+    /// assert_eq!(band.subset(0, 0, 1, 2).unwrap().to_in_memory(), InMemoryRasterData::Int16 {
+    ///     data: vec![vec![-9], vec![9]],
+    ///     nodata: Some(-9),
+    /// });
+    /// assert!(band.subset(1, 0, usize::MAX, 1).is_none());
+    /// ```
+    pub unsafe fn map_band(&self, band: usize, resolver: &FileResolver) -> Result<MappedBand, MapError> {
+
+        let offline = match &self.bands.get(band).ok_or(MapError::BandIndexOutOfRange(band))?.data {
+            RasterDataSource::Offline(offline) => offline,
+            RasterDataSource::InMemory(_) => return Err(MapError::BandIsInMemory(band)),
+        };
+        if offline.band < 0 {
+            return Err(MapError::InvalidBandNumber(offline.band));
+        }
+
+        let path = resolver.map_path(&offline.path);
+        let mapped = MappedBand::open(&path, offline.band as usize)?;
+
+        let found = mapped.layout.pixtype.with_nodata_f64(None);
+        if found != offline.pixtype.with_nodata_f64(None) {
+            return Err(MapError::PixTypeMismatch { path, expected: offline.pixtype, found });
+        }
+
+        // the SRID of the file may be unknown
+        let grid = Raster { srid: self.srid, .. mapped.layout.grid.clone_without_bands() };
+        grid.alignment_with(self).map_err(|misalignment| MapError::NotAligned { path: path.clone(), misalignment })?;
+        let (col_off, row_off) = grid.world_to_pixel(self.ip_x, self.ip_y).unwrap_or((0.0, 0.0));
+
+        Ok(MappedBand {
+            pixtype: offline.pixtype,
+            col_off: col_off.round() as i64,
+            row_off: row_off.round() as i64,
+            width: self.width as usize,
+            height: self.height as usize,
+            .. mapped
+        })
+    }
+}
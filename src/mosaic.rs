@@ -0,0 +1,241 @@
+//! Merging of aligned rasters into one raster (`ST_Union`)
+
+use std::mem::discriminant;
+use std::fmt;
+use crate::{Raster, RasterBand, RasterDataSource, InMemoryRasterData};
+use crate::alignment::Misalignment;
+use crate::resample::checked_size;
+
+#[derive(Debug, Clone, PartialEq, PartialOrd)]
+pub enum MosaicError {
+    /// No rasters were given
+    NoRasters,
+    /// The raster at the given index is not aligned with the first raster
+    NotAligned(usize, Misalignment),
+    /// The raster at the given index has a different number of bands than the first raster
+    BandCountMismatch(usize),
+    /// The band has a different pixel type than the same band of the first raster
+    PixTypeMismatch { raster: usize, band: usize },
+    /// The band is stored out-of-db, pixels can't be merged
+    BandIsOffline { raster: usize, band: usize },
+    /// The band doesn't have the width and height of its raster
+    BandSizeMismatch { raster: usize, band: usize },
+    /// The union of all rasters is larger than 65535 pixels in one direction
+    InvalidSize { width: f64, height: f64 },
+}
+
+impl fmt::Display for MosaicError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MosaicError::NoRasters => write!(f, "no rasters to merge"),
+            MosaicError::NotAligned(raster, reason) => write!(f, "raster {} is not aligned with the first raster: {}", raster, reason),
+            MosaicError::BandCountMismatch(raster) => write!(f, "raster {} has a different number of bands than the first raster", raster),
+            MosaicError::PixTypeMismatch { raster, band } => write!(f, "band {} of raster {} has a different pixel type than the first raster", band, raster),
+            MosaicError::BandIsOffline { raster, band } => write!(f, "band {} of raster {} is stored out-of-db and can't be merged", band, raster),
+            MosaicError::BandSizeMismatch { raster, band } => write!(f, "band {} of raster {} doesn't have the size of its raster", band, raster),
+            MosaicError::InvalidSize { width, height } => write!(f, "merged raster of {}x{} pixels is too large (at most 65535x65535)", width, height),
+        }
+    }
+}
+
+impl std::error::Error for MosaicError { }
+
+/// How overlapping pixel values are merged (same as the `uniontype` of `ST_Union`)
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum MergePolicy {
+    /// Value of the last raster with a valid pixel
+    Last,
+    /// Value of the first raster with a valid pixel
+    First,
+    /// Smallest valid value
+    Min,
+    /// Largest valid value
+    Max,
+    /// Sum of all valid values
+    Sum,
+    /// Mean of all valid values
+    Mean,
+    /// Number of valid values
+    Count,
+    /// Largest minus smallest valid value
+    Range,
+}
+
+/// Running aggregate of the values merged into one pixel
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct Accumulator {
+    first: f64,
+    last: f64,
+    min: f64,
+    max: f64,
+    sum: f64,
+    count: usize,
+}
+
+impl Accumulator {
+
+    pub(crate) const EMPTY: Accumulator = Accumulator {
+        first: 0.0,
+        last: 0.0,
+        min: f64::INFINITY,
+        max: f64::NEG_INFINITY,
+        sum: 0.0,
+        count: 0,
+    };
+
+    pub(crate) fn add(&mut self, value: f64) {
+        if self.count == 0 {
+            self.first = value;
+        }
+        self.last = value;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        self.sum += value;
+        self.count += 1;
+    }
+
+    pub(crate) fn result(&self, policy: MergePolicy) -> Option<f64> {
+        if self.count == 0 {
+            return if policy == MergePolicy::Count { Some(0.0) } else { None };
+        }
+        Some(match policy {
+            MergePolicy::Last => self.last,
+            MergePolicy::First => self.first,
+            MergePolicy::Min => self.min,
+            MergePolicy::Max => self.max,
+            MergePolicy::Sum => self.sum,
+            MergePolicy::Mean => self.sum / self.count as f64,
+            MergePolicy::Count => self.count as f64,
+            MergePolicy::Range => self.max - self.min,
+        })
+    }
+}
+
+/// Merges aligned rasters into one raster covering the union of their extents.
+///
+/// All rasters must be aligned with the first raster (see `Raster::alignment_with`) and have
+/// the same number of bands with the same pixel types and the width and height of the raster. The output takes the metadata and the
+/// nodata values from the first raster. Pixels that aren't covered by any valid input pixel are
+/// set to the nodata value (or 0 if the band has no nodata value). Results are rounded and
+/// clamped to the value range of integer pixel types.
+///
+/// ```rust
+/// use wkb_raster::{Raster, RasterBand, RasterDataSource, InMemoryRasterData, Endian, MergePolicy, MosaicError, mosaic};
+///
+/// let tile = |ip_x: f64, value: u8| Raster {
+///     endian: Endian::Big, version: 0,
+///     scale_x: 1.0, scale_y: -1.0, ip_x, ip_y: 1.0, skew_x: 0.0, skew_y: 0.0,
+///     srid: 4326, width: 2, height: 1,
+///     bands: vec![RasterBand {
+///         is_nodata_value: false,
+///         data: RasterDataSource::InMemory(InMemoryRasterData::UInt8 {
+///             data: vec![vec![value, value]],
+///             nodata: Some(0),
+///         }),
+///     }],
+/// };
+///
+/// let merged = mosaic(&[tile(0.0, 10), tile(1.0, 20), tile(4.0, 30)], MergePolicy::Sum).unwrap();
+/// assert_eq!(merged.width, 6);
+/// assert_eq!(merged.bands[0].data, RasterDataSource::InMemory(InMemoryRasterData::UInt8 {
+///     data: vec![vec![10, 30, 20, 0, 30, 30]],
+///     nodata: Some(0),
+/// }));
+///
+/// let narrow = Raster { width: 1, .. tile(0.0, 10) };
+/// assert_eq!(mosaic(&[narrow], MergePolicy::Sum), Err(MosaicError::BandSizeMismatch { raster: 0, band: 0 }));
+/// ```
+pub fn mosaic(rasters: &[Raster], policy: MergePolicy) -> Result<Raster, MosaicError> {
+
+    let reference = rasters.first().ok_or(MosaicError::NoRasters)?;
+
+    let mut band_data = Vec::with_capacity(rasters.len());
+    let mut offsets = Vec::with_capacity(rasters.len());
+
+    for (i, raster) in rasters.iter().enumerate() {
+
+        reference.alignment_with(raster).map_err(|e| MosaicError::NotAligned(i, e))?;
+
+        if raster.bands.len() != reference.bands.len() {
+            return Err(MosaicError::BandCountMismatch(i));
+        }
+
+        let data = raster.bands.iter().enumerate().map(|(b, band)| {
+            match &band.data {
+                RasterDataSource::InMemory(data) => {
+                    let ref_pixtype = reference.bands[b].data.get_pixtype();
+                    if discriminant(&data.get_pixtype()) != discriminant(&ref_pixtype) {
+                        return Err(MosaicError::PixTypeMismatch { raster: i, band: b });
+                    }
+                    // the extent of the mosaic is computed from the raster sizes
+                    if data.width() != raster.width as usize || data.height() != raster.height as usize {
+                        return Err(MosaicError::BandSizeMismatch { raster: i, band: b });
+                    }
+                    Ok(data)
+                },
+                RasterDataSource::Offline(_) => Err(MosaicError::BandIsOffline { raster: i, band: b }),
+            }
+        }).collect::<Result<Vec<_>, _>>()?;
+
+        band_data.push(data);
+
+        // alignment was checked, so the offset is an integer
+        let (col, row) = reference.world_to_pixel(raster.ip_x, raster.ip_y).unwrap_or_default();
+        offsets.push((col.round() as i64, row.round() as i64));
+    }
+
+    let min_col = offsets.iter().map(|o| o.0).min().unwrap_or(0);
+    let min_row = offsets.iter().map(|o| o.1).min().unwrap_or(0);
+    let max_col = rasters.iter().zip(offsets.iter()).map(|(r, o)| o.0 + r.width as i64).max().unwrap_or(0);
+    let max_row = rasters.iter().zip(offsets.iter()).map(|(r, o)| o.1 + r.height as i64).max().unwrap_or(0);
+
+    let (width, height) = checked_size((max_col - min_col) as f64, (max_row - min_row) as f64)
+        .map_err(|_| MosaicError::InvalidSize { width: (max_col - min_col) as f64, height: (max_row - min_row) as f64 })?;
+
+    let (ip_x, ip_y) = reference.pixel_to_world(min_col as f64, min_row as f64);
+
+    let bands = reference.bands.iter().enumerate().map(|(b, band)| {
+
+        let mut accumulators = vec![Accumulator::EMPTY; width as usize * height as usize];
+
+        for (data, &(off_col, off_row)) in band_data.iter().zip(offsets.iter()) {
+            let data = data[b];
+            let off_col = (off_col - min_col) as usize;
+            let off_row = (off_row - min_row) as usize;
+            for row in 0..data.height() {
+                for col in 0..data.width() {
+                    if let Some(v) = data.get_value(col, row) {
+                        accumulators[(off_row + row) * width as usize + off_col + col].add(v);
+                    }
+                }
+            }
+        }
+
+        let pixtype = band.data.get_pixtype();
+        let fill = pixtype.nodata_f64().unwrap_or(0.0);
+        let (type_min, type_max) = pixtype.value_range();
+        let round = !pixtype.is_floating_point();
+
+        let data = InMemoryRasterData::from_fn_f64(pixtype, width as usize, height as usize, |col, row| {
+            match accumulators[row * width as usize + col].result(policy) {
+                Some(v) if round => v.round().max(type_min).min(type_max),
+                Some(v) => v,
+                None => fill,
+            }
+        });
+
+        RasterBand {
+            is_nodata_value: false,
+            data: RasterDataSource::InMemory(data),
+        }
+    }).collect();
+
+    Ok(Raster {
+        ip_x,
+        ip_y,
+        width,
+        height,
+        bands,
+        .. reference.clone_without_bands()
+    })
+}
@@ -0,0 +1,418 @@
+//! Resolution of out-db bands into in-memory data and conversion of in-db bands to out-db files
+
+use std::fmt;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use crate::{Raster, RasterBand, RasterDataSource, InMemoryRasterData, OfflineRasterData, PixType, Misalignment};
+use crate::{GeoTiffError, GeoTiffOptions, EnviError, Interleave};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MaterializeError {
+    /// Reading the file failed, contains the error message
+    Io { path: PathBuf, message: String },
+    /// The file is not a valid GeoTIFF
+    GeoTiff { path: PathBuf, error: GeoTiffError },
+    /// The file is not a valid ENVI raster
+    Envi { path: PathBuf, error: EnviError },
+    /// The out-db band number is negative
+    InvalidBandNumber(i8),
+    /// The file doesn't have a band with the given 0-based number
+    BandIndexOutOfRange { path: PathBuf, band: usize },
+    /// The pixel grid of the file isn't aligned with the raster
+    NotAligned { path: PathBuf, misalignment: Misalignment },
+}
+
+impl fmt::Display for MaterializeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MaterializeError::Io { path, message } => write!(f, "reading {} failed: {}", path.display(), message),
+            MaterializeError::GeoTiff { path, error } => write!(f, "{}: {}", path.display(), error),
+            MaterializeError::Envi { path, error } => write!(f, "{}: {}", path.display(), error),
+            MaterializeError::InvalidBandNumber(band) => write!(f, "out-db band number {} is negative", band),
+            MaterializeError::BandIndexOutOfRange { path, band } => write!(f, "{} has no band {}", path.display(), band),
+            MaterializeError::NotAligned { path, misalignment } => write!(f, "pixel grid of {} is not aligned with the raster: {:?}", path.display(), misalignment),
+        }
+    }
+}
+
+impl std::error::Error for MaterializeError { }
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum OutDbError {
+    /// Creating or writing the file failed, contains the error message
+    Io { path: PathBuf, message: String },
+    /// The bands can't be encoded as GeoTIFF
+    GeoTiff(GeoTiffError),
+    /// The bands can't be encoded as ENVI raster
+    Envi(EnviError),
+    /// Out-db band numbers are limited to 0 to 127, the raster has more in-db bands of one pixel type
+    TooManyBands(usize),
+}
+
+impl fmt::Display for OutDbError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OutDbError::Io { path, message } => write!(f, "writing {} failed: {}", path.display(), message),
+            OutDbError::GeoTiff(error) => write!(f, "{}", error),
+            OutDbError::Envi(error) => write!(f, "{}", error),
+            OutDbError::TooManyBands(count) => write!(f, "{} bands can't be stored in one out-db file (at most 128)", count),
+        }
+    }
+}
+
+impl std::error::Error for OutDbError { }
+
+/// Format of the files written by `Raster::to_out_db`
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum OutDbFormat {
+    /// GeoTIFF file `<name>.tif`
+    GeoTiff(GeoTiffOptions),
+    /// Raw ENVI data file `<name>.bsq` / `.bil` / `.bip` with the header `<name>.hdr`
+    Envi(Interleave),
+}
+
+/// Source of the pixels of out-db bands
+pub trait OfflineResolver {
+    /// Returns band `band` (0-based) of the file at `path` as a raster with a single in-memory
+    /// band and the georeference of the file
+    fn resolve(&self, path: &Path, band: usize) -> Result<Raster, MaterializeError>;
+}
+
+/// Keeps only band `band` of a raster read from `path`
+fn single_band(mut raster: Raster, path: &Path, band: usize) -> Result<Raster, MaterializeError> {
+    if band >= raster.bands.len() {
+        return Err(MaterializeError::BandIndexOutOfRange { path: path.to_path_buf(), band });
+    }
+    raster.bands = vec![raster.bands.swap_remove(band)];
+    Ok(raster)
+}
+
+/// The `.tif` or `.tiff` extension (case-insensitive) marks GeoTIFF files
+pub(crate) fn is_tiff_path(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .map_or(false, |e| e.eq_ignore_ascii_case("tif") || e.eq_ignore_ascii_case("tiff"))
+}
+
+/// Header of an ENVI data file: `image.bsq.hdr` if it exists, otherwise `image.hdr`
+pub(crate) fn envi_header_path(path: &Path) -> PathBuf {
+    let mut appended = path.as_os_str().to_os_string();
+    appended.push(".hdr");
+    let appended = PathBuf::from(appended);
+    if appended.is_file() {
+        appended
+    } else {
+        path.with_extension("hdr")
+    }
+}
+
+fn read_file(path: &Path) -> Result<Vec<u8>, MaterializeError> {
+    std::fs::read(path).map_err(|e| MaterializeError::Io { path: path.to_path_buf(), message: e.to_string() })
+}
+
+/// Reads out-db bands from GeoTIFF files
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct GeoTiffResolver;
+
+impl OfflineResolver for GeoTiffResolver {
+    fn resolve(&self, path: &Path, band: usize) -> Result<Raster, MaterializeError> {
+        let raster = Raster::from_geotiff(&read_file(path)?)
+            .map_err(|error| MaterializeError::GeoTiff { path: path.to_path_buf(), error })?;
+        single_band(raster, path, band)
+    }
+}
+
+/// Reads out-db bands from raw ENVI data files, the header is expected next to the data file
+/// with `.hdr` appended (`image.bsq.hdr`) or as extension (`image.hdr`)
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct EnviResolver;
+
+impl OfflineResolver for EnviResolver {
+    fn resolve(&self, path: &Path, band: usize) -> Result<Raster, MaterializeError> {
+        let header = read_file(&envi_header_path(path))?;
+        let raster = Raster::from_envi(&String::from_utf8_lossy(&header), &read_file(path)?)
+            .map_err(|error| MaterializeError::Envi { path: path.to_path_buf(), error })?;
+        single_band(raster, path, band)
+    }
+}
+
+/// Reads out-db bands from GeoTIFF (`.tif` / `.tiff`) or ENVI files (all other extensions),
+/// optionally replacing path prefixes, e.g. for files that were moved to another host, and
+/// resolving relative paths against a base directory instead of the working directory
+///
+/// ```rust
+/// use std::path::Path;
+/// use wkb_raster::FileResolver;
+///
+/// let resolver = FileResolver::new()
+///     .with_path_mapping("/mnt/old", "/data/rasters")
+///     .with_base_directory("/data/tiles");
+/// assert_eq!(resolver.map_path(Path::new("/mnt/old/dem/n47.tif")), Path::new("/data/rasters/dem/n47.tif"));
+/// assert_eq!(resolver.map_path(Path::new("/mnt/other/n47.tif")), Path::new("/mnt/other/n47.tif"));
+/// assert_eq!(resolver.map_path(Path::new("dem/n47.tif")), Path::new("/data/tiles/dem/n47.tif"));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct FileResolver {
+    path_mappings: Vec<(PathBuf, PathBuf)>,
+    base_directory: Option<PathBuf>,
+}
+
+impl FileResolver {
+
+    pub fn new() -> Self {
+        FileResolver::default()
+    }
+
+    /// Replaces the prefix `from` of out-db paths with `to`, the first matching mapping is used
+    pub fn with_path_mapping<P: Into<PathBuf>, Q: Into<PathBuf>>(mut self, from: P, to: Q) -> Self {
+        self.path_mappings.push((from.into(), to.into()));
+        self
+    }
+
+    /// Resolves relative out-db paths (after the path mappings) against `directory`
+    pub fn with_base_directory<P: Into<PathBuf>>(mut self, directory: P) -> Self {
+        self.base_directory = Some(directory.into());
+        self
+    }
+
+    /// Returns the path with the first matching prefix replaced, joined to the base directory
+    /// if it is still relative
+    pub fn map_path(&self, path: &Path) -> PathBuf {
+        let path = self.path_mappings.iter()
+            .find_map(|(from, to)| path.strip_prefix(from).ok().map(|rest| to.join(rest)))
+            .unwrap_or_else(|| path.to_path_buf());
+        match &self.base_directory {
+            Some(directory) if path.is_relative() => directory.join(path),
+            _ => path,
+        }
+    }
+}
+
+impl OfflineResolver for FileResolver {
+    fn resolve(&self, path: &Path, band: usize) -> Result<Raster, MaterializeError> {
+        let path = self.map_path(path);
+        if is_tiff_path(&path) {
+            GeoTiffResolver.resolve(&path, band)
+        } else {
+            EnviResolver.resolve(&path, band)
+        }
+    }
+}
+
+impl Raster {
+
+    /// Returns the raster with all out-db bands read into memory through `resolver`.
+    ///
+    /// The pixel grid of the file has to be aligned with the raster (the file may be larger,
+    /// as for tiles created with `raster2pgsql -R`); the pixels of the raster's extent are
+    /// converted to the pixel type of the out-db band, nodata pixels of the file and pixels
+    /// outside of the file are set to the nodata value of the out-db band (0 if it has none).
+    ///
+    /// Unlike `Raster::map_band`, the file may store another sample type than the out-db band
+    /// (ENVI files written by `to_out_db` widen 1, 2 and 4 bit bands to bytes and `Int8` bands
+    /// to 16 bit): values are converted with the saturating cast of `InMemoryRasterData::from_fn_f64`,
+    /// so out-of-range samples are clamped without error.
+    ///
+    /// ```rust
+    /// use std::path::PathBuf;
+    /// use wkb_raster::{Raster, RasterBand, RasterDataSource, InMemoryRasterData, OfflineRasterData, PixType, Endian, FileResolver};
+    ///
+    /// let file = Raster {
+    ///     endian: Endian::Little, version: 0,
+    ///     scale_x: 10.0, scale_y: -10.0, ip_x: 0.0, ip_y: 30.0, skew_x: 0.0, skew_y: 0.0,
+    ///     srid: 32632, width: 3, height: 3,
+    ///     bands: vec![RasterBand {
+    ///         is_nodata_value: false,
+    ///         data: RasterDataSource::InMemory(InMemoryRasterData::UInt8 {
+    ///             data: vec![vec![1, 2, 3], vec![4, 5, 6], vec![7, 8, 9]],
+    ///             nodata: Some(9),
+    ///         }),
+    ///     }],
+    /// };
+    /// let dir = std::env::temp_dir();
+    /// file.write_geotiff(std::fs::File::create(dir.join("materialize_doc.tif")).unwrap(), Default::default()).unwrap();
+    ///
+    /// // lower right 2x2 tile of the file, stored with a path of another host
+    /// let tile = Raster {
+    ///     ip_x: 10.0, ip_y: 20.0, width: 2, height: 2,
+    ///     bands: vec![RasterBand {
+    ///         is_nodata_value: false,
+    ///         data: RasterDataSource::Offline(OfflineRasterData {
+    ///             band: 0,
+    ///             path: PathBuf::from("/mnt/rasters/materialize_doc.tif"),
+    ///             pixtype: PixType::UInt8(Some(0)),
+    ///         }),
+    ///     }],
+    ///     .. file.clone()
+    /// };
+    ///
+    /// // the nodata pixel of the file becomes the nodata value of the out-db band
+    /// let resolver = FileResolver::new().with_path_mapping("/mnt/rasters", &dir);
+    /// assert_eq!(tile.materialize(&resolver).unwrap().bands[0].data, RasterDataSource::InMemory(InMemoryRasterData::UInt8 {
+    ///     data: vec![vec![5, 6], vec![8, 0]],
+    ///     nodata: Some(0),
+    /// }));
+    /// ```
+    pub fn materialize<R: OfflineResolver + ?Sized>(&self, resolver: &R) -> Result<Raster, MaterializeError> {
+
+        let width = self.width as usize;
+        let height = self.height as usize;
+
+        let bands = self.bands.iter().map(|band| {
+            let offline = match &band.data {
+                RasterDataSource::InMemory(_) => return Ok(band.clone()),
+                RasterDataSource::Offline(offline) => offline,
+            };
+
+            if offline.band < 0 {
+                return Err(MaterializeError::InvalidBandNumber(offline.band));
+            }
+            let number = offline.band as usize;
+            let file = resolver.resolve(&offline.path, number)?;
+            let data = match file.bands.first().map(|b| &b.data) {
+                Some(RasterDataSource::InMemory(data)) => data,
+                _ => return Err(MaterializeError::BandIndexOutOfRange { path: offline.path.clone(), band: number }),
+            };
+
+            // the SRID of the file may be unknown
+            let grid = Raster { srid: self.srid, .. file.clone_without_bands() };
+            grid.alignment_with(self).map_err(|misalignment| MaterializeError::NotAligned { path: offline.path.clone(), misalignment })?;
+            let (col_off, row_off) = grid.world_to_pixel(self.ip_x, self.ip_y).unwrap_or((0.0, 0.0));
+            let (col_off, row_off) = (col_off.round() as i64, row_off.round() as i64);
+
+            let nodata = offline.pixtype.nodata_f64().unwrap_or(0.0);
+            let data = InMemoryRasterData::from_fn_f64(offline.pixtype, width, height, |col, row| {
+                let (file_col, file_row) = (col as i64 + col_off, row as i64 + row_off);
+                if file_col < 0 || file_row < 0 {
+                    return nodata;
+                }
+                data.get_value(file_col as usize, file_row as usize).unwrap_or(nodata)
+            });
+
+            Ok(RasterBand {
+                is_nodata_value: band.is_nodata_value,
+                data: RasterDataSource::InMemory(data),
+            })
+        }).collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Raster {
+            bands,
+            .. self.clone_without_bands()
+        })
+    }
+
+    /// Writes all in-db bands to a file `name` in `directory` and returns the raster with these
+    /// bands replaced by out-db references (like `raster2pgsql -R`): the absolute path of the
+    /// file, the 0-based number of the band in the file and the pixel type of the band.
+    /// Bands that are already out-db are kept.
+    ///
+    /// If the in-db bands have different pixel types or nodata values, every band is written
+    /// to its own file `<name>_<band index>`.
+    ///
+    /// ```rust
+    /// use wkb_raster::{Raster, RasterBand, RasterDataSource, InMemoryRasterData, Endian, OutDbFormat, FileResolver};
+    ///
+    /// let band = |data: Vec<Vec<u16>>| RasterBand {
+    ///     is_nodata_value: false,
+    ///     data: RasterDataSource::InMemory(InMemoryRasterData::UInt16 { data, nodata: Some(0) }),
+    /// };
+    /// let raster = Raster {
+    ///     endian: Endian::Little, version: 0,
+    ///     scale_x: 10.0, scale_y: -10.0, ip_x: 0.0, ip_y: 20.0, skew_x: 0.0, skew_y: 0.0,
+    ///     srid: 32632, width: 2, height: 2,
+    ///     bands: vec![band(vec![vec![1, 2], vec![3, 0]]), band(vec![vec![10, 20], vec![30, 40]])],
+    /// };
+    ///
+    /// let out_db = raster.to_out_db(std::env::temp_dir(), "out_db_doc", OutDbFormat::GeoTiff(Default::default())).unwrap();
+    /// match &out_db.bands[1].data {
+    ///     RasterDataSource::Offline(offline) => {
+    ///         assert_eq!(offline.band, 1);
+    ///         assert!(offline.path.is_absolute() && offline.path.ends_with("out_db_doc.tif"));
+    ///     },
+    ///     RasterDataSource::InMemory(_) => unreachable!(),
+    /// }
+    ///
+    /// assert_eq!(out_db.materialize(&FileResolver::new()).unwrap(), raster);
+    /// ```
+    pub fn to_out_db<P: AsRef<Path>>(&self, directory: P, name: &str, format: OutDbFormat) -> Result<Raster, OutDbError> {
+
+        let in_db = self.bands.iter().enumerate()
+            .filter_map(|(i, band)| match &band.data {
+                RasterDataSource::InMemory(data) => Some((i, data.get_pixtype())),
+                RasterDataSource::Offline(_) => None,
+            })
+            .collect::<Vec<_>>();
+
+        if in_db.is_empty() {
+            return Ok(self.clone());
+        }
+
+        let directory = directory.as_ref();
+        let directory = directory.canonicalize()
+            .map_err(|e| OutDbError::Io { path: directory.to_path_buf(), message: e.to_string() })?;
+
+        // band indices and the file name per file
+        let files = if in_db.iter().all(|(_, pixtype)| same_pixtype(*pixtype, in_db[0].1)) {
+            vec![(in_db.iter().map(|(i, _)| *i).collect::<Vec<_>>(), name.to_string())]
+        } else {
+            in_db.iter().map(|(i, _)| (vec![*i], format!("{}_{}", name, i))).collect()
+        };
+
+        let mut bands = self.bands.clone();
+
+        for (indices, file_name) in files {
+
+            if indices.len() > i8::MAX as usize + 1 {
+                return Err(OutDbError::TooManyBands(indices.len()));
+            }
+
+            let file = Raster {
+                bands: indices.iter().map(|i| self.bands[*i].clone()).collect(),
+                .. self.clone_without_bands()
+            };
+
+            let path = match format {
+                OutDbFormat::GeoTiff(options) => {
+                    let path = directory.join(format!("{}.tif", file_name));
+                    let writer = File::create(&path)
+                        .map_err(|e| OutDbError::Io { path: path.clone(), message: e.to_string() })?;
+                    file.write_geotiff(BufWriter::new(writer), options).map_err(OutDbError::GeoTiff)?;
+                    path
+                },
+                OutDbFormat::Envi(interleave) => {
+                    let path = directory.join(format!("{}.{}", file_name, interleave.name()));
+                    let header_path = path.with_extension("hdr");
+                    let (header, data) = file.to_envi(interleave).map_err(OutDbError::Envi)?;
+                    std::fs::write(&path, data)
+                        .map_err(|e| OutDbError::Io { path: path.clone(), message: e.to_string() })?;
+                    std::fs::write(&header_path, header)
+                        .map_err(|e| OutDbError::Io { path: header_path.clone(), message: e.to_string() })?;
+                    path
+                },
+            };
+
+            for (number, i) in indices.into_iter().enumerate() {
+                bands[i].data = RasterDataSource::Offline(OfflineRasterData {
+                    band: number as i8,
+                    path: path.clone(),
+                    pixtype: self.bands[i].data.get_pixtype(),
+                });
+            }
+        }
+
+        Ok(Raster {
+            bands,
+            .. self.clone_without_bands()
+        })
+    }
+}
+
+/// Same pixel type and nodata value, NaN nodata values are equal
+fn same_pixtype(a: PixType, b: PixType) -> bool {
+    let same_nodata = match (a.nodata_f64(), b.nodata_f64()) {
+        (Some(x), Some(y)) => x == y || (x.is_nan() && y.is_nan()),
+        (x, y) => x.is_none() && y.is_none(),
+    };
+    a.with_nodata_f64(None) == b.with_nodata_f64(None) && same_nodata
+}
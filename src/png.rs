@@ -0,0 +1,455 @@
+//! PNG encoding (`ST_AsPNG`) and decoding of rasters
+
+use std::fmt;
+use crate::{Raster, RasterBand, RasterDataSource, InMemoryRasterData, PixType, Endian};
+use crate::geotransform::GeoReference;
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+const COLOR_TYPE_GRAY: u8 = 0;
+const COLOR_TYPE_RGB: u8 = 2;
+const COLOR_TYPE_GRAY_ALPHA: u8 = 4;
+const COLOR_TYPE_RGBA: u8 = 6;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PngError {
+    /// Only rasters with 1 (gray), 3 (RGB) or 4 (RGBA) bands can be encoded
+    UnsupportedBandCount(usize),
+    /// Only `UInt8` and `UInt16` bands can be encoded
+    UnsupportedPixType { band: usize, pixtype: PixType },
+    /// All bands have to be either `UInt8` or `UInt16`
+    MixedPixTypes,
+    /// Band at the given index is stored out-of-db
+    BandIsOffline(usize),
+    /// PNG images can't be empty
+    EmptyRaster,
+    /// The data doesn't start with the PNG signature
+    InvalidSignature,
+    /// A chunk extends past the end of the data
+    TruncatedChunk,
+    /// The CRC of the chunk with the given type doesn't match its contents
+    CrcMismatch(String),
+    /// The image has no valid `IHDR` chunk
+    InvalidHeader,
+    /// Only gray, gray + alpha, RGB and RGBA images can be decoded (no palettes)
+    UnsupportedColorType(u8),
+    /// Only bit depths of 8 and 16 can be decoded
+    UnsupportedBitDepth(u8),
+    /// Interlaced images can't be decoded
+    UnsupportedInterlacing,
+    /// The compressed image data is invalid or has the wrong size
+    InvalidImageData,
+    /// The image is larger than 65535 pixels in one direction
+    TooLarge { width: u32, height: u32 },
+    /// The world file doesn't consist of six numbers
+    InvalidWorldFile,
+}
+
+impl fmt::Display for PngError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PngError::UnsupportedBandCount(n) => write!(f, "PNG requires 1, 3 or 4 bands, the raster has {} bands", n),
+            PngError::UnsupportedPixType { band, pixtype } => write!(f, "PNG requires UInt8 or UInt16 bands, band {} is {:?}", band, pixtype),
+            PngError::MixedPixTypes => write!(f, "PNG requires all bands to have the same pixel type (UInt8 or UInt16)"),
+            PngError::BandIsOffline(band) => write!(f, "band {} is stored out-of-db and can't be encoded as PNG", band),
+            PngError::EmptyRaster => write!(f, "PNG requires a width and height of at least one pixel"),
+            PngError::InvalidSignature => write!(f, "data is not a PNG image (invalid signature)"),
+            PngError::TruncatedChunk => write!(f, "PNG chunk extends past the end of the data"),
+            PngError::CrcMismatch(chunk) => write!(f, "CRC mismatch in PNG chunk {}", chunk),
+            PngError::InvalidHeader => write!(f, "PNG image has no valid IHDR chunk"),
+            PngError::UnsupportedColorType(t) => write!(f, "PNG color type {} is not supported (only gray, gray + alpha, RGB and RGBA)", t),
+            PngError::UnsupportedBitDepth(d) => write!(f, "PNG bit depth {} is not supported (only 8 and 16)", d),
+            PngError::UnsupportedInterlacing => write!(f, "interlaced PNG images are not supported"),
+            PngError::InvalidImageData => write!(f, "PNG image data is invalid"),
+            PngError::TooLarge { width, height } => write!(f, "PNG image of {}x{} pixels is too large for a raster (at most 65535x65535)", width, height),
+            PngError::InvalidWorldFile => write!(f, "world file must contain six numbers"),
+        }
+    }
+}
+
+impl std::error::Error for PngError { }
+
+impl Raster {
+
+    /// Encodes the raster as PNG (like `ST_AsPNG`): 1 band as grayscale, 3 bands as RGB and
+    /// 4 bands as RGBA, with a bit depth of 8 (`UInt8` bands) or 16 (`UInt16` bands).
+    ///
+    /// If a 1- or 3-band raster has nodata values, an alpha channel is added that makes
+    /// nodata pixels (in any band) transparent. The georeference is not stored.
+    ///
+    /// ```rust
+    /// use wkb_raster::{Raster, RasterBand, RasterDataSource, InMemoryRasterData, Endian};
+    ///
+    /// let raster = Raster {
+    ///     endian: Endian::Big, version: 0,
+    ///     scale_x: 1.0, scale_y: -1.0, ip_x: 0.0, ip_y: 0.0, skew_x: 0.0, skew_y: 0.0,
+    ///     srid: 4326, width: 2, height: 2,
+    ///     bands: vec![RasterBand {
+    ///         is_nodata_value: false,
+    ///         data: RasterDataSource::InMemory(InMemoryRasterData::UInt8 {
+    ///             data: vec![vec![0, 255], vec![128, 0]],
+    ///             nodata: Some(0),
+    ///         }),
+    ///     }],
+    /// };
+    ///
+    /// let png = raster.to_png().unwrap();
+    /// assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+    /// assert_eq!(&png[12..16], b"IHDR");
+    /// assert_eq!(png[24..26], [8, 4]); // 8 bit, gray + alpha
+    ///
+    /// let float = Raster { bands: vec![RasterBand {
+    ///     is_nodata_value: false,
+    ///     data: RasterDataSource::InMemory(InMemoryRasterData::Float32 { data: vec![vec![0.0; 2]; 2], nodata: None }),
+    /// }], .. raster.clone() };
+    /// assert_eq!(float.to_png().unwrap_err().to_string(), "PNG requires UInt8 or UInt16 bands, band 0 is Float32(None)");
+    /// ```
+    pub fn to_png(&self) -> Result<Vec<u8>, PngError> {
+
+        if ![1, 3, 4].contains(&self.bands.len()) {
+            return Err(PngError::UnsupportedBandCount(self.bands.len()));
+        }
+
+        let bands = self.bands.iter().enumerate().map(|(i, band)| {
+            match &band.data {
+                RasterDataSource::InMemory(data @ InMemoryRasterData::UInt8 { .. }) |
+                RasterDataSource::InMemory(data @ InMemoryRasterData::UInt16 { .. }) => Ok(data),
+                RasterDataSource::InMemory(data) => Err(PngError::UnsupportedPixType { band: i, pixtype: data.get_pixtype() }),
+                RasterDataSource::Offline(_) => Err(PngError::BandIsOffline(i)),
+            }
+        }).collect::<Result<Vec<_>, _>>()?;
+
+        let sixteen_bit = matches!(bands[0], InMemoryRasterData::UInt16 { .. });
+        if bands.iter().any(|b| matches!(b, InMemoryRasterData::UInt16 { .. }) != sixteen_bit) {
+            return Err(PngError::MixedPixTypes);
+        }
+
+        let width = self.width as usize;
+        let height = self.height as usize;
+        if width == 0 || height == 0 {
+            return Err(PngError::EmptyRaster);
+        }
+
+        let has_nodata = bands.len() != 4 && bands.iter().any(|b| b.get_pixtype().nodata_f64().is_some());
+        let channels = bands.len() + if has_nodata { 1 } else { 0 };
+        let color_type = match channels {
+            1 => COLOR_TYPE_GRAY,
+            2 => COLOR_TYPE_GRAY_ALPHA,
+            3 => COLOR_TYPE_RGB,
+            _ => COLOR_TYPE_RGBA,
+        };
+
+        let bytes_per_sample = if sixteen_bit { 2 } else { 1 };
+        let max_sample = if sixteen_bit { u16::MAX as f64 } else { u8::MAX as f64 };
+        let bytes_per_pixel = channels * bytes_per_sample;
+        let stride = width * bytes_per_pixel;
+
+        // unfiltered scanlines
+        let mut image = Vec::with_capacity(height * stride);
+        for row in 0..height {
+            for col in 0..width {
+                let mut is_nodata = false;
+                for band in bands.iter() {
+                    let value = band.get_value(col, row);
+                    is_nodata |= value.is_none();
+                    push_sample(&mut image, value.or_else(|| band.get_f64(col, row)).unwrap_or(0.0), sixteen_bit);
+                }
+                if has_nodata {
+                    push_sample(&mut image, if is_nodata { 0.0 } else { max_sample }, sixteen_bit);
+                }
+            }
+        }
+
+        let filtered = filter_scanlines(&image, stride, bytes_per_pixel);
+
+        let mut header = Vec::with_capacity(13);
+        header.extend_from_slice(&(width as u32).to_be_bytes());
+        header.extend_from_slice(&(height as u32).to_be_bytes());
+        header.push(if sixteen_bit { 16 } else { 8 });
+        header.push(color_type);
+        header.extend_from_slice(&[0, 0, 0]); // deflate compression, adaptive filtering, no interlace
+
+        let mut png = PNG_SIGNATURE.to_vec();
+        write_chunk(&mut png, b"IHDR", &header);
+        write_chunk(&mut png, b"IDAT", &miniz_oxide::deflate::compress_to_vec_zlib(&filtered, 6));
+        write_chunk(&mut png, b"IEND", &[]);
+        Ok(png)
+    }
+
+    /// Decodes a PNG image into a raster with one band per channel: `UInt8` bands for 8-bit and
+    /// `UInt16` bands for 16-bit images. The georeference is taken from `georeference`.
+    ///
+    /// If `nodata` is set, the alpha channel (or the transparent color of a `tRNS` chunk) isn't
+    /// turned into a band; instead, transparent pixels are set to `nodata` in all bands, which
+    /// also becomes the nodata value of the bands.
+    ///
+    /// ```rust
+    /// use wkb_raster::{Raster, RasterBand, RasterDataSource, InMemoryRasterData, Endian, GeoReference};
+    ///
+    /// let raster = Raster {
+    ///     endian: Endian::Little, version: 0,
+    ///     scale_x: 2.0, scale_y: -2.0, ip_x: 100.0, ip_y: 50.0, skew_x: 0.0, skew_y: 0.0,
+    ///     srid: 3857, width: 2, height: 2,
+    ///     bands: vec![RasterBand {
+    ///         is_nodata_value: false,
+    ///         data: RasterDataSource::InMemory(InMemoryRasterData::UInt8 {
+    ///             data: vec![vec![0, 255], vec![128, 7]],
+    ///             nodata: Some(0),
+    ///         }),
+    ///     }],
+    /// };
+    ///
+    /// let png = raster.to_png().unwrap();
+    /// let world_file = "2.0\n0.0\n0.0\n-2.0\n101.0\n49.0\n";
+    ///
+    /// let decoded = Raster::from_png(&png, GeoReference::WorldFile(world_file), 3857, Some(0.0)).unwrap();
+    /// assert_eq!(decoded, raster);
+    ///
+    /// // without nodata, the alpha channel becomes a second band
+    /// let decoded = Raster::from_png(&png, GeoReference::WorldFile(world_file), 3857, None).unwrap();
+    /// assert_eq!(decoded.bands[1].data, RasterDataSource::InMemory(InMemoryRasterData::UInt8 {
+    ///     data: vec![vec![0, 255], vec![255, 255]],
+    ///     nodata: None,
+    /// }));
+    /// ```
+    pub fn from_png(bytes: &[u8], georeference: GeoReference, srid: i32, nodata: Option<f64>) -> Result<Raster, PngError> {
+
+        let (scale_x, scale_y, ip_x, ip_y, skew_x, skew_y) = georeference.geotransform().ok_or(PngError::InvalidWorldFile)?;
+
+        if !bytes.starts_with(&PNG_SIGNATURE) {
+            return Err(PngError::InvalidSignature);
+        }
+
+        let mut header = None;
+        let mut compressed = Vec::new();
+        let mut transparent_color = None;
+        let mut pos = PNG_SIGNATURE.len();
+
+        loop {
+            let length = bytes.get(pos..pos + 4).ok_or(PngError::TruncatedChunk)?;
+            let length = u32::from_be_bytes([length[0], length[1], length[2], length[3]]) as usize;
+            let chunk = bytes.get(pos + 4..pos + 8 + length).ok_or(PngError::TruncatedChunk)?;
+            let crc = bytes.get(pos + 8 + length..pos + 12 + length).ok_or(PngError::TruncatedChunk)?;
+            if crc32(chunk).to_be_bytes() != crc {
+                return Err(PngError::CrcMismatch(String::from_utf8_lossy(&chunk[..4]).to_string()));
+            }
+            let (chunk_type, data) = chunk.split_at(4);
+            match chunk_type {
+                b"IHDR" => header = Some(data.to_vec()),
+                b"IDAT" => compressed.extend_from_slice(data),
+                b"tRNS" => transparent_color = Some(data.to_vec()),
+                b"IEND" => break,
+                _ => {},
+            }
+            pos += 12 + length;
+        }
+
+        let header = header.filter(|h| h.len() == 13).ok_or(PngError::InvalidHeader)?;
+        let width = u32::from_be_bytes([header[0], header[1], header[2], header[3]]);
+        let height = u32::from_be_bytes([header[4], header[5], header[6], header[7]]);
+        let (bit_depth, color_type, interlace) = (header[8], header[9], header[12]);
+
+        if width == 0 || height == 0 {
+            return Err(PngError::InvalidHeader);
+        }
+        if width > u16::MAX as u32 || height > u16::MAX as u32 {
+            return Err(PngError::TooLarge { width, height });
+        }
+
+        let (channels, has_alpha) = match color_type {
+            COLOR_TYPE_GRAY => (1, false),
+            COLOR_TYPE_RGB => (3, false),
+            COLOR_TYPE_GRAY_ALPHA => (2, true),
+            COLOR_TYPE_RGBA => (4, true),
+            other => return Err(PngError::UnsupportedColorType(other)),
+        };
+        let sixteen_bit = match bit_depth {
+            8 => false,
+            16 => true,
+            other => return Err(PngError::UnsupportedBitDepth(other)),
+        };
+        if interlace != 0 {
+            return Err(PngError::UnsupportedInterlacing);
+        }
+
+        let (width, height) = (width as usize, height as usize);
+        let bytes_per_sample = if sixteen_bit { 2 } else { 1 };
+        let bytes_per_pixel = channels * bytes_per_sample;
+        let stride = width * bytes_per_pixel;
+
+        // every scanline starts with a filter type byte, more data than that is invalid
+        let expected_len = stride.checked_add(1).and_then(|l| l.checked_mul(height))
+            .ok_or(PngError::TooLarge { width: width as u32, height: height as u32 })?;
+        let filtered = miniz_oxide::inflate::decompress_to_vec_zlib_with_limit(&compressed, expected_len)
+            .map_err(|_| PngError::InvalidImageData)?;
+        let image = unfilter_scanlines(&filtered, height, stride, bytes_per_pixel).ok_or(PngError::InvalidImageData)?;
+
+        let sample = |col: usize, row: usize, channel: usize| -> f64 {
+            let i = row * stride + col * bytes_per_pixel + channel * bytes_per_sample;
+            if sixteen_bit { u16::from_be_bytes([image[i], image[i + 1]]) as f64 } else { image[i] as f64 }
+        };
+
+        // tRNS of gray and RGB images: one 16-bit sample per channel
+        let transparent_color = transparent_color
+            .filter(|t| !has_alpha && t.len() == channels * 2)
+            .map(|t| t.chunks(2).map(|s| u16::from_be_bytes([s[0], s[1]]) as f64).collect::<Vec<_>>());
+
+        let is_transparent = |col: usize, row: usize| -> bool {
+            if has_alpha {
+                sample(col, row, channels - 1) == 0.0
+            } else {
+                transparent_color.as_ref().map_or(false, |t| t.iter().enumerate().all(|(c, v)| sample(col, row, c) == *v))
+            }
+        };
+
+        let pixtype = if sixteen_bit { PixType::UInt16(None) } else { PixType::UInt8(None) };
+
+        let bands = match nodata {
+            Some(nodata) => {
+                let color_channels = if has_alpha { channels - 1 } else { channels };
+                (0..color_channels).map(|c| {
+                    InMemoryRasterData::from_fn_f64(pixtype.with_nodata_f64(Some(nodata)), width, height, |col, row| {
+                        if is_transparent(col, row) { nodata } else { sample(col, row, c) }
+                    })
+                }).collect::<Vec<_>>()
+            },
+            None => (0..channels).map(|c| {
+                InMemoryRasterData::from_fn_f64(pixtype, width, height, |col, row| sample(col, row, c))
+            }).collect(),
+        };
+
+        Ok(Raster {
+            endian: Endian::Little,
+            version: 0,
+            scale_x,
+            scale_y,
+            ip_x,
+            ip_y,
+            skew_x,
+            skew_y,
+            srid,
+            width: width as u16,
+            height: height as u16,
+            bands: bands.into_iter().map(|data| RasterBand {
+                is_nodata_value: false,
+                data: RasterDataSource::InMemory(data),
+            }).collect(),
+        })
+    }
+}
+
+fn push_sample(out: &mut Vec<u8>, value: f64, sixteen_bit: bool) {
+    if sixteen_bit {
+        out.extend_from_slice(&(value as u16).to_be_bytes());
+    } else {
+        out.push(value as u8);
+    }
+}
+
+fn write_chunk(out: &mut Vec<u8>, chunk_type: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = out.len();
+    out.extend_from_slice(chunk_type);
+    out.extend_from_slice(data);
+    let crc = crc32(&out[start..]);
+    out.extend_from_slice(&crc.to_be_bytes());
+}
+
+/// CRC-32 (ISO-HDLC) as used by PNG chunks
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = 0xffff_ffff_u32;
+    for &b in bytes {
+        crc ^= b as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+/// Paeth predictor of the PNG filter type 4
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let (pa, pb, pc) = ((p - a as i16).abs(), (p - b as i16).abs(), (p - c as i16).abs());
+    if pa <= pb && pa <= pc { a } else if pb <= pc { b } else { c }
+}
+
+/// Reverses the filtering of `height` scanlines of `stride` bytes, returns `None`
+/// if the data has the wrong size or an unknown filter type
+fn unfilter_scanlines(filtered: &[u8], height: usize, stride: usize, bpp: usize) -> Option<Vec<u8>> {
+
+    if filtered.len() != height * (stride + 1) {
+        return None;
+    }
+
+    let mut image = vec![0; height * stride];
+
+    for (row, line) in filtered.chunks(stride + 1).enumerate() {
+
+        let (filter_type, line) = (line[0], &line[1..]);
+        let (done, current) = image.split_at_mut(row * stride);
+        let prior = if row == 0 { None } else { Some(&done[(row - 1) * stride..]) };
+        let current = &mut current[..stride];
+
+        for i in 0..stride {
+            let a = if i >= bpp { current[i - bpp] } else { 0 };
+            let b = prior.map_or(0, |p| p[i]);
+            let c = if i >= bpp { prior.map_or(0, |p| p[i - bpp]) } else { 0 };
+            let predicted = match filter_type {
+                0 => 0,
+                1 => a,
+                2 => b,
+                3 => ((a as u16 + b as u16) / 2) as u8,
+                4 => paeth(a, b, c),
+                _ => return None,
+            };
+            current[i] = line[i].wrapping_add(predicted);
+        }
+    }
+
+    Some(image)
+}
+
+/// Filters every scanline with the filter type that gives the smallest sum of absolute
+/// differences (the heuristic recommended by the PNG specification)
+fn filter_scanlines(image: &[u8], stride: usize, bpp: usize) -> Vec<u8> {
+
+    let mut out = Vec::with_capacity(image.len() + image.len() / stride.max(1));
+    let zero = vec![0; stride];
+    let mut candidate = vec![0; stride];
+    let mut best = vec![0; stride];
+
+    for (row, line) in image.chunks(stride).enumerate() {
+
+        let prior = if row == 0 { &zero[..] } else { &image[(row - 1) * stride..row * stride] };
+        let mut best_type = 0;
+        let mut best_score = u64::MAX;
+
+        for filter_type in 0..5_u8 {
+            for i in 0..stride {
+                let a = if i >= bpp { line[i - bpp] } else { 0 };
+                let b = prior[i];
+                let c = if i >= bpp { prior[i - bpp] } else { 0 };
+                let predicted = match filter_type {
+                    0 => 0,
+                    1 => a,
+                    2 => b,
+                    3 => ((a as u16 + b as u16) / 2) as u8,
+                    _ => paeth(a, b, c),
+                };
+                candidate[i] = line[i].wrapping_sub(predicted);
+            }
+            let score = candidate.iter().map(|&v| (v as i8).unsigned_abs() as u64).sum::<u64>();
+            if score < best_score {
+                best_score = score;
+                best_type = filter_type;
+                best.copy_from_slice(&candidate);
+            }
+        }
+
+        out.push(best_type);
+        out.extend_from_slice(&best);
+    }
+
+    out
+}
@@ -0,0 +1,345 @@
+//! Conversion of bands into polygons of equal-valued pixels (`ST_DumpAsPolygons`)
+
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::fmt;
+use crate::{Raster, RasterDataSource, InMemoryRasterData};
+use crate::geometry::{Coord, Geometry};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum PolygonizeError {
+    /// The band index does not exist in the raster
+    BandIndexOutOfRange(usize),
+    /// Band data is stored out-of-db, pixels can't be polygonized
+    BandIsOffline,
+}
+
+impl fmt::Display for PolygonizeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PolygonizeError::BandIndexOutOfRange(band) => write!(f, "band {} does not exist in the raster", band),
+            PolygonizeError::BandIsOffline => write!(f, "band is stored out-of-db and can't be polygonized"),
+        }
+    }
+}
+
+impl std::error::Error for PolygonizeError { }
+
+/// Which neighbouring pixels are considered connected
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Connectivity {
+    /// Pixels sharing an edge
+    Four,
+    /// Pixels sharing an edge or a corner
+    Eight,
+}
+
+/// Direction of a pixel edge in pixel space (y pointing down)
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Dir { East, South, West, North }
+
+impl Dir {
+    fn right(self) -> Dir {
+        match self { Dir::East => Dir::South, Dir::South => Dir::West, Dir::West => Dir::North, Dir::North => Dir::East }
+    }
+}
+
+/// Boundary edge of a region, running clockwise (in pixel space) around it
+#[derive(Debug, Copy, Clone)]
+struct Edge {
+    from: (i64, i64),
+    to: (i64, i64),
+    dir: Dir,
+}
+
+impl Raster {
+
+    /// Groups contiguous pixels of equal value of band `band` into polygons (with holes),
+    /// returning `(value, polygon)` pairs in world coordinates. Nodata pixels are skipped.
+    ///
+    /// Exterior rings run counter-clockwise, holes clockwise. Rings never touch themselves:
+    /// with 8-connectivity, a region whose parts only touch at a corner is returned as a
+    /// multipolygon.
+    ///
+    /// ```rust
+    /// use wkb_raster::{Raster, RasterBand, RasterDataSource, InMemoryRasterData, Endian, Connectivity};
+    ///
+    /// let raster = Raster {
+    ///     endian: Endian::Big, version: 0,
+    ///     scale_x: 1.0, scale_y: -1.0, ip_x: 0.0, ip_y: 3.0, skew_x: 0.0, skew_y: 0.0,
+    ///     srid: 4326, width: 3, height: 3,
+    ///     bands: vec![RasterBand {
+    ///         is_nodata_value: false,
+    ///         data: RasterDataSource::InMemory(InMemoryRasterData::UInt8 {
+    ///             data: vec![vec![1, 1, 1], vec![1, 2, 1], vec![1, 1, 1]],
+    ///             nodata: None,
+    ///         }),
+    ///     }],
+    /// };
+    ///
+    /// let polygons = raster.polygonize(0, Connectivity::Four).unwrap();
+    /// assert_eq!(polygons.len(), 2);
+    /// assert_eq!(polygons[0].0, 1.0);
+    /// assert_eq!(polygons[0].1.to_wkt(), "POLYGON((0 3,0 0,3 0,3 3,0 3),(2 2,2 1,1 1,1 2,2 2))");
+    /// assert_eq!(polygons[1].1.to_wkt(), "POLYGON((1 2,1 1,2 1,2 2,1 2))");
+    ///
+    /// // the inner ring of 1s touches the outer one at a corner: one region with an
+    /// // exterior inside of its own hole, the center hole belongs to the inner exterior
+    /// let nested = Raster {
+    ///     width: 7, height: 7, ip_y: 7.0,
+    ///     bands: vec![RasterBand {
+    ///         is_nodata_value: false,
+    ///         data: RasterDataSource::InMemory(InMemoryRasterData::UInt8 {
+    ///             data: vec![
+    ///                 vec![1, 1, 1, 1, 1, 1, 1],
+    ///                 vec![1, 0, 0, 0, 0, 0, 1],
+    ///                 vec![1, 0, 1, 1, 1, 0, 1],
+    ///                 vec![1, 0, 1, 0, 1, 0, 1],
+    ///                 vec![1, 0, 1, 1, 1, 0, 1],
+    ///                 vec![1, 0, 0, 0, 0, 1, 1],
+    ///                 vec![1, 1, 1, 1, 1, 1, 1],
+    ///             ],
+    ///             nodata: None,
+    ///         }),
+    ///     }],
+    ///     .. raster.clone()
+    /// };
+    /// let polygons = nested.polygonize(0, Connectivity::Eight).unwrap();
+    /// assert_eq!(polygons[0].1.to_wkt(), "MULTIPOLYGON(((0 7,0 0,7 0,7 7,0 7),(1 6,6 6,6 2,5 2,5 1,1 1,1 6)),((2 5,2 2,5 2,5 5,2 5),(4 4,4 3,3 3,3 4,4 4)))");
+    /// ```
+    pub fn polygonize(&self, band: usize, connectivity: Connectivity) -> Result<Vec<(f64, Geometry)>, PolygonizeError> {
+
+        let data = match &self.bands.get(band).ok_or(PolygonizeError::BandIndexOutOfRange(band))?.data {
+            RasterDataSource::InMemory(data) => data,
+            RasterDataSource::Offline(_) => return Err(PolygonizeError::BandIsOffline),
+        };
+
+        let (labels, values) = label_regions(data, connectivity);
+        let width = data.width();
+        let height = data.height();
+
+        // collect the boundary edges of every region
+        let mut edges = vec![Vec::new(); values.len()];
+        let label_at = |col: i64, row: i64| -> Option<usize> {
+            if col < 0 || row < 0 || col >= width as i64 || row >= height as i64 {
+                None
+            } else {
+                labels[row as usize * width + col as usize]
+            }
+        };
+
+        for row in 0..height as i64 {
+            for col in 0..width as i64 {
+                let label = match label_at(col, row) {
+                    Some(l) => l,
+                    None => continue,
+                };
+                let region_edges = &mut edges[label];
+                if label_at(col, row - 1) != Some(label) {
+                    region_edges.push(Edge { from: (col, row), to: (col + 1, row), dir: Dir::East });
+                }
+                if label_at(col + 1, row) != Some(label) {
+                    region_edges.push(Edge { from: (col + 1, row), to: (col + 1, row + 1), dir: Dir::South });
+                }
+                if label_at(col, row + 1) != Some(label) {
+                    region_edges.push(Edge { from: (col + 1, row + 1), to: (col, row + 1), dir: Dir::West });
+                }
+                if label_at(col - 1, row) != Some(label) {
+                    region_edges.push(Edge { from: (col, row + 1), to: (col, row), dir: Dir::North });
+                }
+            }
+        }
+
+        Ok(edges.into_iter().zip(values).map(|(region_edges, value)| {
+            let rings = link_edges(&region_edges)
+                .into_iter()
+                .map(|ring| ring.into_iter().map(|(c, r)| (c as f64, r as f64)).collect())
+                .collect();
+            (value, assemble_polygons(self, rings))
+        }).collect())
+    }
+}
+
+/// Assigns a region label to every valid pixel (row-major), returns the labels and the value of each region
+fn label_regions(data: &InMemoryRasterData, connectivity: Connectivity) -> (Vec<Option<usize>>, Vec<f64>) {
+
+    let width = data.width();
+    let height = data.height();
+    let mut labels = vec![None; width * height];
+    let mut values = Vec::new();
+    let mut stack = Vec::new();
+
+    let neighbours: &[(i64, i64)] = match connectivity {
+        Connectivity::Four => &[(1, 0), (-1, 0), (0, 1), (0, -1)],
+        Connectivity::Eight => &[(1, 0), (-1, 0), (0, 1), (0, -1), (1, 1), (1, -1), (-1, 1), (-1, -1)],
+    };
+
+    for row in 0..height {
+        for col in 0..width {
+
+            if labels[row * width + col].is_some() {
+                continue;
+            }
+
+            let value = match data.get_value(col, row) {
+                Some(v) => v,
+                None => continue,
+            };
+
+            let label = values.len();
+            values.push(value);
+            labels[row * width + col] = Some(label);
+            stack.push((col, row));
+
+            while let Some((c, r)) = stack.pop() {
+                for (dc, dr) in neighbours {
+                    let (nc, nr) = (c as i64 + dc, r as i64 + dr);
+                    if nc < 0 || nr < 0 || nc >= width as i64 || nr >= height as i64 {
+                        continue;
+                    }
+                    let (nc, nr) = (nc as usize, nr as usize);
+                    if labels[nr * width + nc].is_none() && data.get_value(nc, nr) == Some(value) {
+                        labels[nr * width + nc] = Some(label);
+                        stack.push((nc, nr));
+                    }
+                }
+            }
+        }
+    }
+
+    (labels, values)
+}
+
+/// Links the boundary edges of a region into closed rings, splitting rings that touch
+/// themselves into simple rings and removing collinear vertices
+fn link_edges(edges: &[Edge]) -> Vec<Vec<(i64, i64)>> {
+
+    let mut outgoing = HashMap::<(i64, i64), Vec<usize>>::new();
+    for (i, e) in edges.iter().enumerate() {
+        outgoing.entry(e.from).or_default().push(i);
+    }
+
+    let mut used = vec![false; edges.len()];
+    let mut rings = Vec::new();
+
+    for start in 0..edges.len() {
+
+        if used[start] {
+            continue;
+        }
+
+        let mut ring = vec![edges[start].from];
+        let mut current = start;
+        used[start] = true;
+
+        loop {
+            let edge = edges[current];
+            ring.push(edge.to);
+            // where two pixels only touch at a corner, keep following the current pixel
+            let next = outgoing.get(&edge.to).into_iter().flatten().copied()
+                .filter(|i| !used[*i])
+                .min_by_key(|i| edges[*i].dir != edge.dir.right());
+            match next {
+                Some(n) => {
+                    used[n] = true;
+                    current = n;
+                },
+                None => break,
+            }
+        }
+
+        rings.extend(split_ring(&ring).into_iter().map(|r| remove_collinear(&r)));
+    }
+
+    rings
+}
+
+/// Splits a closed ring at every vertex it visits more than once
+pub(crate) fn split_ring<T: Copy + Eq + Hash>(ring: &[T]) -> Vec<Vec<T>> {
+
+    let mut rings = Vec::new();
+    let mut path = Vec::new();
+    let mut positions = HashMap::new();
+
+    for &vertex in ring {
+        if let Some(&i) = positions.get(&vertex) {
+            let mut closed = path.split_off(i);
+            for v in closed.iter() {
+                positions.remove(v);
+            }
+            closed.push(vertex);
+            rings.push(closed);
+        }
+        positions.insert(vertex, path.len());
+        path.push(vertex);
+    }
+
+    rings
+}
+
+/// Removes the vertices of a closed ring that lie on a straight line between their neighbours
+fn remove_collinear(ring: &[(i64, i64)]) -> Vec<(i64, i64)> {
+    let open = &ring[..ring.len() - 1];
+    let n = open.len();
+    let mut simplified = (0..n)
+        .filter(|&i| {
+            let (a, b, c) = (open[(i + n - 1) % n], open[i], open[(i + 1) % n]);
+            (b.0 - a.0) * (c.1 - b.1) != (b.1 - a.1) * (c.0 - b.0)
+        })
+        .map(|i| open[i])
+        .collect::<Vec<_>>();
+    simplified.push(simplified[0]);
+    simplified
+}
+
+/// Groups simple closed rings in pixel space (exteriors with a positive, holes with a negative
+/// area) into a polygon in world coordinates, or a multipolygon if there are several exteriors.
+/// Exterior rings run counter-clockwise, holes clockwise.
+pub(crate) fn assemble_polygons(raster: &Raster, rings: Vec<Vec<Coord>>) -> Geometry {
+
+    let (exteriors, holes): (Vec<_>, Vec<_>) = rings.into_iter().partition(|r| signed_area(r) > 0.0);
+
+    let to_world = |ring: &[Coord], counter_clockwise: bool| -> Vec<Coord> {
+        let mut ring = ring.iter().map(|&(c, r)| raster.pixel_to_world(c, r)).collect::<Vec<_>>();
+        if (signed_area(&ring) > 0.0) != counter_clockwise {
+            ring.reverse();
+        }
+        ring
+    };
+
+    let mut polygons = exteriors.iter().map(|r| vec![to_world(r, true)]).collect::<Vec<_>>();
+
+    for hole in holes {
+        // the midpoint of an edge of the hole is inside of its exterior and of all exteriors
+        // around that, but not on any of their boundaries
+        let point = ((hole[0].0 + hole[1].0) / 2.0, (hole[0].1 + hole[1].1) / 2.0);
+        let exterior = exteriors.iter()
+            .enumerate()
+            .filter(|(_, r)| ring_contains(r, point))
+            .min_by(|a, b| signed_area(a.1).partial_cmp(&signed_area(b.1)).unwrap_or(std::cmp::Ordering::Equal))
+            .map(|(i, _)| i);
+        if let Some(i) = exterior {
+            polygons[i].push(to_world(&hole, false));
+        }
+    }
+
+    if polygons.len() == 1 {
+        Geometry::Polygon(polygons.remove(0))
+    } else {
+        Geometry::MultiPolygon(polygons)
+    }
+}
+
+/// Even-odd test of a point against a closed ring
+fn ring_contains(ring: &[Coord], (x, y): Coord) -> bool {
+    ring.windows(2).filter(|w| {
+        let ((x0, y0), (x1, y1)) = (w[0], w[1]);
+        (y0 <= y) != (y1 <= y) && x < x0 + (y - y0) * (x1 - x0) / (y1 - y0)
+    }).count() % 2 == 1
+}
+
+/// Shoelace formula, positive for exterior rings in pixel space and for counter-clockwise
+/// rings in world space
+pub(crate) fn signed_area(ring: &[Coord]) -> f64 {
+    ring.windows(2).map(|w| w[0].0 * w[1].1 - w[1].0 * w[0].1).sum::<f64>() / 2.0
+}
@@ -0,0 +1,189 @@
+//! Distance to the nearest target pixel (like `gdal_proximity`)
+
+use std::fmt;
+use crate::{Raster, RasterDataSource, InMemoryRasterData, PixType};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ProximityError {
+    /// The band index does not exist in the raster
+    BandIndexOutOfRange(usize),
+    /// Band data is stored out-of-db, distances can't be computed
+    BandIsOffline,
+    /// With `DistanceUnits::Georeferenced`: the pixel rows and columns aren't perpendicular
+    /// (skewed grid), georeferenced distances can't be computed row by row
+    SkewedGrid,
+}
+
+impl fmt::Display for ProximityError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProximityError::BandIndexOutOfRange(band) => write!(f, "band {} does not exist in the raster", band),
+            ProximityError::BandIsOffline => write!(f, "band is stored out-of-db, distances can't be computed"),
+            ProximityError::SkewedGrid => write!(f, "georeferenced distances can't be computed on a skewed grid"),
+        }
+    }
+}
+
+impl std::error::Error for ProximityError { }
+
+/// Unit of the computed distances
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum DistanceUnits {
+    /// Distances in pixels
+    Pixels,
+    /// Distances in the units of the SRID, derived from the pixel size
+    Georeferenced,
+}
+
+impl Raster {
+
+    /// Nodata value of the band returned by `proximity`
+    pub const PROXIMITY_NODATA: f32 = -1.0;
+
+    /// Computes the euclidean distance from every pixel center of band `band` to the center of the
+    /// nearest pixel whose value is in `targets` (or to the nearest valid, non-zero pixel if
+    /// `targets` is empty). Target pixels have a distance of 0; pixels farther away than
+    /// `max_distance` (and all pixels if there is no target pixel) are set to
+    /// `Raster::PROXIMITY_NODATA`.
+    ///
+    /// With `DistanceUnits::Georeferenced`, one pixel step along a row or column has the
+    /// length of the pixel edge (`scale_x` / `scale_y`, including skew). Rotated grids are
+    /// supported, skewed grids (rows not perpendicular to the columns) are rejected.
+    ///
+    /// ```rust
+    /// use wkb_raster::{Raster, RasterBand, RasterDataSource, InMemoryRasterData, Endian, DistanceUnits, ProximityError};
+    ///
+    /// let raster = Raster {
+    ///     endian: Endian::Big, version: 0,
+    ///     scale_x: 30.0, scale_y: -40.0, ip_x: 0.0, ip_y: 0.0, skew_x: 0.0, skew_y: 0.0,
+    ///     srid: 32632, width: 4, height: 2,
+    ///     bands: vec![RasterBand {
+    ///         is_nodata_value: false,
+    ///         data: RasterDataSource::InMemory(InMemoryRasterData::UInt8 {
+    ///             data: vec![vec![3, 0, 0, 0], vec![0, 0, 0, 0]],
+    ///             nodata: None,
+    ///         }),
+    ///     }],
+    /// };
+    ///
+    /// let distances = raster.proximity(0, &[3.0], DistanceUnits::Georeferenced, Some(80.0)).unwrap();
+    /// assert_eq!(distances, InMemoryRasterData::Float32 {
+    ///     data: vec![vec![0.0, 30.0, 60.0, -1.0], vec![40.0, 50.0, 72.11102, -1.0]],
+    ///     nodata: Some(Raster::PROXIMITY_NODATA),
+    /// });
+    ///
+    /// let skewed = Raster { skew_x: 10.0, .. raster.clone() };
+    /// assert_eq!(skewed.proximity(0, &[3.0], DistanceUnits::Georeferenced, None), Err(ProximityError::SkewedGrid));
+    /// assert!(skewed.proximity(0, &[3.0], DistanceUnits::Pixels, None).is_ok());
+    /// ```
+    pub fn proximity(&self, band: usize, targets: &[f64], units: DistanceUnits, max_distance: Option<f64>) -> Result<InMemoryRasterData, ProximityError> {
+
+        let data = match &self.bands.get(band).ok_or(ProximityError::BandIndexOutOfRange(band))?.data {
+            RasterDataSource::InMemory(data) => data,
+            RasterDataSource::Offline(_) => return Err(ProximityError::BandIsOffline),
+        };
+
+        let width = data.width();
+        let height = data.height();
+
+        let (step_x, step_y) = match units {
+            DistanceUnits::Pixels => (1.0, 1.0),
+            DistanceUnits::Georeferenced => {
+                let step_x = self.scale_x.hypot(self.skew_y);
+                let step_y = self.skew_x.hypot(self.scale_y);
+                // the distances along rows and columns only add up for perpendicular pixel edges
+                let dot = self.scale_x * self.skew_x + self.skew_y * self.scale_y;
+                if dot.abs() > 1e-9 * step_x * step_y {
+                    return Err(ProximityError::SkewedGrid);
+                }
+                (step_x, step_y)
+            },
+        };
+
+        let is_target = |col: usize, row: usize| match data.get_value(col, row) {
+            Some(v) if targets.is_empty() => v != 0.0,
+            Some(v) => targets.contains(&v),
+            None => false,
+        };
+
+        // squared distances: first along the columns, then along the rows
+        let mut squared = vec![f64::INFINITY; width * height];
+        let mut input = vec![0.0; height];
+        let mut output = vec![0.0; height];
+        for col in 0..width {
+            for (row, f) in input.iter_mut().enumerate() {
+                *f = if is_target(col, row) { 0.0 } else { f64::INFINITY };
+            }
+            distance_transform(&input, step_y * step_y, &mut output);
+            for (row, d) in output.iter().enumerate() {
+                squared[row * width + col] = *d;
+            }
+        }
+
+        let mut output = vec![0.0; width];
+        for row in 0..height {
+            let line = &mut squared[row * width..(row + 1) * width];
+            distance_transform(line, step_x * step_x, &mut output);
+            line.copy_from_slice(&output);
+        }
+
+        let max_squared = max_distance.map_or(f64::INFINITY, |d| d * d);
+
+        let pixtype = PixType::Float32(Some(Raster::PROXIMITY_NODATA));
+        Ok(InMemoryRasterData::from_fn_f64(pixtype, width, height, |col, row| {
+            let d = squared[row * width + col];
+            if d.is_finite() && d <= max_squared {
+                d.sqrt()
+            } else {
+                Raster::PROXIMITY_NODATA as f64
+            }
+        }))
+    }
+}
+
+/// One-dimensional squared euclidean distance transform (Felzenszwalb & Huttenlocher):
+/// `output[p] = min(weight * (p - q)² + input[q])`, infinite inputs are ignored
+fn distance_transform(input: &[f64], weight: f64, output: &mut [f64]) {
+
+    if weight == 0.0 {
+        let min = input.iter().copied().fold(f64::INFINITY, f64::min);
+        output.iter_mut().for_each(|d| *d = min);
+        return;
+    }
+
+    // lower envelope of the parabolas: (q, start of the interval where q is the minimum)
+    let mut envelope: Vec<(usize, f64)> = Vec::new();
+
+    for (q, &fq) in input.iter().enumerate() {
+        if !fq.is_finite() {
+            continue;
+        }
+        let mut start = f64::NEG_INFINITY;
+        while let Some(&(p, p_start)) = envelope.last() {
+            let (qf, pf) = (q as f64, p as f64);
+            let s = ((fq + weight * qf * qf) - (input[p] + weight * pf * pf)) / (2.0 * weight * (qf - pf));
+            if s <= p_start {
+                envelope.pop();
+            } else {
+                start = s;
+                break;
+            }
+        }
+        envelope.push((q, start));
+    }
+
+    if envelope.is_empty() {
+        output.iter_mut().for_each(|d| *d = f64::INFINITY);
+        return;
+    }
+
+    let mut k = 0;
+    for (p, d) in output.iter_mut().enumerate() {
+        while k + 1 < envelope.len() && envelope[k + 1].1 < p as f64 {
+            k += 1;
+        }
+        let q = envelope[k].0;
+        let offset = p as f64 - q as f64;
+        *d = weight * offset * offset + input[q];
+    }
+}
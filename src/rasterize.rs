@@ -0,0 +1,239 @@
+//! Burning vector geometries into the pixel grid of a raster (`ST_AsRaster`)
+
+use std::fmt;
+use crate::{Raster, InMemoryRasterData, PixType};
+use crate::geometry::{Coord, Geometry, GeometryError};
+use crate::mosaic::{Accumulator, MergePolicy};
+
+#[derive(Debug, Clone, PartialEq, PartialOrd, Hash, Eq, Ord)]
+pub enum RasterizeError {
+    /// The WKB of the geometry at the given index could not be parsed
+    Geometry(usize, GeometryError),
+    /// The geotransform of the grid can't be inverted
+    NonInvertibleGeoTransform,
+}
+
+impl fmt::Display for RasterizeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RasterizeError::Geometry(index, e) => write!(f, "invalid geometry {}: {}", index, e),
+            RasterizeError::NonInvertibleGeoTransform => write!(f, "geotransform of the grid can't be inverted"),
+        }
+    }
+}
+
+impl std::error::Error for RasterizeError { }
+
+/// Decides which pixels are covered by a polygon
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum BurnRule {
+    /// Pixels whose center lies inside the polygon
+    CenterIn,
+    /// All pixels that overlap the polygon, including pixels only crossed by its boundary
+    AllTouched,
+}
+
+impl Raster {
+
+    /// Burns geometries (binary WKB or EWKB, with the value to burn) into a new band with
+    /// the geotransform and size of this raster; the bands of this raster are ignored, so a
+    /// raster without bands can be used to rasterize onto an explicit grid. The coordinates
+    /// of the geometries have to be in the SRID of the raster.
+    ///
+    /// `rule` decides which pixels are covered by polygons, lines and points always cover every
+    /// pixel they touch. Where geometries overlap, their values are merged with `merge`.
+    /// Pixels not covered by any geometry are set to the nodata value of `pixtype` (or 0 if
+    /// it has none). Results are rounded and clamped to the value range of integer pixel types.
+    ///
+    /// ```rust
+    /// use wkb_raster::{Raster, InMemoryRasterData, Endian, Geometry, PixType, BurnRule, MergePolicy};
+    ///
+    /// let grid = Raster {
+    ///     endian: Endian::Big, version: 0,
+    ///     scale_x: 1.0, scale_y: -1.0, ip_x: 0.0, ip_y: 3.0, skew_x: 0.0, skew_y: 0.0,
+    ///     srid: 4326, width: 4, height: 3,
+    ///     bands: vec![],
+    /// };
+    ///
+    /// let square = Geometry::Polygon(vec![vec![(0.0, 1.0), (2.0, 1.0), (2.0, 3.0), (0.0, 3.0), (0.0, 1.0)]]);
+    /// let line = Geometry::LineString(vec![(0.5, 2.5), (3.5, 2.5)]);
+    ///
+    /// let band = grid.rasterize(
+    ///     &[(&square.to_wkb(Endian::Little), 10.0), (&line.to_wkb(Endian::Little), 5.0)],
+    ///     PixType::UInt8(Some(0)),
+    ///     BurnRule::CenterIn,
+    ///     MergePolicy::Sum,
+    /// ).unwrap();
+    ///
+    /// assert_eq!(band, InMemoryRasterData::UInt8 {
+    ///     data: vec![vec![15, 15, 5, 5], vec![10, 10, 0, 0], vec![0, 0, 0, 0]],
+    ///     nodata: Some(0),
+    /// });
+    /// ```
+    pub fn rasterize(&self, geometries: &[(&[u8], f64)], pixtype: PixType, rule: BurnRule, merge: MergePolicy) -> Result<InMemoryRasterData, RasterizeError> {
+
+        let width = self.width as usize;
+        let height = self.height as usize;
+        let mut accumulators = vec![Accumulator::EMPTY; width * height];
+
+        for (i, (wkb, value)) in geometries.iter().enumerate() {
+            let geometry = Geometry::from_wkb(wkb).map_err(|e| RasterizeError::Geometry(i, e))?;
+            let mask = geometry_mask(self, &geometry, rule).ok_or(RasterizeError::NonInvertibleGeoTransform)?;
+            for (accumulator, _) in accumulators.iter_mut().zip(mask).filter(|(_, covered)| *covered) {
+                accumulator.add(*value);
+            }
+        }
+
+        let fill = pixtype.nodata_f64().unwrap_or(0.0);
+        let (type_min, type_max) = pixtype.value_range();
+        let round = !pixtype.is_floating_point();
+
+        Ok(InMemoryRasterData::from_fn_f64(pixtype, width, height, |col, row| {
+            match accumulators[row * width + col].result(merge) {
+                Some(v) if round => v.round().clamp(type_min, type_max),
+                Some(v) => v,
+                None => fill,
+            }
+        }))
+    }
+}
+
+/// Returns a row-major `width * height` mask of the pixels of `grid` covered by the
+/// geometry, or `None` if the geotransform of `grid` can't be inverted. Lines and points
+/// always cover every pixel they touch, `rule` only affects polygons.
+pub(crate) fn geometry_mask(grid: &Raster, geometry: &Geometry, rule: BurnRule) -> Option<Vec<bool>> {
+
+    let width = grid.width as usize;
+    let height = grid.height as usize;
+    let mut mask = vec![false; width * height];
+
+    let to_pixel = |coords: &[Coord]| -> Option<Vec<Coord>> {
+        coords.iter().map(|&(x, y)| grid.world_to_pixel(x, y)).collect()
+    };
+
+    let polygons = geometry.polygons();
+    if !polygons.is_empty() {
+        let rings = polygons
+            .iter()
+            .flat_map(|p| p.iter())
+            .map(|r| to_pixel(r))
+            .collect::<Option<Vec<_>>>()?;
+        fill_rings(&mut mask, width, height, &rings);
+        if rule == BurnRule::AllTouched {
+            for ring in rings.iter() {
+                trace_line(&mut mask, width, height, ring, true);
+            }
+        }
+    }
+
+    for line in geometry.lines() {
+        trace_line(&mut mask, width, height, &to_pixel(line)?, false);
+    }
+
+    for point in geometry.points() {
+        trace_line(&mut mask, width, height, &to_pixel(&[point])?, false);
+    }
+
+    Some(mask)
+}
+
+/// Marks all pixels whose center lies inside the rings (even-odd rule)
+fn fill_rings(mask: &mut [bool], width: usize, height: usize, rings: &[Vec<Coord>]) {
+
+    let mut crossings = Vec::new();
+
+    for row in 0..height {
+
+        let y = row as f64 + 0.5;
+        crossings.clear();
+
+        for ring in rings {
+            for (i, p) in ring.iter().enumerate() {
+                // rings are usually closed, but don't depend on it
+                let q = ring[(i + 1) % ring.len()];
+                if (p.1 <= y) != (q.1 <= y) {
+                    crossings.push(p.0 + (y - p.1) * (q.0 - p.0) / (q.1 - p.1));
+                }
+            }
+        }
+
+        crossings.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+
+        for pair in crossings.chunks_exact(2) {
+            // pixel c is inside if x0 <= c + 0.5 < x1
+            let first = (pair[0] - 0.5).ceil().max(0.0);
+            let last = (pair[1] - 0.5).ceil().min(width as f64);
+            if first >= last {
+                continue;
+            }
+            for col in (first as usize)..(last as usize) {
+                mask[row * width + col] = true;
+            }
+        }
+    }
+}
+
+/// Marks all pixels whose interior is touched by the line (a single coordinate
+/// marks its pixel). Lines running exactly along pixel edges don't mark any pixel.
+fn trace_line(mask: &mut [bool], width: usize, height: usize, coords: &[Coord], closed: bool) {
+
+    let mut mark_segment = |(x0, y0): Coord, (x1, y1): Coord| {
+
+        let is_point = x0 == x1 && y0 == y1;
+
+        let (first_row, last_row) = match cell_range(y0.min(y1), y0.max(y1), is_point, height) {
+            Some(s) => s,
+            None => return,
+        };
+
+        for row in first_row..=last_row {
+            // clip the segment to the row [row, row + 1]
+            let (xa, xb) = if y0 == y1 {
+                (x0, x1)
+            } else {
+                let t0 = ((row as f64 - y0) / (y1 - y0)).clamp(0.0, 1.0);
+                let t1 = ((row as f64 + 1.0 - y0) / (y1 - y0)).clamp(0.0, 1.0);
+                (x0 + t0 * (x1 - x0), x0 + t1 * (x1 - x0))
+            };
+            if let Some((first_col, last_col)) = cell_range(xa.min(xb), xa.max(xb), is_point, width) {
+                for col in first_col..=last_col {
+                    mask[row * width + col] = true;
+                }
+            }
+        }
+    };
+
+    match coords.len() {
+        0 => {},
+        1 => mark_segment(coords[0], coords[0]),
+        _ => {
+            for segment in coords.windows(2) {
+                mark_segment(segment[0], segment[1]);
+            }
+            if closed {
+                mark_segment(coords[coords.len() - 1], coords[0]);
+            }
+        },
+    }
+}
+
+/// Returns the first and last cell (clamped to `0..len`) whose interior overlaps `[min, max]`
+fn cell_range(min: f64, max: f64, is_point: bool, len: usize) -> Option<(usize, usize)> {
+    if min.is_nan() || max.is_nan() {
+        return None;
+    }
+    let (first, last) = if min < max {
+        (min.floor(), max.ceil() - 1.0)
+    } else if is_point || min != min.floor() {
+        (min.floor(), min.floor())
+    } else {
+        // line along a cell edge
+        return None;
+    };
+    let first = first.max(0.0);
+    let last = last.min(len as f64 - 1.0);
+    if first > last {
+        return None;
+    }
+    Some((first as usize, last as usize))
+}
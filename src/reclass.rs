@@ -0,0 +1,262 @@
+//! Reclassification of band values (`ST_Reclass`)
+
+use std::str::FromStr;
+use std::fmt;
+use crate::{Raster, RasterBand, RasterDataSource, InMemoryRasterData, PixType};
+
+#[derive(Debug, Clone, PartialEq, PartialOrd)]
+pub enum ReclassError {
+    /// The reclass expression could not be parsed, contains the offending part
+    InvalidExpression(String),
+    /// Range `min` is larger than range `max`
+    InvalidRange { min: f64, max: f64 },
+    /// The ranges at the two indices overlap
+    OverlappingRanges(usize, usize),
+    /// The band index does not exist in the raster
+    BandIndexOutOfRange(usize),
+    /// Band data is stored out-of-db, pixels can't be reclassified
+    BandIsOffline,
+}
+
+impl fmt::Display for ReclassError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReclassError::InvalidExpression(part) => write!(f, "invalid reclass expression: {}", part),
+            ReclassError::InvalidRange { min, max } => write!(f, "reclass range {}..{} is inverted", min, max),
+            ReclassError::OverlappingRanges(a, b) => write!(f, "reclass ranges {} and {} overlap", a, b),
+            ReclassError::BandIndexOutOfRange(band) => write!(f, "band {} does not exist in the raster", band),
+            ReclassError::BandIsOffline => write!(f, "band is stored out-of-db and can't be reclassified"),
+        }
+    }
+}
+
+impl std::error::Error for ReclassError { }
+
+/// Single `source range : target range` mapping of a reclass expression,
+/// i.e. `(10-50]:2` or `0-100:0-255`
+#[derive(Debug, Copy, Clone, PartialEq, PartialOrd)]
+pub struct ReclassRange {
+    /// Lower bound of the source range
+    pub min: f64,
+    /// Upper bound of the source range
+    pub max: f64,
+    /// Whether `min` itself is part of the range (`[`)
+    pub min_inclusive: bool,
+    /// Whether `max` itself is part of the range (`]`)
+    pub max_inclusive: bool,
+    /// Value that `min` maps to
+    pub new_min: f64,
+    /// Value that `max` maps to, values in between are interpolated linearly
+    pub new_max: f64,
+}
+
+impl ReclassRange {
+
+    /// Creates an inclusive range `[min-max]:new_min-new_max`
+    pub fn new(min: f64, max: f64, new_min: f64, new_max: f64) -> Self {
+        ReclassRange { min, max, min_inclusive: true, max_inclusive: true, new_min, new_max }
+    }
+
+    /// Returns `true` if `value` lies in the source range
+    pub fn contains(&self, value: f64) -> bool {
+        let above_min = if self.min_inclusive { value >= self.min } else { value > self.min };
+        let below_max = if self.max_inclusive { value <= self.max } else { value < self.max };
+        above_min && below_max
+    }
+
+    /// Maps a value from the source range onto the target range
+    pub fn map(&self, value: f64) -> f64 {
+        if self.max == self.min {
+            return self.new_min;
+        }
+        self.new_min + (value - self.min) * (self.new_max - self.new_min) / (self.max - self.min)
+    }
+
+    fn overlaps(&self, other: &ReclassRange) -> bool {
+        if self.max < other.min || other.max < self.min {
+            return false;
+        }
+        if self.max == other.min {
+            return self.max_inclusive && other.min_inclusive;
+        }
+        if other.max == self.min {
+            return other.max_inclusive && self.min_inclusive;
+        }
+        true
+    }
+}
+
+/// Validated list of reclass ranges, parsed from a PostGIS reclass expression
+/// like `"0-10:1, (10-50]:2, (50-100]:3-5"`.
+///
+/// Source ranges use bracket notation: `[a-b]` (the default if no brackets are
+/// given) includes both ends, `(` / `)` exclude the corresponding end (`]` and `[`
+/// are accepted as exclusive on the "wrong" side, like PostGIS does). A single
+/// number (`5:1`) matches only that value.
+///
+/// ```rust
+/// use wkb_raster::ReclassExpr;
+///
+/// let expr: ReclassExpr = "0-10:1, (10-50]:2".parse().unwrap();
+/// assert_eq!(expr.classify(10.0), Some(1.0));
+/// assert_eq!(expr.classify(10.5), Some(2.0));
+/// assert_eq!(expr.classify(51.0), None);
+///
+/// assert!("0-10:1, 5-20:2".parse::<ReclassExpr>().is_err());
+/// ```
+#[derive(Debug, Clone, PartialEq, PartialOrd)]
+pub struct ReclassExpr {
+    ranges: Vec<ReclassRange>,
+}
+
+impl ReclassExpr {
+
+    /// Creates a new expression, returns an error if any two ranges overlap
+    pub fn new(ranges: Vec<ReclassRange>) -> Result<Self, ReclassError> {
+        for r in ranges.iter() {
+            if r.min.is_nan() || r.max.is_nan() || r.min > r.max {
+                return Err(ReclassError::InvalidRange { min: r.min, max: r.max });
+            }
+        }
+        for (i, a) in ranges.iter().enumerate() {
+            for (j, b) in ranges.iter().enumerate().skip(i + 1) {
+                if a.overlaps(b) {
+                    return Err(ReclassError::OverlappingRanges(i, j));
+                }
+            }
+        }
+        Ok(ReclassExpr { ranges })
+    }
+
+    /// Returns the ranges of this expression
+    pub fn ranges(&self) -> &[ReclassRange] {
+        &self.ranges
+    }
+
+    /// Returns the reclassified value or `None` if no range matches the value
+    pub fn classify(&self, value: f64) -> Option<f64> {
+        self.ranges.iter().find(|r| r.contains(value)).map(|r| r.map(value))
+    }
+}
+
+impl FromStr for ReclassExpr {
+    type Err = ReclassError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let ranges = s
+            .split(',')
+            .map(|part| part.trim())
+            .filter(|part| !part.is_empty())
+            .map(parse_reclass_range)
+            .collect::<Result<Vec<_>, _>>()?;
+        ReclassExpr::new(ranges)
+    }
+}
+
+fn parse_reclass_range(part: &str) -> Result<ReclassRange, ReclassError> {
+
+    let invalid = || ReclassError::InvalidExpression(part.to_string());
+
+    let mut split = part.splitn(2, ':');
+    let source = split.next().ok_or_else(invalid)?.trim();
+    let target = split.next().ok_or_else(invalid)?.trim();
+
+    let (min_inclusive, source) = match source.chars().next() {
+        Some('[') => (true, &source[1..]),
+        Some('(') | Some(']') => (false, &source[1..]),
+        _ => (true, source),
+    };
+    let (max_inclusive, source) = match source.chars().last() {
+        Some(']') => (true, &source[..source.len() - 1]),
+        Some(')') | Some('[') => (false, &source[..source.len() - 1]),
+        _ => (true, source),
+    };
+
+    let (min, max) = parse_number_range(source).ok_or_else(invalid)?;
+    let (new_min, new_max) = parse_number_range(target).ok_or_else(invalid)?;
+
+    Ok(ReclassRange { min, max, min_inclusive, max_inclusive, new_min, new_max })
+}
+
+/// Parses `a-b` or `a` (where both `a` and `b` may be negative) into `(a, b)`
+fn parse_number_range(s: &str) -> Option<(f64, f64)> {
+    let s = s.trim();
+    let bytes = s.as_bytes();
+    // the separator is the first '-' that directly follows a digit, a '.' or a space
+    let separator = (1..bytes.len()).find(|&i| {
+        bytes[i] == b'-' && (bytes[i - 1].is_ascii_digit() || bytes[i - 1] == b'.' || bytes[i - 1] == b' ')
+    });
+    match separator {
+        Some(i) => Some((s[..i].trim().parse().ok()?, s[i + 1..].trim().parse().ok()?)),
+        None => {
+            let v = s.parse().ok()?;
+            Some((v, v))
+        },
+    }
+}
+
+impl RasterBand {
+
+    /// Reclassifies the band into a new band of type `pixtype`, using the nodata value of
+    /// `pixtype` for source nodata pixels and pixels not matched by any range (0 if
+    /// `pixtype` has no nodata value). Values are rounded for integer pixel types.
+    pub fn reclass(&self, expr: &ReclassExpr, pixtype: PixType) -> Result<RasterBand, ReclassError> {
+        let data = match &self.data {
+            RasterDataSource::InMemory(data) => data,
+            RasterDataSource::Offline(_) => return Err(ReclassError::BandIsOffline),
+        };
+
+        let new_nodata = pixtype.nodata_f64().unwrap_or(0.0);
+        let round = !pixtype.is_floating_point();
+
+        let new_data = InMemoryRasterData::from_fn_f64(pixtype, data.width(), data.height(), |col, row| {
+            data.get_value(col, row)
+                .and_then(|v| expr.classify(v))
+                .map(|v| if round { v.round() } else { v })
+                .unwrap_or(new_nodata)
+        });
+
+        Ok(RasterBand {
+            is_nodata_value: false,
+            data: RasterDataSource::InMemory(new_data),
+        })
+    }
+}
+
+impl Raster {
+
+    /// Returns a copy of the raster with band `band` (0-based) reclassified, see `RasterBand::reclass`
+    ///
+    /// ```rust
+    /// use wkb_raster::{Raster, RasterBand, RasterDataSource, InMemoryRasterData, PixType, Endian};
+    ///
+    /// let raster = Raster {
+    ///     endian: Endian::Big, version: 0,
+    ///     scale_x: 1.0, scale_y: -1.0, ip_x: 0.0, ip_y: 0.0, skew_x: 0.0, skew_y: 0.0,
+    ///     srid: 4326, width: 3, height: 1,
+    ///     bands: vec![RasterBand {
+    ///         is_nodata_value: false,
+    ///         data: RasterDataSource::InMemory(InMemoryRasterData::Float64 {
+    ///             data: vec![vec![5.0, 25.0, 200.0]],
+    ///             nodata: None,
+    ///         }),
+    ///     }],
+    /// };
+    ///
+    /// let expr = "0-10:1, (10-50]:2".parse().unwrap();
+    /// let reclassed = raster.reclass(0, &expr, PixType::UInt8(Some(255))).unwrap();
+    /// assert_eq!(reclassed.bands[0].data, RasterDataSource::InMemory(InMemoryRasterData::UInt8 {
+    ///     data: vec![vec![1, 2, 255]],
+    ///     nodata: Some(255),
+    /// }));
+    /// ```
+    pub fn reclass(&self, band: usize, expr: &ReclassExpr, pixtype: PixType) -> Result<Raster, ReclassError> {
+        let new_band = self.bands
+            .get(band)
+            .ok_or(ReclassError::BandIndexOutOfRange(band))?
+            .reclass(expr, pixtype)?;
+        let mut raster = self.clone();
+        raster.bands[band] = new_band;
+        Ok(raster)
+    }
+}
@@ -0,0 +1,422 @@
+//! Resampling of rasters onto a new grid (`ST_Resample`, `ST_Rescale`, `ST_Resize`)
+
+use std::fmt;
+use crate::{Raster, RasterBand, RasterDataSource, InMemoryRasterData};
+
+#[derive(Debug, Clone, PartialEq, PartialOrd)]
+pub enum ResampleError {
+    /// Band at the given index is stored out-of-db, pixels can't be resampled
+    BandIsOffline(usize),
+    /// Source and target grid have a different SRID (reprojection is not supported)
+    SridMismatch { source: i32, target: i32 },
+    /// The scale is zero, negative or not finite
+    InvalidScale { scale_x: f64, scale_y: f64 },
+    /// The resulting raster would be empty or larger than 65535 pixels in one direction
+    InvalidSize { width: f64, height: f64 },
+    /// The geotransform of the source raster can't be inverted
+    NonInvertibleGeoTransform,
+}
+
+impl fmt::Display for ResampleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ResampleError::BandIsOffline(band) => write!(f, "band {} is stored out-of-db and can't be resampled", band),
+            ResampleError::SridMismatch { source, target } => write!(f, "can't resample from SRID {} to SRID {} (reprojection is not supported)", source, target),
+            ResampleError::InvalidScale { scale_x, scale_y } => write!(f, "invalid scale {}x{} (must be positive and finite)", scale_x, scale_y),
+            ResampleError::InvalidSize { width, height } => write!(f, "resampled raster of {}x{} pixels is empty or too large (at most 65535x65535)", width, height),
+            ResampleError::NonInvertibleGeoTransform => write!(f, "geotransform of the source raster can't be inverted"),
+        }
+    }
+}
+
+impl std::error::Error for ResampleError { }
+
+/// Algorithm used to compute the value of a target pixel from the source pixels
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ResampleAlgorithm {
+    /// Value of the source pixel containing the target pixel center
+    NearestNeighbor,
+    /// Distance-weighted average of the 2x2 closest source pixels
+    Bilinear,
+    /// Cubic convolution over the 4x4 closest source pixels
+    Cubic,
+    /// Mean of all source pixels whose center lies in the target pixel
+    Average,
+    /// Most frequent value of all source pixels whose center lies in the target pixel
+    /// (NaN values are ignored, like by `Min` and `Max`)
+    Mode,
+    /// Minimum of all source pixels whose center lies in the target pixel
+    Min,
+    /// Maximum of all source pixels whose center lies in the target pixel
+    Max,
+}
+
+impl Raster {
+
+    /// Resamples all bands onto the grid (geotransform, width and height) of `reference`,
+    /// which may be a raster without bands. Nodata pixels are ignored when computing the
+    /// target values; target pixels without any valid source pixel are set to the nodata
+    /// value of the band (or 0 if the band has no nodata value).
+    pub fn resample(&self, reference: &Raster, algorithm: ResampleAlgorithm) -> Result<Raster, ResampleError> {
+
+        if self.srid != reference.srid {
+            return Err(ResampleError::SridMismatch { source: self.srid, target: reference.srid });
+        }
+
+        if self.world_to_pixel(0.0, 0.0).is_none() || reference.world_to_pixel(0.0, 0.0).is_none() {
+            return Err(ResampleError::NonInvertibleGeoTransform);
+        }
+
+        let bands = self.bands.iter().enumerate().map(|(i, band)| {
+            let data = match &band.data {
+                RasterDataSource::InMemory(data) => data,
+                RasterDataSource::Offline(_) => return Err(ResampleError::BandIsOffline(i)),
+            };
+            Ok(RasterBand {
+                is_nodata_value: band.is_nodata_value,
+                data: RasterDataSource::InMemory(resample_band(self, data, reference, algorithm)),
+            })
+        }).collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Raster {
+            endian: self.endian,
+            version: self.version,
+            scale_x: reference.scale_x,
+            scale_y: reference.scale_y,
+            ip_x: reference.ip_x,
+            ip_y: reference.ip_y,
+            skew_x: reference.skew_x,
+            skew_y: reference.skew_y,
+            srid: self.srid,
+            width: reference.width,
+            height: reference.height,
+            bands,
+        })
+    }
+
+    /// Changes the pixel size to `scale_x` / `scale_y` while keeping the upper-left corner
+    /// and the extent of the raster (like `ST_Rescale`). The sign of the scales is taken
+    /// from the current raster, the new width and height are rounded up.
+    ///
+    /// ```rust
+    /// use wkb_raster::{Raster, RasterBand, RasterDataSource, InMemoryRasterData, Endian, ResampleAlgorithm};
+    ///
+    /// let raster = Raster {
+    ///     endian: Endian::Big, version: 0,
+    ///     scale_x: 1.0, scale_y: -1.0, ip_x: 0.0, ip_y: 4.0, skew_x: 0.0, skew_y: 0.0,
+    ///     srid: 4326, width: 4, height: 4,
+    ///     bands: vec![RasterBand {
+    ///         is_nodata_value: false,
+    ///         data: RasterDataSource::InMemory(InMemoryRasterData::UInt8 {
+    ///             data: vec![
+    ///                 vec![1, 1, 2, 2],
+    ///                 vec![1, 1, 2, 2],
+    ///                 vec![3, 3, 4, 4],
+    ///                 vec![3, 3, 4, 8],
+    ///             ],
+    ///             nodata: None,
+    ///         }),
+    ///     }],
+    /// };
+    ///
+    /// let rescaled = raster.rescale(2.0, 2.0, ResampleAlgorithm::Max).unwrap();
+    /// assert_eq!((rescaled.width, rescaled.height, rescaled.scale_y), (2, 2, -2.0));
+    /// assert_eq!(rescaled.bands[0].data, RasterDataSource::InMemory(InMemoryRasterData::UInt8 {
+    ///     data: vec![vec![1, 2], vec![3, 8]],
+    ///     nodata: None,
+    /// }));
+    /// ```
+    pub fn rescale(&self, scale_x: f64, scale_y: f64, algorithm: ResampleAlgorithm) -> Result<Raster, ResampleError> {
+
+        if !(scale_x.is_finite() && scale_y.is_finite()) || scale_x == 0.0 || scale_y == 0.0 {
+            return Err(ResampleError::InvalidScale { scale_x, scale_y });
+        }
+
+        let scale_x = scale_x.abs().copysign(self.scale_x);
+        let scale_y = scale_y.abs().copysign(self.scale_y);
+
+        let width = (self.width as f64 * self.scale_x / scale_x).ceil();
+        let height = (self.height as f64 * self.scale_y / scale_y).ceil();
+        let (width, height) = checked_size(width, height)?;
+
+        let reference = Raster {
+            scale_x,
+            scale_y,
+            width,
+            height,
+            .. self.clone_without_bands()
+        };
+
+        self.resample(&reference, algorithm)
+    }
+
+    /// Changes the width and height of the raster while keeping its extent (like `ST_Resize`)
+    ///
+    /// ```rust
+    /// use wkb_raster::{Raster, RasterBand, RasterDataSource, InMemoryRasterData, Endian, ResampleAlgorithm};
+    ///
+    /// let raster = Raster {
+    ///     endian: Endian::Big, version: 0,
+    ///     scale_x: 1.0, scale_y: -1.0, ip_x: 0.0, ip_y: 4.0, skew_x: 0.0, skew_y: 0.0,
+    ///     srid: 4326, width: 4, height: 4,
+    ///     bands: vec![RasterBand {
+    ///         is_nodata_value: false,
+    ///         data: RasterDataSource::InMemory(InMemoryRasterData::Float32 {
+    ///             data: vec![
+    ///                 vec![f32::NAN, 1.0, 2.0, 2.0],
+    ///                 vec![f32::NAN, 1.0, 2.0, 3.0],
+    ///                 vec![f32::NAN, 5.0, 4.0, 4.0],
+    ///                 vec![f32::NAN, f32::NAN, 4.0, 6.0],
+    ///             ],
+    ///             nodata: None,
+    ///         }),
+    ///     }],
+    /// };
+    ///
+    /// // NaN pixels don't count for the most frequent value
+    /// let resized = raster.resize(2, 2, ResampleAlgorithm::Mode).unwrap();
+    /// assert_eq!(resized.bands[0].data, RasterDataSource::InMemory(InMemoryRasterData::Float32 {
+    ///     data: vec![vec![1.0, 2.0], vec![5.0, 4.0]],
+    ///     nodata: None,
+    /// }));
+    /// ```
+    pub fn resize(&self, width: u16, height: u16, algorithm: ResampleAlgorithm) -> Result<Raster, ResampleError> {
+
+        if width == 0 || height == 0 {
+            return Err(ResampleError::InvalidSize { width: width as f64, height: height as f64 });
+        }
+
+        let fx = self.width as f64 / width as f64;
+        let fy = self.height as f64 / height as f64;
+
+        let reference = Raster {
+            scale_x: self.scale_x * fx,
+            skew_y: self.skew_y * fx,
+            scale_y: self.scale_y * fy,
+            skew_x: self.skew_x * fy,
+            width,
+            height,
+            .. self.clone_without_bands()
+        };
+
+        self.resample(&reference, algorithm)
+    }
+
+    /// Returns a copy of the raster metadata with an empty `bands` vec
+    pub(crate) fn clone_without_bands(&self) -> Raster {
+        Raster {
+            endian: self.endian,
+            version: self.version,
+            scale_x: self.scale_x,
+            scale_y: self.scale_y,
+            ip_x: self.ip_x,
+            ip_y: self.ip_y,
+            skew_x: self.skew_x,
+            skew_y: self.skew_y,
+            srid: self.srid,
+            width: self.width,
+            height: self.height,
+            bands: Vec::new(),
+        }
+    }
+}
+
+/// Checks that a computed raster size is valid and converts it to `u16`
+pub(crate) fn checked_size(width: f64, height: f64) -> Result<(u16, u16), ResampleError> {
+    let max = u16::MAX as f64;
+    if !(width >= 1.0 && width <= max && height >= 1.0 && height <= max) {
+        return Err(ResampleError::InvalidSize { width, height });
+    }
+    Ok((width as u16, height as u16))
+}
+
+fn resample_band(source: &Raster, data: &InMemoryRasterData, target: &Raster, algorithm: ResampleAlgorithm) -> InMemoryRasterData {
+
+    let pixtype = data.get_pixtype();
+    let fill = pixtype.nodata_f64().unwrap_or(0.0);
+    let (type_min, type_max) = pixtype.value_range();
+    let round = !pixtype.is_floating_point();
+
+    // target pixel -> fractional source pixel coordinate
+    let to_source = |col: f64, row: f64| {
+        let (x, y) = target.pixel_to_world(col, row);
+        source.world_to_pixel(x, y).unwrap_or((f64::NAN, f64::NAN))
+    };
+
+    InMemoryRasterData::from_fn_f64(pixtype, target.width as usize, target.height as usize, |col, row| {
+
+        let (col, row) = (col as f64, row as f64);
+        let (sc, sr) = to_source(col + 0.5, row + 0.5);
+
+        let value = match algorithm {
+            ResampleAlgorithm::NearestNeighbor => sample_nearest(data, sc, sr),
+            ResampleAlgorithm::Bilinear => sample_bilinear(data, sc, sr),
+            ResampleAlgorithm::Cubic => sample_cubic(data, sc, sr),
+            ResampleAlgorithm::Average |
+            ResampleAlgorithm::Mode |
+            ResampleAlgorithm::Min |
+            ResampleAlgorithm::Max => {
+                let corners = [
+                    to_source(col, row),
+                    to_source(col + 1.0, row),
+                    to_source(col, row + 1.0),
+                    to_source(col + 1.0, row + 1.0),
+                ];
+                match footprint_values(data, &corners) {
+                    Some(values) => aggregate(&values, algorithm),
+                    // target pixel is smaller than a source pixel
+                    None => sample_nearest(data, sc, sr),
+                }
+            },
+        };
+
+        match value {
+            Some(v) if round => v.round().max(type_min).min(type_max),
+            Some(v) => v,
+            None => fill,
+        }
+    })
+}
+
+fn in_bounds(data: &InMemoryRasterData, sc: f64, sr: f64) -> bool {
+    sc >= 0.0 && sr >= 0.0 && sc < data.width() as f64 && sr < data.height() as f64
+}
+
+fn sample_nearest(data: &InMemoryRasterData, sc: f64, sr: f64) -> Option<f64> {
+    if !in_bounds(data, sc, sr) {
+        return None;
+    }
+    data.get_value(sc.floor() as usize, sr.floor() as usize)
+}
+
+/// Sums up `weight * value` over the valid pixels and divides by the sum of the valid
+/// weights. Pixels outside of the raster are replaced by the closest edge pixel.
+fn weighted_sample<I: Iterator<Item = (i64, i64, f64)>>(data: &InMemoryRasterData, kernel: I) -> Option<f64> {
+    let max_col = data.width() as i64 - 1;
+    let max_row = data.height() as i64 - 1;
+    let mut sum = 0.0;
+    let mut weight_sum = 0.0;
+    for (c, r, w) in kernel {
+        if w == 0.0 {
+            continue;
+        }
+        let (c, r) = (c.max(0).min(max_col), r.max(0).min(max_row));
+        if let Some(v) = data.get_value(c as usize, r as usize) {
+            sum += w * v;
+            weight_sum += w;
+        }
+    }
+    if weight_sum.abs() < 1e-12 {
+        None
+    } else {
+        Some(sum / weight_sum)
+    }
+}
+
+fn sample_bilinear(data: &InMemoryRasterData, sc: f64, sr: f64) -> Option<f64> {
+    if !in_bounds(data, sc, sr) {
+        return None;
+    }
+    let (x, y) = (sc - 0.5, sr - 0.5);
+    let (x0, y0) = (x.floor(), y.floor());
+    let (fx, fy) = (x - x0, y - y0);
+    let (x0, y0) = (x0 as i64, y0 as i64);
+    let kernel = (0..2).flat_map(move |dy| (0..2).map(move |dx| {
+        let wx = if dx == 0 { 1.0 - fx } else { fx };
+        let wy = if dy == 0 { 1.0 - fy } else { fy };
+        (x0 + dx, y0 + dy, wx * wy)
+    }));
+    weighted_sample(data, kernel).or_else(|| sample_nearest(data, sc, sr))
+}
+
+/// Cubic convolution kernel (Keys, a = -0.5)
+fn cubic_weight(t: f64) -> f64 {
+    let a = -0.5;
+    let t = t.abs();
+    if t <= 1.0 {
+        (a + 2.0) * t * t * t - (a + 3.0) * t * t + 1.0
+    } else if t < 2.0 {
+        a * t * t * t - 5.0 * a * t * t + 8.0 * a * t - 4.0 * a
+    } else {
+        0.0
+    }
+}
+
+fn sample_cubic(data: &InMemoryRasterData, sc: f64, sr: f64) -> Option<f64> {
+    if !in_bounds(data, sc, sr) {
+        return None;
+    }
+    let (x, y) = (sc - 0.5, sr - 0.5);
+    let (x0, y0) = (x.floor(), y.floor());
+    let (fx, fy) = (x - x0, y - y0);
+    let (x0, y0) = (x0 as i64, y0 as i64);
+    let kernel = (-1..3).flat_map(move |dy| (-1..3).map(move |dx| {
+        let w = cubic_weight(dx as f64 - fx) * cubic_weight(dy as f64 - fy);
+        (x0 + dx, y0 + dy, w)
+    }));
+    weighted_sample(data, kernel).or_else(|| sample_nearest(data, sc, sr))
+}
+
+/// Returns all valid source values whose pixel center lies in the bounding box of
+/// the given corners (in source pixel space) or `None` if no pixel center lies in it
+fn footprint_values(data: &InMemoryRasterData, corners: &[(f64, f64); 4]) -> Option<Vec<f64>> {
+    let min_c = corners.iter().map(|c| c.0).fold(f64::INFINITY, f64::min);
+    let max_c = corners.iter().map(|c| c.0).fold(f64::NEG_INFINITY, f64::max);
+    let min_r = corners.iter().map(|c| c.1).fold(f64::INFINITY, f64::min);
+    let max_r = corners.iter().map(|c| c.1).fold(f64::NEG_INFINITY, f64::max);
+
+    if !(min_c.is_finite() && max_c.is_finite() && min_r.is_finite() && max_r.is_finite()) {
+        return None;
+    }
+
+    // pixel c has its center at c + 0.5: include it if min <= c + 0.5 < max
+    let first_col = (min_c - 0.5).ceil().max(0.0);
+    let last_col = ((max_c - 0.5).ceil() - 1.0).min(data.width() as f64 - 1.0);
+    let first_row = (min_r - 0.5).ceil().max(0.0);
+    let last_row = ((max_r - 0.5).ceil() - 1.0).min(data.height() as f64 - 1.0);
+
+    if first_col > last_col || first_row > last_row {
+        return None;
+    }
+
+    let mut values = Vec::new();
+
+    for r in (first_row as usize)..=(last_row as usize) {
+        for c in (first_col as usize)..=(last_col as usize) {
+            if let Some(v) = data.get_value(c, r) {
+                values.push(v);
+            }
+        }
+    }
+
+    Some(values)
+}
+
+/// Returns `None` if `values` is empty (all pixels in the footprint are nodata)
+fn aggregate(values: &[f64], algorithm: ResampleAlgorithm) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+    let valid = values.iter().copied();
+    let result = match algorithm {
+        ResampleAlgorithm::Min => valid.fold(f64::NAN, f64::min),
+        ResampleAlgorithm::Max => valid.fold(f64::NAN, f64::max),
+        ResampleAlgorithm::Mode => {
+            // without NaN the order is total and every run has at least one value
+            let mut sorted = valid.filter(|v| !v.is_nan()).collect::<Vec<_>>();
+            sorted.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+            let mut best = (f64::NAN, 0);
+            let mut i = 0;
+            while i < sorted.len() {
+                let run = sorted[i..].iter().take_while(|v| **v == sorted[i]).count();
+                if run > best.1 {
+                    best = (sorted[i], run);
+                }
+                i += run;
+            }
+            best.0
+        },
+        _ => valid.sum::<f64>() / values.len() as f64,
+    };
+    Some(result)
+}
@@ -0,0 +1,235 @@
+//! Generic band access through the `Pixel` trait, implemented for the value type of every `PixType`
+
+use std::convert::TryFrom;
+use std::fmt;
+use crate::{InMemoryRasterData, PixType};
+
+/// 2-bit unsigned pixel value of `PixType::UInt2` bands (0 to 3)
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct UInt2(pub u8);
+
+/// 4-bit unsigned pixel value of `PixType::UInt4` bands (0 to 15)
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct UInt4(pub u8);
+
+mod private {
+    pub trait Sealed { }
+}
+
+/// Value type of the pixels of a band: `bool`, `UInt2`, `UInt4`, `i8`, `u8`, `i16`, `u16`,
+/// `i32`, `u32`, `f32` or `f64`
+pub trait Pixel: private::Sealed + Copy + PartialEq + PartialOrd + fmt::Debug {
+
+    /// Returns the pixel type of bands with this value type and the given nodata value
+    fn pixtype(nodata: Option<Self>) -> PixType;
+
+    /// Converts the value to a `f64` (`bool` to 0 or 1)
+    fn to_f64(self) -> f64;
+
+    /// Converts a `f64` with the same saturating cast as `InMemoryRasterData::from_fn_f64`
+    fn from_f64(value: f64) -> Self;
+
+    #[doc(hidden)]
+    fn into_in_memory(data: Vec<Vec<Self>>, nodata: Option<Self>) -> InMemoryRasterData;
+
+    #[doc(hidden)]
+    fn from_in_memory(data: InMemoryRasterData) -> Result<(Vec<Vec<Self>>, Option<Self>), InMemoryRasterData>;
+}
+
+macro_rules! impl_pixel {
+    ($t:ty, $variant:ident, $value:ident => $to_f64:expr, $v:ident => $from_f64:expr) => {
+        impl private::Sealed for $t { }
+
+        impl Pixel for $t {
+            fn pixtype(nodata: Option<Self>) -> PixType {
+                PixType::$variant(nodata)
+            }
+
+            fn to_f64(self) -> f64 {
+                let $value = self;
+                $to_f64
+            }
+
+            fn from_f64($v: f64) -> Self {
+                $from_f64
+            }
+
+            fn into_in_memory(data: Vec<Vec<Self>>, nodata: Option<Self>) -> InMemoryRasterData {
+                InMemoryRasterData::$variant { data, nodata }
+            }
+
+            fn from_in_memory(data: InMemoryRasterData) -> Result<(Vec<Vec<Self>>, Option<Self>), InMemoryRasterData> {
+                match data {
+                    InMemoryRasterData::$variant { data, nodata } => Ok((data, nodata)),
+                    other => Err(other),
+                }
+            }
+        }
+    };
+}
+
+/// `Pixel` for the 2-bit and 4-bit wrappers, stored as `u8` in `InMemoryRasterData`
+macro_rules! impl_pixel_bits {
+    ($t:ident, $max:expr) => {
+        impl private::Sealed for $t { }
+
+        impl Pixel for $t {
+            fn pixtype(nodata: Option<Self>) -> PixType {
+                PixType::$t(nodata.map(|v| v.0))
+            }
+
+            fn to_f64(self) -> f64 {
+                self.0 as f64
+            }
+
+            fn from_f64(value: f64) -> Self {
+                $t((value as u8).min($max))
+            }
+
+            fn into_in_memory(data: Vec<Vec<Self>>, nodata: Option<Self>) -> InMemoryRasterData {
+                InMemoryRasterData::$t {
+                    data: data.into_iter().map(|row| row.into_iter().map(|v| v.0).collect()).collect(),
+                    nodata: nodata.map(|v| v.0),
+                }
+            }
+
+            fn from_in_memory(data: InMemoryRasterData) -> Result<(Vec<Vec<Self>>, Option<Self>), InMemoryRasterData> {
+                match data {
+                    InMemoryRasterData::$t { data, nodata } => Ok((
+                        data.into_iter().map(|row| row.into_iter().map($t).collect()).collect(),
+                        nodata.map($t),
+                    )),
+                    other => Err(other),
+                }
+            }
+        }
+    };
+}
+
+impl_pixel!(bool, Bool1Bit, v => v as u8 as f64, v => v != 0.0);
+impl_pixel_bits!(UInt2, 3);
+impl_pixel_bits!(UInt4, 15);
+impl_pixel!(i8, Int8, v => v as f64, v => v as i8);
+impl_pixel!(u8, UInt8, v => v as f64, v => v as u8);
+impl_pixel!(i16, Int16, v => v as f64, v => v as i16);
+impl_pixel!(u16, UInt16, v => v as f64, v => v as u16);
+impl_pixel!(i32, Int32, v => v as f64, v => v as i32);
+impl_pixel!(u32, UInt32, v => v as f64, v => v as u32);
+impl_pixel!(f32, Float32, v => v as f64, v => v as f32);
+impl_pixel!(f64, Float64, v => v, v => v);
+
+/// In-memory band with pixels of type `T`, the generic counterpart of `InMemoryRasterData`.
+///
+/// Conversions from and to `InMemoryRasterData` move the pixel rows (`UInt2` and `UInt4`
+/// values are wrapped and unwrapped), converting from a band of another type returns the
+/// band unchanged as error.
+///
+/// ```rust
+/// use std::convert::TryFrom;
+/// use wkb_raster::{InMemoryRasterData, Pixel, TypedBand};
+///
+/// // written once for all pixel types
+/// fn count_valid<T: Pixel>(band: &TypedBand<T>) -> usize {
+///     (0..band.height())
+///         .flat_map(|row| (0..band.width()).map(move |col| (col, row)))
+///         .filter(|&(col, row)| band.get_value(col, row).is_some())
+///         .count()
+/// }
+///
+/// let data = InMemoryRasterData::Int16 { data: vec![vec![1, -1], vec![3, 4]], nodata: Some(-1) };
+/// let band = TypedBand::<i16>::try_from(data.clone()).unwrap();
+/// assert_eq!(count_valid(&band), 3);
+/// assert_eq!(InMemoryRasterData::from(band), data);
+///
+/// let float = TypedBand::from_fn(2, 2, Some(f32::NAN), |col, row| if col == row { f32::NAN } else { 1.5 });
+/// assert_eq!(count_valid(&float), 2);
+///
+/// // a band of another type is returned unchanged
+/// assert_eq!(TypedBand::<u8>::try_from(data.clone()), Err(data));
+/// ```
+#[derive(Debug, Clone, PartialEq, PartialOrd)]
+pub struct TypedBand<T: Pixel> {
+    /// Pixel rows, `data[row][col]`
+    pub data: Vec<Vec<T>>,
+    pub nodata: Option<T>,
+}
+
+impl<T: Pixel> TypedBand<T> {
+
+    /// Creates a band of `width * height` pixels set to `value`
+    pub fn filled(width: usize, height: usize, nodata: Option<T>, value: T) -> Self {
+        TypedBand { data: vec![vec![value; width]; height], nodata }
+    }
+
+    /// Creates a band of `width * height` pixels, calling `f(col, row)` for each pixel
+    pub fn from_fn<F: FnMut(usize, usize) -> T>(width: usize, height: usize, nodata: Option<T>, mut f: F) -> Self {
+        TypedBand {
+            data: (0..height).map(|row| (0..width).map(|col| f(col, row)).collect()).collect(),
+            nodata,
+        }
+    }
+
+    /// Returns the pixel type of the band, including the nodata value
+    pub fn pixtype(&self) -> PixType {
+        T::pixtype(self.nodata)
+    }
+
+    /// Returns the number of pixel columns (0 if the band has no rows)
+    pub fn width(&self) -> usize {
+        self.data.first().map(|r| r.len()).unwrap_or(0)
+    }
+
+    /// Returns the number of pixel rows
+    pub fn height(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Returns the pixel value at `(col, row)`, regardless of whether the value is the
+    /// nodata value. Returns `None` if out of bounds.
+    pub fn get(&self, col: usize, row: usize) -> Option<T> {
+        self.data.get(row)?.get(col).copied()
+    }
+
+    /// Returns the pixel value at `(col, row)`, or `None` if the pixel is out of bounds or
+    /// equal to the nodata value of the band (NaN nodata values match NaN pixels)
+    pub fn get_value(&self, col: usize, row: usize) -> Option<T> {
+        let value = self.get(col, row)?;
+        match self.nodata {
+            Some(nodata) if nodata == value || (nodata.to_f64().is_nan() && value.to_f64().is_nan()) => None,
+            _ => Some(value),
+        }
+    }
+
+    /// Sets the pixel at `(col, row)`, returns `false` if out of bounds
+    pub fn set(&mut self, col: usize, row: usize, value: T) -> bool {
+        match self.data.get_mut(row).and_then(|r| r.get_mut(col)) {
+            Some(pixel) => {
+                *pixel = value;
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Returns a band of type `U` with `f` applied to every pixel and the nodata value
+    pub fn map<U: Pixel, F: FnMut(T) -> U>(&self, mut f: F) -> TypedBand<U> {
+        TypedBand {
+            data: self.data.iter().map(|row| row.iter().map(|v| f(*v)).collect()).collect(),
+            nodata: self.nodata.map(&mut f),
+        }
+    }
+}
+
+impl<T: Pixel> From<TypedBand<T>> for InMemoryRasterData {
+    fn from(band: TypedBand<T>) -> Self {
+        T::into_in_memory(band.data, band.nodata)
+    }
+}
+
+impl<T: Pixel> TryFrom<InMemoryRasterData> for TypedBand<T> {
+    type Error = InMemoryRasterData;
+
+    fn try_from(data: InMemoryRasterData) -> Result<Self, Self::Error> {
+        T::from_in_memory(data).map(|(data, nodata)| TypedBand { data, nodata })
+    }
+}
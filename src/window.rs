@@ -0,0 +1,129 @@
+//! Extraction of pixel windows from rasters
+
+use std::fmt;
+use crate::{Raster, RasterBand, RasterDataSource};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum WindowError {
+    /// The window is empty (zero width or height)
+    EmptyWindow,
+    /// The window is not completely inside of the raster
+    OutOfBounds { col_off: usize, row_off: usize, width: usize, height: usize },
+    /// The bounding box doesn't intersect the raster
+    NoIntersection,
+    /// Band at the given index is stored out-of-db, pixels can't be extracted
+    BandIsOffline(usize),
+    /// The geotransform of the raster can't be inverted
+    NonInvertibleGeoTransform,
+}
+
+impl fmt::Display for WindowError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WindowError::EmptyWindow => write!(f, "window is empty"),
+            WindowError::OutOfBounds { col_off, row_off, width, height } => write!(f, "window of {}x{} pixels at ({}, {}) is not inside of the raster", width, height, col_off, row_off),
+            WindowError::NoIntersection => write!(f, "bounding box doesn't intersect the raster"),
+            WindowError::BandIsOffline(band) => write!(f, "band {} is stored out-of-db and can't be extracted", band),
+            WindowError::NonInvertibleGeoTransform => write!(f, "geotransform of the raster can't be inverted"),
+        }
+    }
+}
+
+impl std::error::Error for WindowError { }
+
+impl Raster {
+
+    /// Returns a new raster containing the pixels in the given window of all bands,
+    /// with the upper-left corner (`ip_x` / `ip_y`) moved to the window origin
+    ///
+    /// ```rust
+    /// use wkb_raster::{Raster, RasterBand, RasterDataSource, InMemoryRasterData, Endian};
+    ///
+    /// let raster = Raster {
+    ///     endian: Endian::Big, version: 0,
+    ///     scale_x: 10.0, scale_y: -10.0, ip_x: 100.0, ip_y: 50.0, skew_x: 0.0, skew_y: 0.0,
+    ///     srid: 3857, width: 3, height: 3,
+    ///     bands: vec![RasterBand {
+    ///         is_nodata_value: false,
+    ///         data: RasterDataSource::InMemory(InMemoryRasterData::UInt8 {
+    ///             data: vec![vec![1, 2, 3], vec![4, 5, 6], vec![7, 8, 9]],
+    ///             nodata: None,
+    ///         }),
+    ///     }],
+    /// };
+    ///
+    /// let window = raster.window(1, 1, 2, 2).unwrap();
+    /// assert_eq!((window.ip_x, window.ip_y), (110.0, 40.0));
+    /// assert_eq!(window.bands[0].data, RasterDataSource::InMemory(InMemoryRasterData::UInt8 {
+    ///     data: vec![vec![5, 6], vec![8, 9]],
+    ///     nodata: None,
+    /// }));
+    ///
+    /// assert!(raster.window(2, 0, 2, 1).is_err());
+    /// assert!(raster.window(1, 0, usize::MAX, 1).is_err());
+    /// assert_eq!(raster.window_from_bbox(115.0, 25.0, 200.0, 39.0).unwrap(), window);
+    /// ```
+    pub fn window(&self, col_off: usize, row_off: usize, width: usize, height: usize) -> Result<Raster, WindowError> {
+
+        if width == 0 || height == 0 {
+            return Err(WindowError::EmptyWindow);
+        }
+
+        let fits = |off: usize, len: usize, size: u16| off.checked_add(len).map_or(false, |end| end <= size as usize);
+        if !fits(col_off, width, self.width) || !fits(row_off, height, self.height) {
+            return Err(WindowError::OutOfBounds { col_off, row_off, width, height });
+        }
+
+        let bands = self.bands.iter().enumerate().map(|(i, band)| {
+            match &band.data {
+                RasterDataSource::InMemory(data) => Ok(RasterBand {
+                    is_nodata_value: band.is_nodata_value,
+                    data: RasterDataSource::InMemory(data
+                        .subset(col_off, row_off, width, height)
+                        .ok_or(WindowError::OutOfBounds { col_off, row_off, width, height })?),
+                }),
+                RasterDataSource::Offline(_) => Err(WindowError::BandIsOffline(i)),
+            }
+        }).collect::<Result<Vec<_>, _>>()?;
+
+        let (ip_x, ip_y) = self.pixel_to_world(col_off as f64, row_off as f64);
+
+        Ok(Raster {
+            ip_x,
+            ip_y,
+            width: width as u16,
+            height: height as u16,
+            bands,
+            .. self.clone_without_bands()
+        })
+    }
+
+    /// Returns the window of all pixels overlapping the bounding box (in world coordinates),
+    /// limited to the extent of the raster
+    pub fn window_from_bbox(&self, min_x: f64, min_y: f64, max_x: f64, max_y: f64) -> Result<Raster, WindowError> {
+        let (col_off, row_off, width, height) = self.bbox_to_window(min_x, min_y, max_x, max_y)?;
+        self.window(col_off, row_off, width, height)
+    }
+
+    /// Returns `(col_off, row_off, width, height)` of the pixels overlapping the
+    /// bounding box, limited to the extent of the raster
+    pub(crate) fn bbox_to_window(&self, min_x: f64, min_y: f64, max_x: f64, max_y: f64) -> Result<(usize, usize, usize, usize), WindowError> {
+
+        let corners = [(min_x, min_y), (max_x, min_y), (min_x, max_y), (max_x, max_y)]
+            .iter()
+            .map(|&(x, y)| self.world_to_pixel(x, y))
+            .collect::<Option<Vec<_>>>()
+            .ok_or(WindowError::NonInvertibleGeoTransform)?;
+
+        let min_col = corners.iter().map(|c| c.0).fold(f64::INFINITY, f64::min).floor().max(0.0);
+        let max_col = corners.iter().map(|c| c.0).fold(f64::NEG_INFINITY, f64::max).ceil().min(self.width as f64);
+        let min_row = corners.iter().map(|c| c.1).fold(f64::INFINITY, f64::min).floor().max(0.0);
+        let max_row = corners.iter().map(|c| c.1).fold(f64::NEG_INFINITY, f64::max).ceil().min(self.height as f64);
+
+        if !(min_col < max_col && min_row < max_row) {
+            return Err(WindowError::NoIntersection);
+        }
+
+        Ok((min_col as usize, min_row as usize, (max_col - min_col) as usize, (max_row - min_row) as usize))
+    }
+}
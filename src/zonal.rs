@@ -0,0 +1,353 @@
+//! Statistics of a band per zone, with zones given by a second band or by polygons
+
+use std::collections::HashMap;
+use std::fmt;
+use crate::{Raster, RasterDataSource, InMemoryRasterData};
+use crate::alignment::Misalignment;
+use crate::geometry::{Coord, Geometry, GeometryError};
+use crate::rasterize::{BurnRule, geometry_mask};
+
+#[derive(Debug, Clone, PartialEq, PartialOrd)]
+pub enum ZonalError {
+    /// The value band index does not exist in the raster
+    BandIndexOutOfRange(usize),
+    /// The zone band index does not exist in the zone raster
+    ZoneBandIndexOutOfRange(usize),
+    /// The value band is stored out-of-db
+    BandIsOffline,
+    /// The zone band is stored out-of-db
+    ZoneBandIsOffline,
+    /// The zone raster is not aligned with the value raster
+    NotAligned(Misalignment),
+    /// The WKB of the geometry at the given index could not be parsed
+    Geometry(usize, GeometryError),
+    /// The geometry at the given index doesn't contain any polygons
+    NotAPolygon(usize),
+    /// The geotransform of the raster can't be inverted
+    NonInvertibleGeoTransform,
+}
+
+impl fmt::Display for ZonalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ZonalError::BandIndexOutOfRange(band) => write!(f, "band {} does not exist in the raster", band),
+            ZonalError::ZoneBandIndexOutOfRange(band) => write!(f, "band {} does not exist in the zone raster", band),
+            ZonalError::BandIsOffline => write!(f, "value band is stored out-of-db"),
+            ZonalError::ZoneBandIsOffline => write!(f, "zone band is stored out-of-db"),
+            ZonalError::NotAligned(reason) => write!(f, "zone raster is not aligned with the value raster: {}", reason),
+            ZonalError::Geometry(index, e) => write!(f, "invalid geometry {}: {}", index, e),
+            ZonalError::NotAPolygon(index) => write!(f, "geometry {} doesn't contain any polygons", index),
+            ZonalError::NonInvertibleGeoTransform => write!(f, "geotransform of the raster can't be inverted"),
+        }
+    }
+}
+
+impl std::error::Error for ZonalError { }
+
+/// How much a pixel contributes to the statistics of a polygon
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum PixelWeight {
+    /// Pixels selected by the burn rule count fully, all other pixels are ignored
+    Full(BurnRule),
+    /// Pixels are weighted by the fraction of their area covered by the polygon
+    CoveredFraction,
+}
+
+/// Summary statistics of the valid pixels in a zone
+#[derive(Debug, Copy, Clone, PartialEq, PartialOrd)]
+pub struct ZonalStats {
+    /// Number of pixels (sum of the pixel weights with `PixelWeight::CoveredFraction`)
+    pub count: f64,
+    /// Sum of the (weighted) values
+    pub sum: f64,
+    /// Weighted mean of the values
+    pub mean: f64,
+    /// Smallest value
+    pub min: f64,
+    /// Largest value
+    pub max: f64,
+    /// Weighted population standard deviation of the values
+    pub stddev: f64,
+}
+
+/// Weighted running statistics (West's algorithm)
+#[derive(Debug, Copy, Clone)]
+struct StatsAccumulator {
+    weight: f64,
+    sum: f64,
+    mean: f64,
+    m2: f64,
+    min: f64,
+    max: f64,
+}
+
+impl StatsAccumulator {
+
+    const EMPTY: StatsAccumulator = StatsAccumulator {
+        weight: 0.0,
+        sum: 0.0,
+        mean: 0.0,
+        m2: 0.0,
+        min: f64::INFINITY,
+        max: f64::NEG_INFINITY,
+    };
+
+    fn add(&mut self, value: f64, weight: f64) {
+        if weight <= 0.0 {
+            return;
+        }
+        self.weight += weight;
+        self.sum += weight * value;
+        let delta = value - self.mean;
+        self.mean += delta * weight / self.weight;
+        self.m2 += weight * delta * (value - self.mean);
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+    }
+
+    fn result(&self) -> Option<ZonalStats> {
+        if self.weight <= 0.0 {
+            return None;
+        }
+        Some(ZonalStats {
+            count: self.weight,
+            sum: self.sum,
+            mean: self.mean,
+            min: self.min,
+            max: self.max,
+            stddev: (self.m2 / self.weight).max(0.0).sqrt(),
+        })
+    }
+}
+
+impl Raster {
+
+    /// Computes statistics of band `band` for every zone of band `zone_band` of `zones`,
+    /// returning `(zone value, statistics)` ordered by zone value. `zones` has to be
+    /// aligned with this raster, but may cover a different extent. Pixels that are nodata
+    /// in either band or not covered by `zones` are ignored.
+    ///
+    /// ```rust
+    /// use wkb_raster::{Raster, RasterBand, RasterDataSource, InMemoryRasterData, Endian};
+    ///
+    /// let raster = |data| Raster {
+    ///     endian: Endian::Big, version: 0,
+    ///     scale_x: 1.0, scale_y: -1.0, ip_x: 0.0, ip_y: 2.0, skew_x: 0.0, skew_y: 0.0,
+    ///     srid: 4326, width: 3, height: 2,
+    ///     bands: vec![RasterBand { is_nodata_value: false, data: RasterDataSource::InMemory(data) }],
+    /// };
+    ///
+    /// let population = raster(InMemoryRasterData::UInt16 {
+    ///     data: vec![vec![10, 20, 30], vec![40, 0, 60]],
+    ///     nodata: Some(0),
+    /// });
+    /// let districts = raster(InMemoryRasterData::UInt8 {
+    ///     data: vec![vec![1, 1, 2], vec![1, 2, 2]],
+    ///     nodata: None,
+    /// });
+    ///
+    /// let stats = population.zonal_stats(0, &districts, 0).unwrap();
+    /// assert_eq!(stats.len(), 2);
+    /// assert_eq!((stats[0].0, stats[0].1.count, stats[0].1.sum), (1.0, 3.0, 70.0));
+    /// assert_eq!((stats[1].0, stats[1].1.mean, stats[1].1.stddev), (2.0, 45.0, 15.0));
+    /// ```
+    pub fn zonal_stats(&self, band: usize, zones: &Raster, zone_band: usize) -> Result<Vec<(f64, ZonalStats)>, ZonalError> {
+
+        let data = self.zonal_band(band)?;
+
+        let zone_data = match &zones.bands.get(zone_band).ok_or(ZonalError::ZoneBandIndexOutOfRange(zone_band))?.data {
+            RasterDataSource::InMemory(data) => data,
+            RasterDataSource::Offline(_) => return Err(ZonalError::ZoneBandIsOffline),
+        };
+
+        self.alignment_with(zones).map_err(ZonalError::NotAligned)?;
+
+        // alignment was checked, so the offset is an integer
+        let (off_col, off_row) = zones.world_to_pixel(self.ip_x, self.ip_y).unwrap_or_default();
+        let (off_col, off_row) = (off_col.round() as i64, off_row.round() as i64);
+
+        let mut zone_index = HashMap::new();
+        let mut accumulators = Vec::new();
+
+        for row in 0..data.height() {
+            for col in 0..data.width() {
+
+                let (zone_col, zone_row) = (col as i64 + off_col, row as i64 + off_row);
+                if zone_col < 0 || zone_row < 0 {
+                    continue;
+                }
+
+                let zone = match zone_data.get_value(zone_col as usize, zone_row as usize) {
+                    Some(z) if !z.is_nan() => z + 0.0, // turns -0.0 into 0.0
+                    _ => continue,
+                };
+
+                if let Some(v) = data.get_value(col, row) {
+                    let i = *zone_index.entry(zone.to_bits()).or_insert_with(|| {
+                        accumulators.push((zone, StatsAccumulator::EMPTY));
+                        accumulators.len() - 1
+                    });
+                    accumulators[i].1.add(v, 1.0);
+                }
+            }
+        }
+
+        let mut stats = accumulators
+            .into_iter()
+            .filter_map(|(zone, acc)| Some((zone, acc.result()?)))
+            .collect::<Vec<_>>();
+        stats.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+        Ok(stats)
+    }
+
+    /// Computes statistics of band `band` for every polygon (binary WKB or EWKB, in the SRID
+    /// of the raster), in the order of `polygons`. Returns `None` for polygons that don't
+    /// cover any valid pixel. Nodata pixels are ignored.
+    ///
+    /// ```rust
+    /// use wkb_raster::{Raster, RasterBand, RasterDataSource, InMemoryRasterData, Endian, Geometry, BurnRule, PixelWeight};
+    ///
+    /// let raster = Raster {
+    ///     endian: Endian::Big, version: 0,
+    ///     scale_x: 1.0, scale_y: -1.0, ip_x: 0.0, ip_y: 2.0, skew_x: 0.0, skew_y: 0.0,
+    ///     srid: 4326, width: 2, height: 2,
+    ///     bands: vec![RasterBand {
+    ///         is_nodata_value: false,
+    ///         data: RasterDataSource::InMemory(InMemoryRasterData::UInt8 {
+    ///             data: vec![vec![10, 20], vec![30, 40]],
+    ///             nodata: None,
+    ///         }),
+    ///     }],
+    /// };
+    ///
+    /// // covers the left column and half of the right column
+    /// let district = Geometry::Polygon(vec![vec![(0.0, 0.0), (1.5, 0.0), (1.5, 2.0), (0.0, 2.0), (0.0, 0.0)]]);
+    /// let wkb = district.to_wkb(Endian::Little);
+    ///
+    /// let stats = raster.zonal_stats_by_polygons(0, &[&wkb], PixelWeight::Full(BurnRule::CenterIn)).unwrap();
+    /// assert_eq!((stats[0].unwrap().count, stats[0].unwrap().sum), (2.0, 40.0));
+    ///
+    /// let stats = raster.zonal_stats_by_polygons(0, &[&wkb], PixelWeight::CoveredFraction).unwrap();
+    /// assert_eq!((stats[0].unwrap().count, stats[0].unwrap().sum), (3.0, 70.0));
+    /// ```
+    pub fn zonal_stats_by_polygons(&self, band: usize, polygons: &[&[u8]], weight: PixelWeight) -> Result<Vec<Option<ZonalStats>>, ZonalError> {
+
+        let data = self.zonal_band(band)?;
+
+        polygons.iter().enumerate().map(|(i, wkb)| {
+
+            let geometry = Geometry::from_wkb(wkb).map_err(|e| ZonalError::Geometry(i, e))?;
+            if geometry.polygons().is_empty() {
+                return Err(ZonalError::NotAPolygon(i));
+            }
+
+            // only the polygons are used
+            let geometry = Geometry::MultiPolygon(geometry.polygons().iter().map(|p| p.to_vec()).collect());
+
+            let weights = match weight {
+                PixelWeight::Full(rule) => geometry_mask(self, &geometry, rule)
+                    .map(|mask| mask.into_iter().map(|m| if m { 1.0 } else { 0.0 }).collect()),
+                PixelWeight::CoveredFraction => covered_fractions(self, &geometry),
+            }.ok_or(ZonalError::NonInvertibleGeoTransform)?;
+
+            // the weights cover the grid of the raster, pixels of the band outside of it are ignored
+            let (width, height) = (self.width as usize, self.height as usize);
+            let mut acc = StatsAccumulator::EMPTY;
+            for row in 0..height {
+                for col in 0..width {
+                    let w = weights[row * width + col];
+                    if w > 0.0 {
+                        if let Some(v) = data.get_value(col, row) {
+                            acc.add(v, w);
+                        }
+                    }
+                }
+            }
+
+            Ok(acc.result())
+        }).collect()
+    }
+
+    fn zonal_band(&self, band: usize) -> Result<&InMemoryRasterData, ZonalError> {
+        match &self.bands.get(band).ok_or(ZonalError::BandIndexOutOfRange(band))?.data {
+            RasterDataSource::InMemory(data) => Ok(data),
+            RasterDataSource::Offline(_) => Err(ZonalError::BandIsOffline),
+        }
+    }
+}
+
+/// Returns the fraction of the area of every pixel (row-major) covered by the polygons of
+/// `geometry`, or `None` if the geotransform can't be inverted
+fn covered_fractions(grid: &Raster, geometry: &Geometry) -> Option<Vec<f64>> {
+
+    let width = grid.width as usize;
+    let height = grid.height as usize;
+
+    // pixels touched by the polygons, but not crossed by a ring, are completely covered
+    let touched = geometry_mask(grid, geometry, BurnRule::AllTouched)?;
+    let rings = Geometry::MultiLineString(geometry.polygons().iter().flat_map(|p| p.iter().cloned()).collect());
+    let crossed = geometry_mask(grid, &rings, BurnRule::AllTouched)?;
+
+    let mut fractions = touched.iter().map(|&t| if t { 1.0 } else { 0.0 }).collect::<Vec<_>>();
+
+    // rings in pixel space, holes are subtracted
+    let mut pixel_rings = Vec::new();
+    for polygon in geometry.polygons() {
+        for (i, ring) in polygon.iter().enumerate() {
+            let ring = ring.iter().map(|&(x, y)| grid.world_to_pixel(x, y)).collect::<Option<Vec<_>>>()?;
+            pixel_rings.push((if i == 0 { 1.0 } else { -1.0 }, ring));
+        }
+    }
+
+    for row in 0..height {
+
+        if !(0..width).any(|col| crossed[row * width + col]) {
+            continue;
+        }
+
+        let strips = pixel_rings.iter().map(|(sign, ring)| {
+            let strip = clip_ring(ring, |p| p.1, row as f64, true);
+            (*sign, clip_ring(&strip, |p| p.1, row as f64 + 1.0, false))
+        }).collect::<Vec<_>>();
+
+        for col in (0..width).filter(|&col| crossed[row * width + col]) {
+            let area = strips.iter().map(|(sign, strip)| {
+                let cell = clip_ring(strip, |p| p.0, col as f64, true);
+                let cell = clip_ring(&cell, |p| p.0, col as f64 + 1.0, false);
+                sign * ring_area(&cell).abs()
+            }).sum::<f64>();
+            fractions[row * width + col] = area.clamp(0.0, 1.0);
+        }
+    }
+
+    Some(fractions)
+}
+
+/// Sutherland-Hodgman clipping of a ring against an axis-parallel half-plane: keeps the part
+/// where `axis(p) >= bound` (or `<= bound` if `keep_greater` is false)
+fn clip_ring(ring: &[Coord], axis: impl Fn(Coord) -> f64, bound: f64, keep_greater: bool) -> Vec<Coord> {
+
+    let inside = |p: Coord| if keep_greater { axis(p) >= bound } else { axis(p) <= bound };
+    let mut clipped = Vec::with_capacity(ring.len());
+
+    for (i, &p) in ring.iter().enumerate() {
+        let q = ring[(i + 1) % ring.len()];
+        if inside(p) {
+            clipped.push(p);
+        }
+        if inside(p) != inside(q) {
+            let t = (bound - axis(p)) / (axis(q) - axis(p));
+            clipped.push((p.0 + t * (q.0 - p.0), p.1 + t * (q.1 - p.1)));
+        }
+    }
+
+    clipped
+}
+
+/// Signed area of a ring (closed or not) with the shoelace formula
+fn ring_area(ring: &[Coord]) -> f64 {
+    (0..ring.len()).map(|i| {
+        let (p, q) = (ring[i], ring[(i + 1) % ring.len()]);
+        p.0 * q.1 - q.0 * p.1
+    }).sum::<f64>() / 2.0
+}